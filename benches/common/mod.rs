@@ -0,0 +1,15 @@
+// Helpers shared by the benchmarks, which can not reach the crate's test
+// helpers.
+
+// random returns n pseudo-random numbers, using xorshift64.
+pub fn random(n: usize, seed: u64) -> Vec<u64> {
+    let mut x = seed;
+    (0..n)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        })
+        .collect()
+}
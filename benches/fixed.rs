@@ -0,0 +1,105 @@
+// Benchmarks of SurfU64 against a Surf over the big-endian bytes of the same
+// u64 keys.
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use rsurf::fixed::SurfU64;
+use rsurf::options::Options;
+use rsurf::surf::Surf;
+
+use common::random;
+
+const KEYS: usize = 100_000;
+
+fn bytes(keys: &[u64]) -> Vec<Vec<u8>> {
+    keys.iter().map(|k| k.to_be_bytes().to_vec()).collect()
+}
+
+fn bench_build(c: &mut Criterion) {
+    let keys = random(KEYS, 0x2545F4914F6CDD1D);
+
+    let mut group = c.benchmark_group("build");
+    group.sample_size(10);
+    group.bench_function("SurfU64", |b| {
+        b.iter(|| SurfU64::new(keys.iter().copied(), Options::new()).unwrap())
+    });
+    group.bench_function("Surf", |b| {
+        b.iter(|| Surf::new(bytes(&keys), Options::new()).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_queries(c: &mut Criterion) {
+    let keys = random(KEYS, 0x2545F4914F6CDD1D);
+    let misses = random(1_000, 0x9E3779B97F4A7C15);
+    let fixed = SurfU64::new(keys.iter().copied(), Options::new()).unwrap();
+    let surf = Surf::new(bytes(&keys), Options::new()).unwrap();
+
+    let mut group = c.benchmark_group("queries");
+    for (name, queries) in [("hit", &keys[..1_000]), ("miss", &misses[..])] {
+        group.bench_with_input(BenchmarkId::new("SurfU64/get", name), queries, |b, q| {
+            b.iter(|| {
+                q.iter()
+                    .filter(|k| fixed.get(black_box(**k)).unwrap())
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Surf/get", name), queries, |b, q| {
+            b.iter(|| {
+                q.iter()
+                    .filter(|k| surf.get(black_box(k.to_be_bytes().to_vec())).unwrap().0)
+                    .count()
+            })
+        });
+    }
+
+    // Ranges spanning about ten keys each.
+    let width = u64::MAX / KEYS as u64 * 10;
+    let ranges: Vec<(u64, u64)> = misses
+        .iter()
+        .map(|k| (*k, k.saturating_add(width)))
+        .collect();
+    group.bench_function("SurfU64/range", |b| {
+        b.iter(|| {
+            ranges
+                .iter()
+                .filter(|(l, h)| fixed.range(*l, *h).unwrap())
+                .count()
+        })
+    });
+    group.bench_function("Surf/range", |b| {
+        b.iter(|| {
+            ranges
+                .iter()
+                .filter(|(l, h)| {
+                    surf.range(l.to_be_bytes().to_vec(), h.to_be_bytes().to_vec())
+                        .unwrap()
+                })
+                .count()
+        })
+    });
+    group.bench_function("SurfU64/count", |b| {
+        b.iter(|| {
+            ranges
+                .iter()
+                .map(|(l, h)| fixed.count(*l, *h).unwrap())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("Surf/count", |b| {
+        b.iter(|| {
+            ranges
+                .iter()
+                .map(|(l, h)| {
+                    surf.count(l.to_be_bytes().to_vec(), h.to_be_bytes().to_vec())
+                        .unwrap()
+                })
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_build, bench_queries);
+criterion_main!(benches);
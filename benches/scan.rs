@@ -0,0 +1,80 @@
+// Benchmarks of counts over whole filters and of full scans, which step from
+// each stored key to the next, or back to the previous one. Random keys make
+// for sparse nodes, holding few of their 256 labels, while consecutive keys
+// fill their nodes.
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use rsurf::fixed::SurfU64;
+use rsurf::options::Options;
+use rsurf::surf::Surf;
+
+use common::random;
+
+const KEYS: usize = 100_000;
+
+// key_sets returns the sparse and the dense keys.
+fn key_sets() -> [(&'static str, Vec<u64>); 2] {
+    [
+        ("sparse", random(KEYS, 0x2545F4914F6CDD1D)),
+        ("dense", (0..KEYS as u64).collect()),
+    ]
+}
+
+fn bytes(keys: &[u64]) -> Vec<Vec<u8>> {
+    keys.iter().map(|k| k.to_be_bytes().to_vec()).collect()
+}
+
+fn bench_count(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count");
+    group.sample_size(10);
+    for (name, keys) in key_sets() {
+        let fixed = SurfU64::new(keys.iter().copied(), Options::new()).unwrap();
+        let surf = Surf::new(bytes(&keys), Options::new()).unwrap();
+
+        group.bench_function(BenchmarkId::new("SurfU64", name), |b| {
+            b.iter(|| fixed.count(0, u64::MAX).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("Surf", name), |b| {
+            b.iter(|| surf.count(vec![], vec![0xFF; 8]).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(10);
+    for (name, keys) in key_sets() {
+        let surf = Surf::new(bytes(&keys), Options::new()).unwrap();
+
+        group.bench_function(BenchmarkId::new("next_key", name), |b| {
+            b.iter(|| {
+                let (_, mut it) = surf.get_or_next(vec![]).unwrap();
+                let mut scanned = 1;
+                while it.next_key().is_ok() {
+                    scanned += 1;
+                }
+                scanned
+            })
+        });
+
+        // Scanning back from the last key.
+        let last = keys.iter().max().unwrap().to_be_bytes().to_vec();
+        group.bench_function(BenchmarkId::new("prev_key", name), |b| {
+            b.iter(|| {
+                let (_, mut it) = surf.get_or_next(last.clone()).unwrap();
+                let mut scanned = 0;
+                while it.prev_key().is_ok() {
+                    scanned += 1;
+                }
+                scanned
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_count, bench_scan);
+criterion_main!(benches);
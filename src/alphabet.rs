@@ -0,0 +1,364 @@
+// Alphabets remapping the bytes of keys to the edges of dense nodes.
+//
+// Every node of the trie holds one D-Labels and one D-HasChild bit per
+// possible edge. Keys drawn from a small alphabet, e.g. hex digits, base32 or
+// DNA bases, only ever use a few of the 256 possible bytes, in which case
+// nodes may hold one bit per byte of the alphabet instead. The bytes of the
+// alphabet are numbered in order, and the number of a byte is its symbol.
+// Mapping keys to symbols thus preserves their order, and range queries keep
+// working.
+//
+// A filter maps the keys it is built from to symbols, as well as the keys
+// and bounds of all queries. A key holding a byte outside the alphabet can
+// not be stored, so get answers false for it, and range bounds holding one
+// are replaced by the closest bounds which only hold symbols.
+use std::str::FromStr;
+
+use crate::iterator::Error;
+use crate::key::Key;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Alphabet {
+    // Bytes gives nodes one edge per byte.
+    #[default]
+    Bytes,
+    // Detect uses the bytes occurring in the keys the filter is built from,
+    // which the built filter then records as Symbols.
+    Detect,
+    // Symbols uses the given bytes. Building a filter from keys holding any
+    // other byte fails.
+    Symbols(Vec<u8>),
+}
+
+impl Alphabet {
+    // width returns the number of edges of each node, i.e. the number of
+    // bytes in the alphabet. Alphabets which are yet to be detected are
+    // counted as all bytes.
+    pub fn width(&self) -> usize {
+        SymbolMap::new(self).width()
+    }
+
+    // resolve returns the alphabet used for the given keys, which is Bytes
+    // or the sorted, distinct bytes making up all keys.
+    pub(crate) fn resolve(&self, keys: &[Key]) -> Result<Alphabet, Error> {
+        let mut present = [false; 256];
+        let mut symbols = match self {
+            Alphabet::Bytes => return Ok(Alphabet::Bytes),
+            Alphabet::Detect => {
+                for b in keys.iter().flatten() {
+                    present[*b as usize] = true;
+                }
+                (0..=255u8).filter(|b| present[*b as usize]).collect()
+            }
+            Alphabet::Symbols(symbols) => {
+                for b in symbols {
+                    present[*b as usize] = true;
+                }
+                if let Some(b) = keys.iter().flatten().find(|b| !present[**b as usize]) {
+                    return Err(Error::CustomError(format!(
+                        "Keys hold byte 0x{:02X}, which is not part of the alphabet",
+                        b
+                    )));
+                }
+                symbols.clone()
+            }
+        };
+
+        symbols.sort();
+        symbols.dedup();
+        match symbols.len() {
+            // Without any key bytes there is nothing to number, and a trie
+            // needs at least one edge per node.
+            0 if *self == Alphabet::Detect => Ok(Alphabet::Bytes),
+            0 => Err(Error::CustomError("Alphabet is empty".to_string())),
+            256 => Ok(Alphabet::Bytes),
+            _ => Ok(Alphabet::Symbols(symbols)),
+        }
+    }
+}
+
+// Alphabets are parsed from "bytes", "detect", or "symbols:" followed by the
+// bytes of the alphabet, e.g. "symbols:ACGT".
+impl FromStr for Alphabet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Alphabet, Error> {
+        match s {
+            "bytes" => Ok(Alphabet::Bytes),
+            "detect" => Ok(Alphabet::Detect),
+            _ => s
+                .strip_prefix("symbols:")
+                .map(|symbols| Alphabet::Symbols(symbols.as_bytes().to_vec()))
+                .ok_or_else(|| Error::CustomError(format!("Unknown alphabet {}", s))),
+        }
+    }
+}
+
+// SymbolMap maps bytes to the symbols of an alphabet and back.
+//
+// It holds the set of bytes of the alphabet as a 256 bit bitmap, so that the
+// symbol of a byte is the rank of its bit. This is the form in which
+// alphabets are serialized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SymbolMap {
+    words: [u64; 4],
+    width: usize,
+}
+
+impl SymbolMap {
+    pub(crate) fn new(alphabet: &Alphabet) -> SymbolMap {
+        let mut words = [0u64; 4];
+        match alphabet {
+            Alphabet::Bytes | Alphabet::Detect => words = [u64::MAX; 4],
+            Alphabet::Symbols(symbols) => {
+                for b in symbols {
+                    words[*b as usize / 64] |= 0x8000000000000000 >> (b % 64);
+                }
+            }
+        }
+        SymbolMap::from_words(words)
+    }
+
+    pub(crate) fn from_words(words: [u64; 4]) -> SymbolMap {
+        let width = words.iter().map(|w| w.count_ones() as usize).sum();
+        SymbolMap { words, width }
+    }
+
+    // words returns the bitmap of the bytes of the alphabet.
+    pub(crate) fn words(&self) -> [u64; 4] {
+        self.words
+    }
+
+    // alphabet returns the alphabet of the map.
+    pub(crate) fn alphabet(&self) -> Alphabet {
+        match self.width {
+            256 => Alphabet::Bytes,
+            _ => Alphabet::Symbols((0..=255u8).filter(|b| self.symbol(*b).is_ok()).collect()),
+        }
+    }
+
+    // width returns the number of symbols, i.e. the number of edges of each
+    // node.
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    // symbol returns the symbol of the given byte, or, if the byte is not
+    // part of the alphabet, the number of symbols of lesser bytes.
+    pub(crate) fn symbol(&self, b: u8) -> Result<u8, usize> {
+        let (w, offset) = (b as usize / 64, b % 64);
+        let lesser = self.words[..w]
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum::<usize>()
+            + (self.words[w] & !(u64::MAX >> offset)).count_ones() as usize;
+
+        match self.words[w] & (0x8000000000000000 >> offset) {
+            0 => Err(lesser),
+            _ => Ok(lesser as u8),
+        }
+    }
+
+    // byte returns the byte of the given symbol.
+    pub(crate) fn byte(&self, symbol: u8) -> u8 {
+        let mut rest = symbol as u32;
+        for (w, word) in self.words.iter().enumerate() {
+            let ones = word.count_ones();
+            if rest < ones {
+                // Clear the leading ones of the word until the wanted one
+                // leads.
+                let mut word = *word;
+                for _ in 0..rest {
+                    word &= !(0x8000000000000000 >> word.leading_zeros());
+                }
+                return (64 * w) as u8 + word.leading_zeros() as u8;
+            }
+            rest -= ones;
+        }
+        unreachable!("symbol {} is not part of the alphabet", symbol)
+    }
+
+    // encode maps the given key to symbols, or returns None if it holds a
+    // byte outside the alphabet.
+    pub(crate) fn encode(&self, key: Vec<u8>) -> Option<Vec<u8>> {
+        if self.width == 256 {
+            return Some(key);
+        }
+
+        let mut symbols = Vec::with_capacity(key.len());
+        self.encode_into(&key, &mut symbols).then_some(symbols)
+    }
+
+    // encode_into is encode, which appends the symbols to the given ones, and
+    // returns whether all bytes are part of the alphabet.
+    pub(crate) fn encode_into(&self, key: &[u8], symbols: &mut impl Extend<u8>) -> bool {
+        if self.width == 256 {
+            symbols.extend(key.iter().copied());
+            return true;
+        }
+
+        for b in key {
+            match self.symbol(*b) {
+                Ok(symbol) => symbols.extend([symbol]),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    // encode_low maps the given lower bound to symbols, such that a key of
+    // the alphabet is at least the bound if and only if its symbols are at
+    // least the mapped bound.
+    //
+    // A byte outside the alphabet is replaced by the symbol of the next
+    // greater byte, ending the bound. If there is none, it is replaced by the
+    // width of the alphabet, which no edge has, so that the bound lies past
+    // all keys sharing the preceding prefix.
+    pub(crate) fn encode_low(&self, key: Vec<u8>) -> Vec<u8> {
+        if self.width == 256 {
+            return key;
+        }
+
+        let mut symbols = Vec::with_capacity(key.len());
+        self.encode_low_into(&key, &mut symbols);
+        symbols
+    }
+
+    // encode_low_into is encode_low, which appends the symbols to the given
+    // ones.
+    pub(crate) fn encode_low_into(&self, key: &[u8], symbols: &mut impl Extend<u8>) {
+        if self.width == 256 {
+            symbols.extend(key.iter().copied());
+            return;
+        }
+
+        for b in key {
+            match self.symbol(*b) {
+                Ok(symbol) => symbols.extend([symbol]),
+                Err(lesser) => {
+                    symbols.extend([lesser as u8]);
+                    break;
+                }
+            }
+        }
+    }
+
+    // encode_high maps the given upper bound to symbols, such that a key of
+    // the alphabet is at most the bound if and only if its symbols are at
+    // most the mapped bound.
+    //
+    // A byte outside the alphabet is replaced by the symbol of the next
+    // lesser byte, followed by the width of the alphabet, which lies past
+    // all keys extending it. If there is none, the bound ends before it.
+    pub(crate) fn encode_high(&self, key: Vec<u8>) -> Vec<u8> {
+        if self.width == 256 {
+            return key;
+        }
+
+        let mut symbols = Vec::with_capacity(key.len() + 1);
+        self.encode_high_into(&key, &mut symbols);
+        symbols
+    }
+
+    // encode_high_into is encode_high, which appends the symbols to the
+    // given ones, one more than the bytes of the bound at most.
+    pub(crate) fn encode_high_into(&self, key: &[u8], symbols: &mut impl Extend<u8>) {
+        if self.width == 256 {
+            symbols.extend(key.iter().copied());
+            return;
+        }
+
+        for b in key {
+            match self.symbol(*b) {
+                Ok(symbol) => symbols.extend([symbol]),
+                Err(0) => break,
+                Err(lesser) => {
+                    symbols.extend([lesser as u8 - 1, self.width as u8]);
+                    break;
+                }
+            }
+        }
+    }
+
+    // decode maps the given key of symbols back to bytes.
+    pub(crate) fn decode(&self, key: Vec<u8>) -> Vec<u8> {
+        if self.width == 256 {
+            return key;
+        }
+        key.into_iter().map(|s| self.byte(s)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dna() -> SymbolMap {
+        SymbolMap::new(&Alphabet::Symbols(b"ACGT".to_vec()))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let keys = vec![b"GATTACA".to_vec(), b"CAT".to_vec()];
+
+        assert_eq!(
+            Alphabet::Detect.resolve(&keys),
+            Ok(Alphabet::Symbols(b"ACGT".to_vec()))
+        );
+        assert_eq!(
+            Alphabet::Symbols(b"TGCAA".to_vec()).resolve(&keys),
+            Ok(Alphabet::Symbols(b"ACGT".to_vec()))
+        );
+        assert!(Alphabet::Symbols(b"ACT".to_vec()).resolve(&keys).is_err());
+        assert!(Alphabet::Symbols(vec![]).resolve(&[]).is_err());
+        assert_eq!(Alphabet::Detect.resolve(&[vec![]]), Ok(Alphabet::Bytes));
+        assert_eq!(
+            Alphabet::Symbols((0..=255).collect()).resolve(&keys),
+            Ok(Alphabet::Bytes)
+        );
+
+        assert_eq!("detect".parse(), Ok(Alphabet::Detect));
+        assert_eq!(
+            "symbols:ACGT".parse(),
+            Ok(Alphabet::Symbols(b"ACGT".to_vec()))
+        );
+        assert!("dna".parse::<Alphabet>().is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let map = dna();
+        assert_eq!(map.width(), 4);
+        assert_eq!(map.alphabet(), Alphabet::Symbols(b"ACGT".to_vec()));
+        assert_eq!(SymbolMap::from_words(map.words()), map);
+
+        assert_eq!(
+            map.encode(b"GATTACA".to_vec()),
+            Some(vec![2, 0, 3, 3, 0, 1, 0])
+        );
+        assert_eq!(map.decode(vec![2, 0, 3, 3, 0, 1, 0]), b"GATTACA");
+        assert_eq!(map.encode(b"GAUTACA".to_vec()), None);
+
+        // B lies between A and C, Z past T, and 0 before A.
+        assert_eq!(map.encode_low(b"GB".to_vec()), vec![2, 1]);
+        assert_eq!(map.encode_low(b"GZA".to_vec()), vec![2, 4]);
+        assert_eq!(map.encode_high(b"GBT".to_vec()), vec![2, 0, 4]);
+        assert_eq!(map.encode_high(b"G0".to_vec()), vec![2]);
+
+        let bytes = SymbolMap::new(&Alphabet::Bytes);
+        assert_eq!(bytes.width(), 256);
+        assert_eq!(
+            bytes.encode(b"\x00\xFF".to_vec()),
+            Some(b"\x00\xFF".to_vec())
+        );
+        assert_eq!(bytes.alphabet(), Alphabet::Bytes);
+
+        let spread = SymbolMap::new(&Alphabet::Symbols(vec![0, 63, 64, 200, 255]));
+        for (symbol, b) in [0u8, 63, 64, 200, 255].into_iter().enumerate() {
+            assert_eq!(spread.symbol(b), Ok(symbol as u8));
+            assert_eq!(spread.byte(symbol as u8), b);
+        }
+        assert_eq!(spread.symbol(65), Err(3));
+    }
+}
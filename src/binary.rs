@@ -0,0 +1,295 @@
+// Filters over the bits of fixed-width integer keys.
+//
+// Surf and FixedSurf branch on bytes, so keys are truncated at the first byte
+// which tells them apart from their neighbours. Keys drawn from a narrow
+// region, e.g. timestamps, then share their high bytes and are told apart by
+// the last few bits of a byte, and the rest of that byte is stored for
+// nothing. BinarySurf builds its trie over bits instead, truncating keys at
+// the first distinguishing bit. The bits saved go into real suffixes, which
+// lowers the false-positive rate of range queries at the same size.
+//
+// A key is stored as the sequence of its bits, most significant first, each
+// bit being a symbol of the alphabet {0, 1}. The trie is thus a trie of
+// Surf, whose nodes have two edges, and which takes one real suffix bit per
+// bit of a key.
+use std::io::Read;
+use std::marker::PhantomData;
+
+use crate::alphabet::Alphabet;
+use crate::fixed::FixedKey;
+use crate::iterator::Error;
+use crate::key::{truncate, Key};
+use crate::options::Options;
+use crate::stats::MemoryUsage;
+use crate::surf::Surf;
+use crate::transform::KeyTransform;
+
+// BinarySurf is a filter over fixed-width integer keys, whose trie branches
+// on single bits.
+pub struct BinarySurf<K: FixedKey> {
+    surf: Surf,
+    keys: PhantomData<K>,
+}
+
+impl<K: FixedKey> BinarySurf<K> {
+    // new builds a filter from the given keys. Options::transform must be the
+    // identity, as transformed keys would no longer be of a fixed width, and
+    // Options::alphabet Bytes, which is replaced by the alphabet of bits.
+    pub fn new(keys: impl IntoIterator<Item = K>, mut options: Options) -> Result<Self, Error> {
+        if options.transform != KeyTransform::Identity {
+            return Err(Error::CustomError(
+                "Fixed-width keys can not be transformed".to_string(),
+            ));
+        }
+        if options.alphabet != Alphabet::Bytes {
+            return Err(Error::CustomError(
+                "Bit-level tries have two edges per node, not an alphabet".to_string(),
+            ));
+        }
+        options.alphabet = bits_alphabet();
+
+        let mut keys: Vec<K> = keys.into_iter().collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let keys: Vec<Key> = keys.into_iter().map(bits).collect();
+        let truncated = truncate(&keys);
+
+        Ok(BinarySurf {
+            surf: Surf::build(&keys, &truncated, options)?,
+            keys: PhantomData,
+        })
+    }
+
+    // from_inner wraps the given filter over the bits of keys, e.g. one
+    // loaded from a file, failing unless it was built by new.
+    pub fn from_inner(surf: Surf) -> Result<Self, Error> {
+        if surf.options().transform != KeyTransform::Identity
+            || surf.options().alphabet != bits_alphabet()
+        {
+            return Err(Error::CustomError(
+                "Filter is not a trie over bits".to_string(),
+            ));
+        }
+        surf.trie().check_key_width(8 * K::WIDTH)?;
+
+        Ok(BinarySurf {
+            surf,
+            keys: PhantomData,
+        })
+    }
+
+    // read_from loads a filter serialized by Surf::write_to of its inner
+    // filter, like from_inner.
+    pub fn read_from(r: impl Read) -> Result<Self, Error> {
+        BinarySurf::from_inner(Surf::read_from(r)?)
+    }
+
+    // inner returns the underlying filter over the bits of the keys, e.g. to
+    // serialize, verify or explain it.
+    pub fn inner(&self) -> &Surf {
+        &self.surf
+    }
+
+    // options returns the options the filter was built with, holding the
+    // alphabet of bits.
+    pub fn options(&self) -> &Options {
+        self.surf.options()
+    }
+
+    // height returns the number of levels of the trie, i.e. the length in
+    // bits of the longest truncated key.
+    pub fn height(&self) -> usize {
+        self.surf.height()
+    }
+
+    // memory returns the number of bytes used by each component of the
+    // filter.
+    pub fn memory(&self) -> MemoryUsage {
+        self.surf.stats().memory
+    }
+
+    pub fn get(&self, key: K) -> Result<bool, Error> {
+        let (exists, _, _) = self.surf.get(bits(key))?;
+        Ok(exists)
+    }
+
+    // range returns whether a key may lie within [low, high].
+    pub fn range(&self, low: K, high: K) -> Result<bool, Error> {
+        self.surf.range(bits(low), bits(high))
+    }
+
+    // count returns the number of stored truncated keys within [low, high].
+    pub fn count(&self, low: K, high: K) -> Result<usize, Error> {
+        self.surf.count(bits(low), bits(high))
+    }
+}
+
+// bits_alphabet returns the alphabet of the bits of keys.
+fn bits_alphabet() -> Alphabet {
+    Alphabet::Symbols(vec![0, 1])
+}
+
+// bits returns the bits of the given key, most significant first, one per
+// byte.
+fn bits<K: FixedKey>(key: K) -> Key {
+    let v = u64::from_be_bytes(key.bytes());
+    (0..8 * K::WIDTH)
+        .map(|i| (v >> (63 - i)) as u8 & 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed::SurfU64;
+    use crate::testing::random;
+
+    #[test]
+    fn test_queries() {
+        let keys = [0x10u8, 0x11, 0x18, 0x80];
+        let options = Options {
+            hash_bits: 0,
+            real_bits: 0,
+            ..Options::new()
+        };
+        let surf = BinarySurf::new(keys, options).unwrap();
+
+        // 0x10 and 0x11 share 7 bits, 0x18 is told apart from them by its
+        // fifth bit, and 0x80 by its first.
+        assert_eq!(surf.height(), 8);
+        assert_eq!(surf.inner().levels, vec![1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(surf.inner().verify(), Ok(()));
+
+        for k in keys {
+            assert!(surf.get(k).unwrap(), "{:#x}", k);
+        }
+        assert!(!surf.get(0x12).unwrap());
+        assert!(surf.get(0xFF).unwrap());
+        assert_eq!(surf.range(0x12, 0x17), Ok(false));
+        assert_eq!(surf.range(0x12, 0x18), Ok(true));
+        assert_eq!(surf.range(0x19, 0x7F), Ok(true));
+        assert_eq!(surf.range(0x20, 0x7F), Ok(false));
+        assert_eq!(surf.count(0x00, 0xFF), Ok(4));
+        assert_eq!(surf.count(0x11, 0x18), Ok(2));
+
+        let empty = BinarySurf::<u32>::new([], Options::new()).unwrap();
+        assert_eq!(empty.get(1), Ok(false));
+        assert_eq!(empty.range(0, u32::MAX), Ok(false));
+        assert_eq!(empty.count(0, u32::MAX), Ok(0));
+    }
+
+    #[test]
+    fn test_read_from() {
+        let keys = random(1_000, 0x2545F4914F6CDD1D);
+        let options = Options {
+            hash_bits: 3,
+            real_bits: 5,
+            path_compression: true,
+            root_table_bytes: 1,
+            ..Options::new()
+        };
+        let surf = BinarySurf::new(keys.iter().copied(), options).unwrap();
+        assert_eq!(surf.inner().verify(), Ok(()));
+
+        let mut buf = Vec::new();
+        surf.inner().write_to(&mut buf).unwrap();
+        let loaded = BinarySurf::<u64>::read_from(buf.as_slice()).unwrap();
+        for k in &keys {
+            assert!(loaded.get(*k).unwrap(), "{:#x}", k);
+        }
+        assert_eq!(loaded.count(0, u64::MAX), Ok(keys.len()));
+
+        let explanation = loaded.inner().explain_get(bits(keys[0])).unwrap();
+        assert!(explanation.answer);
+
+        // Keys of 64 bits are truncated to more than 16 bits, and filters
+        // over bytes are not tries over bits.
+        assert!(BinarySurf::<u16>::read_from(buf.as_slice()).is_err());
+        let bytes = Surf::new(vec![vec![1], vec![2]], Options::new()).unwrap();
+        assert!(BinarySurf::<u8>::from_inner(bytes).is_err());
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        // Keys are spread over a narrow region, with plenty of gaps.
+        let keys: Vec<u64> = random(2_000, 0x2545F4914F6CDD1D)
+            .iter()
+            .map(|v| 0x0123_4567_0000_0000 | v & 0xFFFF_FF00)
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+
+        for (hash_bits, real_bits) in [(0, 0), (4, 0), (0, 4), (3, 13), (0, 64)] {
+            let options = Options {
+                hash_bits,
+                real_bits,
+                ..Options::new()
+            };
+            let surf = BinarySurf::new(keys.iter().copied(), options).unwrap();
+
+            for k in &keys {
+                assert!(surf.get(*k).unwrap(), "{:#x}", k);
+            }
+            for pair in random(2_000, 0x9E3779B97F4A7C15).chunks(2) {
+                let low = 0x0123_4567_0000_0000 | pair[0] & 0xFFFF_FFFF;
+                let high = low.saturating_add(pair[1] & 0xFFFF);
+
+                let first = sorted.partition_point(|k| *k < low);
+                let last = sorted.partition_point(|k| *k <= high);
+                if first < last {
+                    assert_eq!(surf.range(low, high), Ok(true), "{:#x}..={:#x}", low, high);
+                }
+                assert!(surf.count(low, high).unwrap() >= last - first);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tighter_at_same_size() {
+        // Keys sharing their five high bytes, where byte-level truncation
+        // spends most of its space on 256-bit nodes with few edges.
+        let keys: Vec<u64> = random(5_000, 0x2545F4914F6CDD1D)
+            .iter()
+            .map(|v| 0xAB_CDEF_0100_0000 | v & 0x00FF_FFFF)
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        // The binary trie is small enough to afford more real bits, and
+        // still fit in the space of the byte-level one.
+        let options = |real_bits| Options {
+            hash_bits: 0,
+            real_bits,
+            ..Options::new()
+        };
+        let binary = BinarySurf::new(keys.iter().copied(), options(12)).unwrap();
+        let bytes = SurfU64::new(keys.iter().copied(), options(4)).unwrap();
+        assert!(
+            binary.memory().total() <= bytes.inner().stats().memory.total(),
+            "{:?}",
+            binary.memory()
+        );
+
+        let (mut binary_fp, mut bytes_fp) = (0, 0);
+        for v in random(20_000, 0x9E3779B97F4A7C15) {
+            let low = 0xAB_CDEF_0100_0000 | v & 0x00FF_FFFF;
+            let high = low + 16;
+            let first = sorted.partition_point(|k| *k < low);
+            if first < sorted.len() && sorted[first] <= high {
+                continue;
+            }
+
+            binary_fp += binary.range(low, high).unwrap() as usize;
+            bytes_fp += bytes.range(low, high).unwrap() as usize;
+        }
+
+        assert!(
+            2 * binary_fp < bytes_fp,
+            "{} binary, {} byte false positives",
+            binary_fp,
+            bytes_fp
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use crate::bitops::{leading_ones_mask, ones_mask, single_one_mask};
+use crate::rank::rank1;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Bitmap {
@@ -11,8 +12,8 @@ pub struct Bitmap {
 
 impl Bitmap {
     pub fn new(size: usize, capacity: usize) -> Self {
-        let data_size = (size + 63) / 64;
-        let data_capacity = (capacity + 63) / 64;
+        let data_size = size.div_ceil(64);
+        let data_capacity = capacity.div_ceil(64);
 
         let mut data = Vec::with_capacity(data_capacity);
         data.resize(data_size, 0);
@@ -24,6 +25,18 @@ impl Bitmap {
         }
     }
 
+    // from_words creates a bitmap backed by the given words. Its capacity is
+    // fixed to the bits held by these words.
+    pub(crate) fn from_words(data: Vec<u64>) -> Self {
+        let length = data.len() * 64;
+
+        Bitmap {
+            capacity: length,
+            length,
+            data,
+        }
+    }
+
     pub fn set(&mut self, bit: usize) -> Result<(), &'static str> {
         if bit >= self.capacity {
             return Err("Invalid index. Must be in range [0, capacity - 1]");
@@ -118,7 +131,77 @@ impl Bitmap {
     }
 
     pub fn rank(&mut self, val: u8, idx: usize) -> Result<usize, &'static str> {
-        if idx >= self.length {
+        self.frozen(&[]).rank(val, idx)
+    }
+
+    // frozen returns a read-only view of the bitmap, using the given rank
+    // directory to answer rank queries. The directory may be empty.
+    pub fn frozen<'a>(&'a self, ranks: &'a [u64]) -> BitmapRef<'a> {
+        BitmapRef::new(&self.data, ranks)
+    }
+
+    fn resize(&mut self, bit: usize) {
+        if bit < self.length {
+            return;
+        }
+
+        let bit = bit.min(self.capacity);
+        let new_length = bit.div_ceil(64);
+        let _additional_uints = new_length - self.data.len();
+
+        self.data.resize(new_length, 0);
+        self.length = new_length * 64;
+    }
+}
+
+// BitmapRef is a read-only view of a frozen bitmap, backed by borrowed words.
+//
+// It optionally carries a rank directory, as built by rank::directory, in
+// which case rank queries take constant time.
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapRef<'a> {
+    data: &'a [u64],
+    ranks: &'a [u64],
+}
+
+impl<'a> BitmapRef<'a> {
+    pub fn new(data: &'a [u64], ranks: &'a [u64]) -> Self {
+        BitmapRef { data, ranks }
+    }
+
+    // len returns the number of bits in the bitmap.
+    pub fn len(&self) -> usize {
+        self.data.len() * 64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // words returns the words backing the bitmap.
+    pub fn words(&self) -> &'a [u64] {
+        self.data
+    }
+
+    // ranks returns the rank directory of the bitmap, which may be empty.
+    pub fn ranks(&self) -> &'a [u64] {
+        self.ranks
+    }
+
+    pub fn get(&self, bit: usize) -> Result<u8, &'static str> {
+        if bit >= self.len() {
+            return Err("Invalid index. Must be in range [0, length - 1]");
+        }
+
+        let offset = bit % 64;
+        let mask = single_one_mask(offset as u32);
+
+        let val = (self.data[bit / 64] & mask) >> (64 - offset - 1);
+        Ok(val as u8)
+    }
+
+    pub fn rank(&self, val: u8, idx: usize) -> Result<usize, &'static str> {
+        if idx >= self.len() {
             return Err("Index must be in range [0, length - 1]");
         }
 
@@ -127,6 +210,12 @@ impl Bitmap {
         }
 
         let check_ones = val == 1;
+
+        if !self.ranks.is_empty() {
+            let ones = rank1(self.data, self.ranks, idx);
+            return Ok(if check_ones { ones } else { idx + 1 - ones });
+        }
+
         let mut count = 0;
 
         for i in (0..=idx).step_by(64) {
@@ -150,28 +239,14 @@ impl Bitmap {
 
         Ok(count)
     }
-
-    fn resize(&mut self, bit: usize) {
-        if bit < self.length {
-            return;
-        }
-
-        let bit = bit.min(self.capacity);
-        let new_length = (bit + 63) / 64;
-        let _additional_uints = new_length - self.data.len();
-
-        self.data.resize(new_length, 0);
-        self.length = new_length * 64;
-    }
 }
 
 impl fmt::Display for Bitmap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let max_offset_length = (self.length as f64).log10().ceil() as usize;
-        let pattern = format!("%0{max_offset_length}d |");
 
         for i in (0..self.data.len() * 64).step_by(64) {
-            write!(f, "{}{}", pattern, i)?;
+            write!(f, "{:0width$} |", i, width = max_offset_length)?;
 
             let bits_string = format!("{:064b}", self.data[i / 64]);
 
@@ -222,4 +297,17 @@ mod tests {
             assert_eq!(bitmap.get(key).unwrap(), 0, "bitmap.get({}) == 0", key);
         }
     }
+
+    #[test]
+    fn test_display() {
+        let mut bitmap = Bitmap::new(128, 128);
+        bitmap.set(0).unwrap();
+        bitmap.set(71).unwrap();
+
+        assert_eq!(
+            bitmap.to_string(),
+            "000 | 10000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000\n\
+             064 | 00000001 00000000 00000000 00000000 00000000 00000000 00000000 00000000\n"
+        );
+    }
 }
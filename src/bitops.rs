@@ -39,6 +39,35 @@ pub fn single_one_mask(idx: u32) -> u64 {
     0x8000000000000000 >> idx
 }
 
+// range_masks returns the words overlapping the bits [start, end) of a
+// bitmap, each along with the mask of the bits of the range it holds.
+pub fn range_masks(start: usize, end: usize) -> impl DoubleEndedIterator<Item = (usize, u64)> {
+    (start / 64..end.div_ceil(64)).map(move |w| {
+        let from = start.max(64 * w) - 64 * w;
+        let to = end.min(64 * w + 64) - 64 * w;
+        let mask = leading_ones_mask(to as u32) & !leading_ones_mask(from as u32);
+        (w, mask)
+    })
+}
+
+// next_one returns the first set bit of a bitmap within the bits
+// [start, end), scanning a word at a time.
+pub fn next_one(words: &[u64], start: usize, end: usize) -> Option<usize> {
+    range_masks(start, end).find_map(|(w, mask)| {
+        let bits = words.get(w)? & mask;
+        (bits != 0).then(|| 64 * w + bits.leading_zeros() as usize)
+    })
+}
+
+// prev_one returns the last set bit of a bitmap within the bits [start, end),
+// scanning a word at a time.
+pub fn prev_one(words: &[u64], start: usize, end: usize) -> Option<usize> {
+    range_masks(start, end).rev().find_map(|(w, mask)| {
+        let bits = words.get(w)? & mask;
+        (bits != 0).then(|| 64 * w + 63 - bits.trailing_zeros() as usize)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,4 +149,35 @@ mod tests {
         assert_eq!(trailing_ones_mask(64), 0xFFFFFFFFFFFFFFFF);
         assert_eq!(trailing_ones_mask(70), 0xFFFFFFFFFFFFFFFF);
     }
+
+    #[test]
+    fn test_range_masks() {
+        let masks: Vec<(usize, u64)> = range_masks(60, 130).collect();
+        assert_eq!(
+            masks,
+            vec![(0, 0xF), (1, u64::MAX), (2, 0xC000000000000000)]
+        );
+        assert_eq!(
+            range_masks(8, 12).collect::<Vec<_>>(),
+            vec![(0, 0x00F0000000000000)]
+        );
+        assert_eq!(range_masks(64, 64).count(), 0);
+    }
+
+    #[test]
+    fn test_next_prev_one() {
+        // Bits 3, 64 and 130 are set.
+        let words = [0x1000000000000000, 0x8000000000000000, 0x2000000000000000];
+        assert_eq!(next_one(&words, 0, 192), Some(3));
+        assert_eq!(next_one(&words, 4, 192), Some(64));
+        assert_eq!(next_one(&words, 65, 192), Some(130));
+        assert_eq!(next_one(&words, 65, 130), None);
+        assert_eq!(next_one(&words, 131, 256), None);
+
+        assert_eq!(prev_one(&words, 0, 192), Some(130));
+        assert_eq!(prev_one(&words, 0, 130), Some(64));
+        assert_eq!(prev_one(&words, 0, 64), Some(3));
+        assert_eq!(prev_one(&words, 4, 64), None);
+        assert_eq!(prev_one(&words, 8, 8), None);
+    }
 }
@@ -0,0 +1,109 @@
+// Chains of unary nodes, which path compression removes from the trie.
+//
+// Keys sharing long prefixes, e.g. URLs or "tenant/00000/..." paths, lead to
+// long runs of nodes which have a single edge leading to a child, and on
+// which no key ends. With path compression, such runs are left out of the
+// trie, and the labels of their edges are instead stored as the chain of the
+// node the run leads to. A key reaching a node must continue with the node's
+// chain before the node's edges apply, and the number of levels of the trie
+// thus only depends on the number of points at which keys branch.
+//
+// Chains are stored in a side structure, consisting of
+//
+//   - D-HasChain, a bitmap with one bit per node, set for nodes which have a
+//     chain,
+//   - the end offsets of all chains within the chain labels, one per set bit
+//     of D-HasChain, in level order, and
+//   - the chain labels themselves, packed eight to a word, starting with the
+//     least significant byte.
+//
+// Filters built without path compression have an empty D-HasChain, and no
+// chains at all.
+use std::ops::Range;
+
+use crate::bitmap::BitmapRef;
+use crate::iterator::Error;
+use crate::memory::bitmap_bytes;
+use crate::rank;
+
+// Chains is a read-only view of the chains of a trie.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chains<'a> {
+    pub(crate) has_chain: BitmapRef<'a>,
+    pub(crate) ends: &'a [u64],
+    pub(crate) labels: &'a [u64],
+}
+
+impl<'a> Chains<'a> {
+    // get returns the range of the chain labels holding the chain of the
+    // given node, which is empty if it has none.
+    pub(crate) fn get(&self, node: usize) -> Result<Range<usize>, Error> {
+        if self.has_chain.is_empty() || self.has_chain.get(node)? == 0 {
+            return Ok(0..0);
+        }
+
+        let index = self.has_chain.rank(1, node)? - 1;
+        let start = match index {
+            0 => 0,
+            _ => self.ends[index - 1] as usize,
+        };
+        Ok(start..self.ends[index] as usize)
+    }
+
+    // label returns the chain label at the given offset.
+    pub(crate) fn label(&self, offset: usize) -> u8 {
+        (self.labels[offset / 8] >> (8 * (offset % 8))) as u8
+    }
+
+    // labels returns the chain of the given node.
+    pub(crate) fn labels(&self, node: usize) -> Result<Vec<u8>, Error> {
+        Ok(self.get(node)?.map(|i| self.label(i)).collect())
+    }
+}
+
+// pack returns the given chain labels packed into words.
+pub(crate) fn pack(labels: &[u8]) -> Vec<u64> {
+    let mut words = vec![0u64; labels.len().div_ceil(8)];
+    for (i, l) in labels.iter().enumerate() {
+        words[i / 8] |= (*l as u64) << (8 * (i % 8));
+    }
+    words
+}
+
+// encoded_bytes returns the number of bytes taken up by the chains of a tree
+// with the given number of nodes, holding the given number of chains and
+// chain labels, including the rank directory of D-HasChain.
+pub(crate) fn encoded_bytes(nodes: usize, chains: usize, labels: usize) -> usize {
+    bitmap_bytes(nodes) + rank::directory_bytes(nodes) + 8 * chains + 8 * labels.div_ceil(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chains() {
+        // Nodes 1 and 3 have the chains "tenant/" and "0".
+        let has_chain = [0x5000000000000000];
+        let ranks = rank::directory(&has_chain);
+        let ends = [7, 8];
+        let labels = pack(b"tenant/0");
+        let chains = Chains {
+            has_chain: BitmapRef::new(&has_chain, &ranks),
+            ends: &ends,
+            labels: &labels,
+        };
+
+        assert_eq!(chains.get(0), Ok(0..0));
+        assert_eq!(chains.labels(1), Ok(b"tenant/".to_vec()));
+        assert_eq!(chains.labels(3), Ok(b"0".to_vec()));
+        assert_eq!(chains.label(7), b'0');
+
+        let empty = Chains {
+            has_chain: BitmapRef::new(&[], &[]),
+            ends: &[],
+            labels: &[],
+        };
+        assert_eq!(empty.get(5), Ok(0..0));
+    }
+}
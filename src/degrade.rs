@@ -0,0 +1,225 @@
+use crate::dense::{encoded_bytes, level_node_counts};
+use crate::iterator::Error;
+use crate::key::{truncate, Key};
+use crate::options::Options;
+use crate::surf::{prepare, Surf};
+
+// Precision describes how precise a filter built by Surf::with_budget is,
+// after giving up what it took to fit the budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Precision {
+    // hash_bits is the number of hash bits stored for each key.
+    pub hash_bits: u32,
+    // real_bits is the number of real bits stored for each key.
+    pub real_bits: u32,
+    // max_depth is the depth the trie was capped at, if it had to be. Keys
+    // are then only stored by their first max_depth bytes, so that the
+    // filter answers yes to any query matching one of those prefixes.
+    pub max_depth: Option<usize>,
+    // bytes is the number of bytes used by the filter.
+    pub bytes: usize,
+}
+
+impl Surf {
+    // with_budget builds a filter from the given keys with the given options
+    // which uses at most budget bytes, giving up only as much precision as it
+    // takes to fit. The budget replaces Options::memory_limit, while all other
+    // options, such as the transform and the alphabet, are kept.
+    //
+    // It starts out from the suffixes of the given options, and first drops
+    // hash bits, then real bits, as the latter serve range queries as well. If
+    // the trie alone does not fit either, it is capped at the greatest depth
+    // which does, leaving a filter of key prefixes.
+    //
+    // Moving levels from LOUDS-DENSE to LOUDS-SPARSE would come in between
+    // dropping suffix bits and capping the trie, but is blocked on
+    // LOUDS-SPARSE, which is not implemented yet. Until it is, the step is
+    // skipped.
+    //
+    // If not even the root node of the trie fits, Error::MemoryLimitExceeded
+    // is returned.
+    pub fn with_budget(
+        raw_keys: Vec<Vec<u8>>,
+        mut options: Options,
+        budget: usize,
+    ) -> Result<(Surf, Precision), Error> {
+        options.memory_limit = budget;
+        let keys = prepare(raw_keys, &mut options)?;
+        let truncated = truncate(&keys);
+
+        loop {
+            match Surf::build(&keys, &truncated, options.clone()) {
+                Ok(surf) => return Ok(with_precision(surf, None)),
+                Err(Error::MemoryLimitExceeded { .. }) if options.hash_bits > 0 => {
+                    options.hash_bits -= 1
+                }
+                Err(Error::MemoryLimitExceeded { .. }) if options.real_bits > 0 => {
+                    options.real_bits -= 1
+                }
+                Err(Error::MemoryLimitExceeded { .. }) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Capping the trie at a depth leaves the levels above it untouched, so
+        // the greatest depth whose bitmaps fit follows from the full trie.
+        // What else the options store, such as chains, a root table or
+        // records, may not fit at that depth, so shallower depths are tried
+        // in turn.
+        let levels = level_node_counts(&truncated);
+        let width = options.alphabet.width();
+        let mut depth = 1;
+        while depth < levels.len() && encoded_bytes(levels[..=depth].iter().sum(), width) <= budget
+        {
+            depth += 1;
+        }
+
+        loop {
+            // Cutting sorted keys short keeps them sorted.
+            let mut capped: Vec<Key> = truncated
+                .iter()
+                .map(|k| k[..k.len().min(depth)].to_vec())
+                .collect();
+            capped.dedup();
+
+            match Surf::build(&capped, &capped, options.clone()) {
+                Ok(surf) => return Ok(with_precision(surf, Some(depth))),
+                Err(Error::MemoryLimitExceeded { .. }) if depth > 1 => depth -= 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// with_precision pairs the given filter with its precision.
+fn with_precision(surf: Surf, max_depth: Option<usize>) -> (Surf, Precision) {
+    let precision = Precision {
+        hash_bits: surf.options.hash_bits,
+        real_bits: surf.options.real_bits,
+        max_depth,
+        bytes: surf.stats().memory.total(),
+    };
+    (surf, precision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::testing::random;
+    use crate::transform::KeyTransform;
+
+    // random_keys returns n pseudo-random 8-byte keys, as generated by a
+    // xorshift generator with the given seed.
+    fn random_keys(n: usize, seed: u64) -> Vec<Vec<u8>> {
+        random(n, seed)
+            .iter()
+            .map(|x| x.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    fn check_keys(surf: &Surf, keys: &[Vec<u8>]) {
+        assert_eq!(surf.verify(), Ok(()));
+        for k in keys {
+            assert!(surf.get(k.clone()).unwrap().0, "{:?}", k);
+            assert_eq!(surf.range(k.clone(), k.clone()), Ok(true), "{:?}", k);
+        }
+    }
+
+    #[test]
+    fn test_with_budget() {
+        // 640 keys take up 80 bytes per suffix bit.
+        let keys = random_keys(640, 0x2545F4914F6CDD1D);
+        let options = Options {
+            hash_bits: 0,
+            real_bits: 0,
+            ..Options::new()
+        };
+        let trie_bytes = Surf::new(keys.clone(), options)
+            .unwrap()
+            .stats()
+            .memory
+            .total();
+
+        let (surf, precision) =
+            Surf::with_budget(keys.clone(), Options::new(), usize::MAX).unwrap();
+        assert_eq!((precision.hash_bits, precision.real_bits), (4, 4));
+        assert_eq!(precision.max_depth, None);
+        assert_eq!(precision.bytes, trie_bytes + 8 * 80);
+        check_keys(&surf, &keys);
+
+        // Hash bits go first.
+        let (surf, precision) =
+            Surf::with_budget(keys.clone(), Options::new(), trie_bytes + 5 * 80).unwrap();
+        assert_eq!((precision.hash_bits, precision.real_bits), (1, 4));
+        assert_eq!(precision.bytes, trie_bytes + 5 * 80);
+        check_keys(&surf, &keys);
+
+        let (surf, precision) =
+            Surf::with_budget(keys.clone(), Options::new(), trie_bytes + 80 - 1).unwrap();
+        assert_eq!((precision.hash_bits, precision.real_bits), (0, 0));
+        assert_eq!(precision.max_depth, None);
+        check_keys(&surf, &keys);
+    }
+
+    #[test]
+    fn test_with_budget_capped() {
+        let keys = random_keys(640, 0x9E3779B97F4A7C15);
+        let full = Surf::new(keys.clone(), Options::new()).unwrap();
+        assert!(full.height() > 2);
+
+        let budget = encoded_bytes(full.levels[..2].iter().sum(), 256);
+        let (surf, precision) = Surf::with_budget(keys.clone(), Options::new(), budget).unwrap();
+        assert_eq!((precision.hash_bits, precision.real_bits), (0, 0));
+        assert_eq!(precision.max_depth, Some(2));
+        assert_eq!(precision.bytes, budget);
+        assert_eq!(surf.height(), 2);
+        check_keys(&surf, &keys);
+
+        // A capped filter matches keys by their prefix only.
+        let mut prefix_match = keys[0][..2].to_vec();
+        prefix_match.push(0xFF);
+        assert!(surf.get(prefix_match).unwrap().0);
+
+        assert!(matches!(
+            Surf::with_budget(keys, Options::new(), encoded_bytes(1, 256) - 1),
+            Err(Error::MemoryLimitExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_budget_options() {
+        // Upper case hex keys, which the transform maps to lower case.
+        let keys: Vec<Vec<u8>> = random(640, 0x9E3779B97F4A7C15)
+            .iter()
+            .map(|x| format!("{:016X}", x).into_bytes())
+            .collect();
+        let options = Options {
+            transform: KeyTransform::AsciiLowercase,
+            alphabet: Alphabet::Detect,
+            ..Options::new()
+        };
+        let full = Surf::new(keys.clone(), options.clone()).unwrap();
+        let budget = full.stats().memory.total() - 1;
+
+        let (surf, precision) = Surf::with_budget(keys.clone(), options, budget).unwrap();
+        assert!(precision.bytes <= budget);
+        assert_eq!(surf.options.transform, KeyTransform::AsciiLowercase);
+        assert_eq!(surf.options.alphabet, full.options.alphabet);
+        assert_eq!(surf.options.alphabet.width(), 16);
+        check_keys(&surf, &keys);
+        for k in &keys {
+            let lower = k.to_ascii_lowercase();
+            assert!(surf.get(lower.clone()).unwrap().0, "{:?}", lower);
+        }
+
+        // Capping the trie keeps the options as well.
+        let budget = encoded_bytes(full.levels[..2].iter().sum(), 16);
+        let (surf, precision) =
+            Surf::with_budget(keys.clone(), full.options.clone(), budget).unwrap();
+        assert_eq!(precision.max_depth, Some(2));
+        assert!(precision.bytes <= budget);
+        assert_eq!(surf.options.alphabet.width(), 16);
+        check_keys(&surf, &keys);
+    }
+}
@@ -1,5 +1,15 @@
+use std::convert::Infallible;
+use std::ops::Range;
+
 use crate::bitmap::Bitmap;
+use crate::chain;
+use crate::iterator::Error;
 use crate::key::Key;
+use crate::layout::{self, DenseLayout};
+use crate::memory::{bitmap_bytes, Budget};
+use crate::rank;
+use crate::root_table;
+use crate::suffix;
 
 // NodeTask contains things which need to be considered for building up a future node.
 //
@@ -19,11 +29,16 @@ struct NodeTask {
 pub(crate) struct Builder {
     // Labels is the D-Labels bitmap of the DENSE-encoded FST.
     //
-    // Nodes are encoded as blocks of 256 bits in level-order. If the node
-    // has an outbound edge with value `b`, then the b-th bit of the node's
-    // 256 bit block is set.
+    // Nodes are encoded as blocks of `width` bits in level-order, one bit per
+    // symbol of the alphabet, which are all 256 bytes by default. If the node
+    // has an outbound edge with symbol `b`, then the b-th bit of the node's
+    // block is set.
     pub(crate) labels: Bitmap,
 
+    // labels_ranks is the rank directory of the D-Labels bitmap, used to find
+    // the suffix of the key an edge ends.
+    pub(crate) labels_ranks: Vec<u64>,
+
     // HasChild is the D-HasChild bitmap of the DENSE-encoded FST.
     //
     // If a node has an outbound edge with value `b` leading to a subtree
@@ -31,12 +46,63 @@ pub(crate) struct Builder {
     // node's block is set.
     pub(crate) has_child: Bitmap,
 
+    // has_child_ranks is the rank directory of the D-HasChild bitmap, used
+    // to find the child node an edge leads to.
+    pub(crate) has_child_ranks: Vec<u64>,
+
     // IsPrefixKey is the D-IsPrefixKey bitmap of the DENSE-encoded FST.
     //
     // If the n-th node is also the terminal node of a stored key, the n-th
     // bit of this bitmap will be set.
     pub(crate) is_prefix_key: Bitmap,
 
+    // is_prefix_key_ranks is the rank directory of the D-IsPrefixKey bitmap,
+    // used to find the suffix of the key a node ends.
+    pub(crate) is_prefix_key_ranks: Vec<u64>,
+
+    // HasChain is the D-HasChain bitmap of trees built with path
+    // compression, and empty otherwise.
+    //
+    // If the n-th node is reached through a chain of unary nodes which were
+    // left out of the tree, the n-th bit of this bitmap will be set.
+    pub(crate) has_chain: Bitmap,
+
+    // has_chain_ranks is the rank directory of the D-HasChain bitmap, used to
+    // find the chain of a node.
+    pub(crate) has_chain_ranks: Vec<u64>,
+
+    // chain_ends holds the end offset of each chain within chain_labels.
+    pub(crate) chain_ends: Vec<u64>,
+
+    // chain_labels holds the labels of all chains, as packed by chain::pack.
+    pub(crate) chain_labels: Vec<u64>,
+
+    // level_nodes holds the number of nodes on each level of the tree.
+    pub(crate) level_nodes: Vec<usize>,
+
+    // budget accounts for the memory used by the encoded tree, and enforces
+    // the configured memory limit.
+    pub(crate) budget: Budget,
+
+    // root_table_bytes is the number of leading symbols indexing the root
+    // table built along with the tree, which is accounted for up front, as
+    // the table is built once the tree is done. It is 0 unless set after
+    // creating the builder.
+    pub(crate) root_table_bytes: usize,
+
+    // layout is the layout the tree will be stored in, whose records are
+    // built once the tree is done and accounted for along with it. It is
+    // DenseLayout::Bitmaps unless set after creating the builder.
+    pub(crate) layout: DenseLayout,
+
+    // suffix_width is the number of bits of the suffix stored for each key,
+    // which are accounted for along with the tree.
+    suffix_width: u32,
+
+    // width is the number of edges of each node, i.e. the number of symbols
+    // keys are made of.
+    width: usize,
+
     // tasks is a slice of tasks to be taken care of to define nodes
     // further down the tree.
     // There is a 1:1 correspondence between tasks and (potential) future
@@ -56,15 +122,27 @@ pub(crate) struct Builder {
 impl Builder {
     // NewBuilder instantiates a new LOUDS-DENSE builder.
     //
-    // memory_limit specifies the memory limits in bits.
-    pub(crate) fn new(memory_limit: usize) -> Self {
-        // Labels and HasChild are 256 bits per node, IsPrefixKey is 1 bit per node.
-        let memory_unit = memory_limit / (256 + 256 + 1);
-
+    // memory_limit specifies the memory limit in bytes, suffix_width the
+    // number of suffix bits which will be stored for each key, and width the
+    // number of symbols keys are made of, all of which must be less than it.
+    pub(crate) fn new(memory_limit: usize, suffix_width: u32, width: usize) -> Self {
         Builder {
-            labels: Bitmap::new(256, 256 * memory_unit),
-            has_child: Bitmap::new(256, 256 * memory_unit),
-            is_prefix_key: Bitmap::new(1, memory_unit),
+            labels: Bitmap::new(0, 0),
+            labels_ranks: Vec::new(),
+            has_child: Bitmap::new(0, 0),
+            has_child_ranks: Vec::new(),
+            is_prefix_key: Bitmap::new(0, 0),
+            is_prefix_key_ranks: Vec::new(),
+            has_chain: Bitmap::new(0, 0),
+            has_chain_ranks: Vec::new(),
+            chain_ends: Vec::new(),
+            chain_labels: Vec::new(),
+            level_nodes: Vec::new(),
+            budget: Budget::new(memory_limit),
+            root_table_bytes: 0,
+            layout: DenseLayout::Bitmaps,
+            suffix_width,
+            width,
             tasks: Vec::new(),
             current_task_id: 0,
             current_node_id: 0,
@@ -75,16 +153,44 @@ impl Builder {
     //
     // Build may only be called on a freshly created instance. Calling Build on a
     // builder more than once is not guaranteed to produce a consistent tree.
-    pub(crate) fn build(&mut self, keys: &[Key]) -> Result<(), &'static str> {
-        // For depth = 0 we'll consider all keys
+    pub(crate) fn build(&mut self, keys: &[Key]) -> Result<(), Error> {
+        // We know up front how many nodes and keys each level will have, so
+        // we can account for the memory of the whole tree before allocating
+        // any of it.
+        self.level_nodes = level_node_counts(keys);
+        let level_keys = level_key_counts(keys, self.level_nodes.len());
+        self.charge_root_table();
+
+        let size = |nodes, keys| {
+            encoded_bytes(nodes, self.width)
+                + 8 * suffix::words(keys, self.suffix_width)
+                + layout::encoded_bytes(self.layout, nodes, self.width)
+        };
+        let (mut nodes, mut ended) = (0, 0);
+        for (level, level_nodes) in self.level_nodes.iter().enumerate() {
+            let before = size(nodes, ended);
+            nodes += level_nodes;
+            ended += level_keys[level];
+            self.budget.charge(level, size(nodes, ended) - before);
+        }
+        self.budget.check()?;
+
+        // Labels and HasChild are width bits per node, IsPrefixKey is 1 bit per node.
+        self.labels = Bitmap::new(0, self.width * nodes);
+        self.has_child = Bitmap::new(0, self.width * nodes);
+        self.is_prefix_key = Bitmap::new(0, nodes);
+
+        // For depth = 0 we'll consider all keys. The empty key ends on the
+        // root node itself.
         self.append_node_task();
         {
             let tasks = &mut self.tasks;
             let current_task = tasks.get_mut(self.current_task_id).unwrap();
-            current_task.keys = keys.to_vec();
+            current_task.keys = keys.iter().filter(|k| !k.is_empty()).cloned().collect();
+            current_task.is_prefix_key = keys.iter().any(|k| k.is_empty());
         }
 
-        for depth in 0..max_key_length(keys) {
+        for depth in 0..self.level_nodes.len() {
             // During iteration we'll be adding tasks of the next tree
             // level. But we only want to consider tasks of the current
             // level.
@@ -95,10 +201,11 @@ impl Builder {
                     tasks[i].clone()
                 };
 
-                if task.keys.is_empty() {
+                if task.keys.is_empty() && depth > 0 {
                     // Empty tasks are the result of there only being a
                     // single key pointing to this node, which has reached
-                    // the end.
+                    // the end. The root node is built even if only the
+                    // empty key ends on it.
                     continue;
                 }
 
@@ -111,13 +218,14 @@ impl Builder {
                 // allocated.
                 // This is not strictly needed, but makes for cleaner / easier to test
                 // results.
-                self.labels.get(self.label_offset() + 255)?;
-                self.has_child.get(self.has_child_offset() + 255)?;
+                self.labels.get(self.label_offset() + self.width - 1)?;
+                self.has_child
+                    .get(self.has_child_offset() + self.width - 1)?;
                 let bit = self.is_prefix_key_offset();
                 self.is_prefix_key.get(bit)?;
 
-                // If the node is non-empty (which is the case if we are here), and the task has
-                // its is_prefix_key flag set, then that means that one key ended on this node.
+                // If the task has its is_prefix_key flag set, then that means that one key ended
+                // on this node.
                 if task.is_prefix_key {
                     self.is_prefix_key.set(bit)?;
                 }
@@ -163,17 +271,96 @@ impl Builder {
             // discard them.
             self.tasks.drain(..n);
         }
+
+        self.labels_ranks = rank::directory(&self.labels.data);
+        self.has_child_ranks = rank::directory(&self.has_child.data);
+        self.is_prefix_key_ranks = rank::directory(&self.is_prefix_key.data);
+
+        Ok(())
+    }
+
+    // charge_root_table charges the root table to the root level, which its
+    // entries lead down from.
+    fn charge_root_table(&mut self) {
+        let bytes = root_table::encoded_bytes(self.width, self.root_table_bytes);
+        self.budget.charge(0, bytes);
+    }
+
+    // build_compressed instantiates a LOUDS-DENSE encoded tree using the
+    // given keys like build, but with path compression: chains of unary
+    // nodes are left out of the tree, and stored as the chain of the node
+    // they lead to instead.
+    pub(crate) fn build_compressed(&mut self, keys: &[Key]) -> Result<(), Error> {
+        // A first walk over the tree gives the size of each level, so that
+        // the memory of the whole tree is accounted for before allocating any
+        // of it.
+        let levels = compressed_level_counts(keys);
+        self.charge_root_table();
+
+        let size = |[nodes, keys, chains, chain_labels]: [usize; 4]| {
+            encoded_bytes(nodes, self.width)
+                + 8 * suffix::words(keys, self.suffix_width)
+                + chain::encoded_bytes(nodes, chains, chain_labels)
+                + layout::encoded_bytes(self.layout, nodes, self.width)
+        };
+        let mut total = [0; 4];
+        for (level, counts) in levels.iter().enumerate() {
+            let before = size(total);
+            for (t, c) in total.iter_mut().zip(counts) {
+                *t += c;
+            }
+            self.budget.charge(level, size(total) - before);
+        }
+        self.budget.check()?;
+
+        let [nodes, _, _, chain_labels] = total;
+        self.level_nodes = levels.iter().map(|[nodes, ..]| *nodes).collect();
+        self.labels = Bitmap::new(self.width * nodes, self.width * nodes);
+        self.has_child = Bitmap::new(self.width * nodes, self.width * nodes);
+        self.is_prefix_key = Bitmap::new(nodes, nodes);
+        self.has_chain = Bitmap::new(nodes, nodes);
+
+        let mut labels = Vec::with_capacity(chain_labels);
+        let mut node = 0;
+        walk_compressed(keys, |_, keys, chain| -> Result<(), Error> {
+            if keys[0].len() == chain.end {
+                self.is_prefix_key.set(node)?;
+            }
+            if !chain.is_empty() {
+                self.has_chain.set(node)?;
+                labels.extend_from_slice(&keys[0][chain.clone()]);
+                self.chain_ends.push(labels.len() as u64);
+            }
+
+            for (label, _, leaf) in edges(keys, chain.end) {
+                let bit = self.width * node + label as usize;
+                self.labels.set(bit)?;
+                if !leaf {
+                    self.has_child.set(bit)?;
+                }
+            }
+
+            node += 1;
+            Ok(())
+        })?;
+
+        self.labels_ranks = rank::directory(&self.labels.data);
+        self.has_child_ranks = rank::directory(&self.has_child.data);
+        self.is_prefix_key_ranks = rank::directory(&self.is_prefix_key.data);
+        self.has_chain_ranks = rank::directory(&self.has_chain.data);
+        self.chain_labels = chain::pack(&labels);
+
         Ok(())
     }
 
     // label_offset returns the offset in the D-Labels bitmap of the currently processed node.
     fn label_offset(&self) -> usize {
-        self.current_node_id * 256
+        self.current_node_id * self.width
     }
 
     // has_child_offset returns the offset in the D-HasChild bitmap of the currently processed node.
     fn has_child_offset(&self) -> usize {
-        self.current_node_id * 256
+        self.current_node_id * self.width
     }
 
     // is_prefix_key_offset returns the offset in the D-IsPrefixKey bitmap of the currently processed node.
@@ -194,6 +381,146 @@ impl Builder {
     }
 }
 
+// encoded_bytes returns the number of bytes taken up by the LOUDS-DENSE
+// encoding of a tree with the given number of nodes of the given width,
+// including the rank directories of its bitmaps.
+pub(crate) fn encoded_bytes(nodes: usize, width: usize) -> usize {
+    2 * bitmap_bytes(width * nodes)
+        + bitmap_bytes(nodes)
+        + 2 * rank::directory_bytes(width * nodes)
+        + rank::directory_bytes(nodes)
+}
+
+// level_node_counts returns the number of nodes on each level of the tree
+// built from the given sorted keys.
+//
+// The nodes on level `d` correspond to the distinct prefixes of length `d` of
+// all keys which are longer than `d` bytes.
+pub(crate) fn level_node_counts(keys: &[Key]) -> Vec<usize> {
+    // Any key, even only the empty one, makes for a root node.
+    let levels = match keys.is_empty() {
+        true => 0,
+        false => max_key_length(keys).max(1),
+    };
+    let mut counts = vec![0; levels];
+
+    for (depth, count) in counts.iter_mut().enumerate() {
+        let mut previous: Option<&[u8]> = None;
+        for key in keys.iter().filter(|k| k.len() > depth || depth == 0) {
+            let prefix = &key[..depth];
+            if previous != Some(prefix) {
+                *count += 1;
+                previous = Some(prefix);
+            }
+        }
+    }
+
+    counts
+}
+
+// level_key_counts returns the number of the given sorted, truncated keys
+// which end on each of the given number of levels of the tree.
+//
+// A key which is a prefix of the next one ends on the node its last byte
+// leads to, all other keys end on the leaf edge of their last byte.
+fn level_key_counts(keys: &[Key], levels: usize) -> Vec<usize> {
+    let mut counts = vec![0; levels];
+
+    for (i, key) in keys.iter().enumerate() {
+        let is_prefix_key = keys.get(i + 1).is_some_and(|next| next.starts_with(key));
+        match key.len() {
+            n if is_prefix_key => counts[n] += 1,
+            0 => counts[0] += 1,
+            n => counts[n - 1] += 1,
+        }
+    }
+
+    counts
+}
+
+// compressed_level_counts returns the number of nodes, keys, chains and chain
+// labels on each level of the path compressed tree built from the given
+// sorted, truncated keys.
+pub(crate) fn compressed_level_counts(keys: &[Key]) -> Vec<[usize; 4]> {
+    let mut levels: Vec<[usize; 4]> = Vec::new();
+    let Ok(()) = walk_compressed(keys, |level, keys, chain| -> Result<(), Infallible> {
+        if levels.len() == level {
+            levels.push([0; 4]);
+        }
+        let [nodes, ended, chains, chain_labels] = &mut levels[level];
+        *nodes += 1;
+        *ended += edges(keys, chain.end).filter(|(_, _, leaf)| *leaf).count();
+        *ended += (keys[0].len() == chain.end) as usize;
+        *chains += !chain.is_empty() as usize;
+        *chain_labels += chain.len();
+        Ok(())
+    });
+
+    levels
+}
+
+// walk_compressed visits the nodes of the path compressed tree built from the
+// given sorted, truncated keys in level order.
+//
+// Each node is visited along with its level, the keys below it, and the
+// range of bytes of these keys which make up its chain. All keys below a node
+// share their bytes up to the end of its chain, after which its edges
+// follow.
+fn walk_compressed<E>(
+    keys: &[Key],
+    mut visit: impl FnMut(usize, &[Key], Range<usize>) -> Result<(), E>,
+) -> Result<(), E> {
+    // Without any key there is no root node.
+    let mut nodes = match !keys.is_empty() {
+        true => vec![(keys, 0)],
+        false => vec![],
+    };
+
+    let mut level = 0;
+    while !nodes.is_empty() {
+        let mut children = Vec::new();
+        for (keys, start) in nodes {
+            // A node is unary if no key ends on it, and all keys continue
+            // with the same label, which leads to a child rather than being a
+            // leaf.
+            let (first, last) = (&keys[0], &keys[keys.len() - 1]);
+            let mut end = start;
+            while first.len() > end
+                && first[end] == last[end]
+                && !(keys.len() == 1 && first.len() == end + 1)
+            {
+                end += 1;
+            }
+
+            visit(level, keys, start..end)?;
+            for (_, keys, leaf) in edges(keys, end) {
+                if !leaf {
+                    children.push((keys, end + 1));
+                }
+            }
+        }
+
+        nodes = children;
+        level += 1;
+    }
+
+    Ok(())
+}
+
+// edges returns the edges of the node whose keys share the given number of
+// bytes: the label of each edge, the keys below it, and whether it is a leaf
+// edge, on which a single key ends.
+fn edges(keys: &[Key], depth: usize) -> impl Iterator<Item = (u8, &[Key], bool)> {
+    // A key ending on the node itself has no edge.
+    let skip = (keys[0].len() == depth) as usize;
+    keys[skip..]
+        .chunk_by(move |a, b| a[depth] == b[depth])
+        .map(move |keys| {
+            let leaf = keys.len() == 1 && keys[0].len() == depth + 1;
+            (keys[0][depth], keys, leaf)
+        })
+}
+
 // max_key_length returns the maximum length in bytes of the given LOUDS keys.
 fn max_key_length(keys: &[Key]) -> usize {
     keys.iter().map(|k| k.len()).max().unwrap_or(0)
@@ -219,11 +546,11 @@ mod tests {
         ]
     }
 
-    const MEM_LIMIt: usize = 80_000_000;
+    const MEM_LIMIT: usize = 80_000_000;
 
     #[test]
     fn test_build_one_level() {
-        let mut b = Builder::new(MEM_LIMIt);
+        let mut b = Builder::new(MEM_LIMIT, 0, 256);
         let keys: Vec<Key> = vec![
             vec![0x00], // 0
             vec![0x17], // 1
@@ -247,8 +574,9 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn test_build_two_levels() {
-        let mut builder = Builder::new(MEM_LIMIt);
+        let mut builder = Builder::new(MEM_LIMIT, 0, 256);
         let keys: Vec<Key> = vec![
             b"ai".to_vec(),
             b"ao".to_vec(),
@@ -283,7 +611,7 @@ mod tests {
         ];
 
         for bit in children {
-            expected_has_child.set(bit as usize).unwrap();
+            expected_has_child.set(bit).unwrap();
         }
 
         let prefix_keys = vec![
@@ -313,4 +641,51 @@ mod tests {
             expected_is_prefix_key, builder.is_prefix_key
         );
     }
+
+    #[test]
+    fn test_level_node_counts() {
+        assert_eq!(level_node_counts(&get_keys()), vec![1, 2, 3, 2]);
+    }
+
+    #[test]
+    fn test_build_memory_limit() {
+        let keys = get_keys();
+
+        // Levels hold 1, 2, 3 and 2 nodes. 8 nodes take up 2 * 256 bytes of
+        // labels and has-child bits, 8 bytes of prefix key bits and 2 * 32 + 8
+        // bytes of rank directories.
+        let mut builder = Builder::new(592, 0, 256);
+        assert_eq!(builder.build(&keys), Ok(()));
+        assert_eq!(builder.budget.used(), 592);
+
+        // The first three levels take up 448 bytes, so the third one is the
+        // first to no longer fit.
+        let mut builder = Builder::new(300, 0, 256);
+        let err = builder.build(&keys).unwrap_err();
+        assert_eq!(
+            err,
+            Error::MemoryLimitExceeded {
+                needed: 592,
+                allowed: 300,
+                level: 2,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Memory limit exceeded at level 2: 592 bytes needed, 300 bytes allowed"
+        );
+
+        // With 8 suffix bits for each of the 11 keys, another 16 bytes are
+        // needed. Only the suffixes of the keys ending on the last level no
+        // longer fit.
+        let mut builder = Builder::new(592, 8, 256);
+        assert_eq!(
+            builder.build(&keys),
+            Err(Error::MemoryLimitExceeded {
+                needed: 608,
+                allowed: 592,
+                level: 3,
+            })
+        );
+    }
 }
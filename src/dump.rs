@@ -0,0 +1,339 @@
+use std::fmt::Write;
+
+use crate::iterator::Error;
+use crate::surf::{Surf, SurfRef};
+use crate::trie::Trie;
+
+impl Surf {
+    // to_dot renders the trie in the Graphviz DOT language.
+    //
+    // Nodes are labelled with their level-order id, and drawn with a double
+    // circle if a stored key ends on them. Keys ending on leaf edges are drawn
+    // as boxes holding the key. Edges leading to a node with a chain are
+    // labelled with the chain following their own label. This is only useful
+    // for small tries.
+    pub fn to_dot(&self) -> Result<String, Error> {
+        to_dot(&self.trie(), &self.levels)
+    }
+
+    // dump renders the stored truncated keys as an indented tree, followed by
+    // the bits set in each node's block of the bitmaps, level by level.
+    pub fn dump(&self) -> Result<String, Error> {
+        dump(&self.trie(), &self.levels)
+    }
+}
+
+impl<'a> SurfRef<'a> {
+    // to_dot renders the trie in the Graphviz DOT language.
+    //
+    // Nodes are labelled with their level-order id, and drawn with a double
+    // circle if a stored key ends on them. Keys ending on leaf edges are drawn
+    // as boxes holding the key. Edges leading to a node with a chain are
+    // labelled with the chain following their own label. This is only useful
+    // for small tries.
+    pub fn to_dot(&self) -> Result<String, Error> {
+        to_dot(&self.trie, &self.levels)
+    }
+
+    // dump renders the stored truncated keys as an indented tree, followed by
+    // the bits set in each node's block of the bitmaps, level by level.
+    pub fn dump(&self) -> Result<String, Error> {
+        dump(&self.trie, &self.levels)
+    }
+}
+
+fn to_dot(trie: &Trie, levels: &[usize]) -> Result<String, Error> {
+    let mut out = String::new();
+    writeln!(out, "digraph surf {{").unwrap();
+    writeln!(out, "  node [shape=circle];").unwrap();
+
+    let nodes: usize = levels.iter().sum();
+    for node in 0..nodes {
+        let shape = match trie.is_prefix_key.get(node)? {
+            1 => ", shape=doublecircle",
+            _ => "",
+        };
+        writeln!(out, "  n{} [label=\"{}\"{}];", node, node, shape).unwrap();
+    }
+
+    let mut keys = vec![Vec::new(); nodes];
+    if nodes > 0 {
+        keys[0] = chain(trie, 0)?;
+    }
+    for node in 0..nodes {
+        for (edge, child) in edges(trie, node)? {
+            let label = escape(&[edge]).replace('\\', "\\\\");
+            let mut key = keys[node].clone();
+            key.push(edge);
+
+            match child {
+                Some(child) => {
+                    let chain = chain(trie, child)?;
+                    let label = label + &escape(&chain).replace('\\', "\\\\");
+                    writeln!(out, "  n{} -> n{} [label=\"{}\"];", node, child, label).unwrap();
+                    key.extend_from_slice(&chain);
+                    keys[child] = key;
+                }
+                None => {
+                    let key = escape(&key).replace('\\', "\\\\");
+                    writeln!(out, "  l{}_{} [label=\"{}\", shape=box];", node, edge, key).unwrap();
+                    writeln!(
+                        out,
+                        "  n{} -> l{}_{} [label=\"{}\"];",
+                        node, node, edge, label
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+
+    writeln!(out, "}}").unwrap();
+    Ok(out)
+}
+
+fn dump(trie: &Trie, levels: &[usize]) -> Result<String, Error> {
+    let mut out = String::new();
+
+    writeln!(out, "keys:").unwrap();
+    if !levels.is_empty() {
+        // All keys start with the chain of the root, if there is one.
+        let mut prefix = chain(trie, 0)?;
+        let ends = trie.is_prefix_key.get(0)? == 1;
+        match (prefix.is_empty(), ends) {
+            (true, false) => {}
+            (true, true) => writeln!(out, "  \"\"").unwrap(),
+            (false, false) => writeln!(out, "  {}", escape(&prefix)).unwrap(),
+            (false, true) => {
+                writeln!(out, "  {}  \"{}\"", escape(&prefix), escape(&prefix)).unwrap()
+            }
+        }
+        dump_keys(trie, 0, &mut prefix, 1, &mut out)?;
+    }
+
+    writeln!(out, "levels:").unwrap();
+    let mut node = 0;
+    for (level, n) in levels.iter().enumerate() {
+        writeln!(out, "  level {}:", level).unwrap();
+        for node in node..node + n {
+            let edges = edges(trie, node)?;
+            let labels: Vec<u8> = edges.iter().map(|(edge, _)| *edge).collect();
+            let has_child: Vec<u8> = edges
+                .iter()
+                .filter(|(_, child)| child.is_some())
+                .map(|(edge, _)| *edge)
+                .collect();
+
+            write!(
+                out,
+                "    node {}: is_prefix_key={} labels=[{}] has_child=[{}]",
+                node,
+                trie.is_prefix_key.get(node)?,
+                escape_each(&labels),
+                escape_each(&has_child)
+            )
+            .unwrap();
+            let chain = chain(trie, node)?;
+            if !chain.is_empty() {
+                write!(out, " chain={}", escape(&chain)).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        node += n;
+    }
+
+    Ok(out)
+}
+
+// dump_keys writes the keys below the given node, whose path from the root
+// is the given prefix, one edge per line, indented by the given depth. Edges
+// leading to a node with a chain are followed by the chain.
+fn dump_keys(
+    trie: &Trie,
+    node: usize,
+    prefix: &mut Vec<u8>,
+    depth: usize,
+    out: &mut String,
+) -> Result<(), Error> {
+    let indent = "  ".repeat(depth);
+
+    for (edge, child) in edges(trie, node)? {
+        let len = prefix.len();
+        prefix.push(edge);
+        if let Some(child) = child {
+            prefix.extend_from_slice(&chain(trie, child)?);
+        }
+
+        let ends = match child {
+            Some(child) => trie.is_prefix_key.get(child)? == 1,
+            None => true,
+        };
+        let label = escape(&prefix[len..]);
+        match ends {
+            true => writeln!(out, "{}{}  \"{}\"", indent, label, escape(prefix)),
+            false => writeln!(out, "{}{}", indent, label),
+        }
+        .unwrap();
+
+        if let Some(child) = child {
+            dump_keys(trie, child, prefix, depth + 1, out)?;
+        }
+        prefix.truncate(len);
+    }
+
+    Ok(())
+}
+
+// edges returns the labels of the edges of the given node, mapped back from
+// symbols to bytes, along with the child node each leads to, or None for
+// leaf edges.
+fn edges(trie: &Trie, node: usize) -> Result<Vec<(u8, Option<usize>)>, Error> {
+    let width = trie.symbols.width();
+    let mut edges = Vec::new();
+    for symbol in 0..width {
+        let bit = width * node + symbol;
+        if trie.labels.get(bit)? == 0 {
+            continue;
+        }
+
+        let child = match trie.has_child.get(bit)? {
+            1 => Some(trie.has_child.rank(1, bit)?),
+            _ => None,
+        };
+        edges.push((trie.symbols.byte(symbol as u8), child));
+    }
+
+    Ok(edges)
+}
+
+// chain returns the chain of the given node, mapped back from symbols to
+// bytes.
+fn chain(trie: &Trie, node: usize) -> Result<Vec<u8>, Error> {
+    let chain = trie.chains.labels(node)?;
+    Ok(chain.into_iter().map(|s| trie.symbols.byte(s)).collect())
+}
+
+// escape renders the given bytes, printing those which are not graphic ASCII
+// characters, as well as quotes and backslashes, as \xNN.
+fn escape(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for b in bytes {
+        match b {
+            b'"' | b'\\' => write!(s, "\\x{:02X}", b),
+            b if b.is_ascii_graphic() => write!(s, "{}", *b as char),
+            b => write!(s, "\\x{:02X}", b),
+        }
+        .unwrap();
+    }
+    s
+}
+
+// escape_each renders the given bytes separated by spaces.
+fn escape_each(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| escape(&[*b]))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::surf::Surf;
+
+    fn get_surf() -> Surf {
+        let keys = vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ];
+        Surf::new(keys, Options::new()).unwrap()
+    }
+
+    #[test]
+    fn test_dump() {
+        let expected = "\
+keys:
+  f  \"f\"
+    a
+      r  \"far\"
+      s  \"fas\"
+  s  \"s\"
+  t
+    o
+      p  \"top\"
+      y  \"toy\"
+    r  \"tr\"
+levels:
+  level 0:
+    node 0: is_prefix_key=0 labels=[f s t] has_child=[f t]
+  level 1:
+    node 1: is_prefix_key=1 labels=[a] has_child=[a]
+    node 2: is_prefix_key=0 labels=[o r] has_child=[o]
+  level 2:
+    node 3: is_prefix_key=0 labels=[r s] has_child=[]
+    node 4: is_prefix_key=0 labels=[p y] has_child=[]
+";
+        assert_eq!(get_surf().dump().unwrap(), expected);
+
+        let keys = vec![vec![], vec![0x00, 0x22]];
+        let surf = Surf::new(keys, Options::new()).unwrap();
+        assert_eq!(
+            surf.dump().unwrap(),
+            "keys:\n  \"\"\n  \\x00  \"\\x00\"\nlevels:\n  level 0:\n    \
+             node 0: is_prefix_key=1 labels=[\\x00] has_child=[]\n"
+        );
+    }
+
+    #[test]
+    fn test_dump_chains() {
+        let keys = vec![
+            b"tenant/a/x1".to_vec(),
+            b"tenant/a/x2".to_vec(),
+            b"tenant/b".to_vec(),
+        ];
+        let options = Options {
+            path_compression: true,
+            ..Options::new()
+        };
+        let surf = Surf::new(keys, options).unwrap();
+
+        let expected = "\
+keys:
+  tenant/
+  a/x
+    1  \"tenant/a/x1\"
+    2  \"tenant/a/x2\"
+  b  \"tenant/b\"
+levels:
+  level 0:
+    node 0: is_prefix_key=0 labels=[a b] has_child=[a] chain=tenant/
+  level 1:
+    node 1: is_prefix_key=0 labels=[1 2] has_child=[] chain=/x
+";
+        assert_eq!(surf.dump().unwrap(), expected);
+
+        let dot = surf.to_dot().unwrap();
+        assert!(dot.contains("  n0 -> n1 [label=\"a/x\"];\n"));
+        assert!(dot.contains("  l1_49 [label=\"tenant/a/x1\", shape=box];\n"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = get_surf().to_dot().unwrap();
+
+        assert!(dot.starts_with("digraph surf {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  n1 [label=\"1\", shape=doublecircle];\n"));
+        assert!(dot.contains("  n0 -> n1 [label=\"f\"];\n"));
+        assert!(dot.contains("  n1 -> n3 [label=\"a\"];\n"));
+        assert!(dot.contains("  l3_115 [label=\"fas\", shape=box];\n"));
+        assert!(dot.contains("  n3 -> l3_115 [label=\"s\"];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 10);
+    }
+}
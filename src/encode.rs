@@ -0,0 +1,310 @@
+// Order-preserving encodings of typed keys.
+//
+// Filters compare keys as byte strings, so typed keys must be encoded such
+// that comparing their encodings gives the same order as comparing the keys
+// themselves. All encodings here are memcomparable in that sense, and of a
+// fixed width per type:
+//
+// - Unsigned integers are stored big-endian.
+// - Signed integers are stored big-endian with their sign bit flipped, so
+//   that negative numbers come first.
+// - Floats are stored as their IEEE 754 bits, big-endian. Positive numbers
+//   have their sign bit flipped, negative numbers have all bits flipped. -0.0
+//   is stored as 0.0, and all NaNs as a single NaN, which comes after
+//   infinity.
+// - Fixed-size byte arrays, such as UUIDs or ULIDs, are stored as they are.
+// - Durations are stored as their whole seconds (u64), followed by their
+//   nanoseconds (u32), both big-endian.
+// - Points in time are stored like durations since the Unix epoch, but with
+//   their seconds signed (i64), rounded down, so that times before the epoch
+//   come first.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// KeyEncode is implemented by types with an order-preserving encoding.
+pub trait KeyEncode: Sized {
+    // WIDTH is the number of bytes of every encoded key.
+    const WIDTH: usize;
+
+    // encode returns the encoding of the key, whose order is that of the key.
+    fn encode(&self) -> Vec<u8>;
+
+    // decode returns the key of the given encoding, or None if it is not an
+    // encoding of this type.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! unsigned {
+    ($($t:ty),*) => {$(
+        impl KeyEncode for $t {
+            const WIDTH: usize = std::mem::size_of::<$t>();
+
+            fn encode(&self) -> Vec<u8> {
+                self.to_be_bytes().to_vec()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                Some(<$t>::from_be_bytes(bytes.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+macro_rules! signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncode for $t {
+            const WIDTH: usize = std::mem::size_of::<$t>();
+
+            fn encode(&self) -> Vec<u8> {
+                ((*self as $u) ^ (1 << (<$u>::BITS - 1))).to_be_bytes().to_vec()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                let v = <$u>::from_be_bytes(bytes.try_into().ok()?);
+                Some((v ^ (1 << (<$u>::BITS - 1))) as $t)
+            }
+        }
+    )*};
+}
+
+macro_rules! float {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncode for $t {
+            const WIDTH: usize = std::mem::size_of::<$t>();
+
+            fn encode(&self) -> Vec<u8> {
+                let v = match self {
+                    v if v.is_nan() => <$t>::NAN,
+                    // Adding zero turns -0.0 into 0.0.
+                    v => v + 0.0,
+                };
+
+                let sign = 1 << (<$u>::BITS - 1);
+                let bits = v.to_bits();
+                let bits = match bits & sign {
+                    0 => bits ^ sign,
+                    _ => !bits,
+                };
+                bits.to_be_bytes().to_vec()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                let sign = 1 << (<$u>::BITS - 1);
+                let bits = <$u>::from_be_bytes(bytes.try_into().ok()?);
+                let bits = match bits & sign {
+                    0 => !bits,
+                    _ => bits ^ sign,
+                };
+                Some(<$t>::from_bits(bits))
+            }
+        }
+    )*};
+}
+
+unsigned!(u8, u16, u32, u64, u128);
+signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+float!(f32 => u32, f64 => u64);
+
+impl<const N: usize> KeyEncode for [u8; N] {
+    const WIDTH: usize = N;
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+impl KeyEncode for Duration {
+    const WIDTH: usize = 12;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.as_secs().encode();
+        bytes.extend(self.subsec_nanos().encode());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::WIDTH {
+            return None;
+        }
+        let secs = u64::decode(&bytes[..8])?;
+        let nanos = u32::decode(&bytes[8..])?;
+        (nanos < NANOS_PER_SEC).then(|| Duration::new(secs, nanos))
+    }
+}
+
+impl KeyEncode for SystemTime {
+    const WIDTH: usize = 12;
+
+    fn encode(&self) -> Vec<u8> {
+        // Times before the epoch are rounded down to the second before, from
+        // which the nanoseconds count up. System times hold i64 seconds on
+        // all supported platforms, so the seconds always fit.
+        let (secs, nanos) = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+            Err(e) => {
+                let before = e.duration();
+                match before.subsec_nanos() {
+                    0 => (-(before.as_secs() as i64), 0),
+                    n => (-(before.as_secs() as i64) - 1, NANOS_PER_SEC - n),
+                }
+            }
+        };
+
+        let mut bytes = secs.encode();
+        bytes.extend(nanos.encode());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::WIDTH {
+            return None;
+        }
+        let secs = i64::decode(&bytes[..8])?;
+        let nanos = u32::decode(&bytes[8..])?;
+        if nanos >= NANOS_PER_SEC {
+            return None;
+        }
+
+        let whole = match secs {
+            0.. => UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))?,
+            _ => UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))?,
+        };
+        whole.checked_add(Duration::from_nanos(nanos as u64))
+    }
+}
+
+// NANOS_PER_SEC is the number of nanoseconds of a second.
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+// successor returns the least encoding of the given width which is greater
+// than the given one, or None if there is none.
+pub(crate) fn successor(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = bytes.to_vec();
+    for b in bytes.iter_mut().rev() {
+        let (v, overflow) = b.overflowing_add(1);
+        *b = v;
+        if !overflow {
+            return Some(bytes);
+        }
+    }
+    None
+}
+
+// predecessor returns the greatest encoding of the given width which is
+// lesser than the given one, or None if there is none.
+pub(crate) fn predecessor(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = bytes.to_vec();
+    for b in bytes.iter_mut().rev() {
+        let (v, overflow) = b.overflowing_sub(1);
+        *b = v;
+        if !overflow {
+            return Some(bytes);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // check_order asserts that the encodings of the given ascending values
+    // are ascending as well, and decode to the values.
+    fn check_order<K: KeyEncode + PartialOrd + std::fmt::Debug>(values: &[K]) {
+        for pair in values.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+            assert!(
+                pair[0].encode() < pair[1].encode(),
+                "encode({:?}) < encode({:?})",
+                pair[0],
+                pair[1]
+            );
+        }
+        for v in values {
+            assert_eq!(v.encode().len(), K::WIDTH);
+            assert_eq!(K::decode(&v.encode()).as_ref(), Some(v));
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        check_order(&[0u8, 1, 0x7F, 0x80, 0xFF]);
+        check_order(&[0u16, 1, 0xFF, 0x100, u16::MAX]);
+        check_order(&[0u32, 1, 0xFF, 0x100, u32::MAX]);
+        check_order(&[0u64, 1, 0xFF, 0x100, u64::MAX]);
+        check_order(&[0u128, 1, 0xFF, 0x100, u128::MAX]);
+
+        check_order(&[i8::MIN, -1, 0, 1, i8::MAX]);
+        check_order(&[i16::MIN, -256, -1, 0, 1, 256, i16::MAX]);
+        check_order(&[i32::MIN, -256, -1, 0, 1, 256, i32::MAX]);
+        check_order(&[i64::MIN, -256, -1, 0, 1, 256, i64::MAX]);
+        check_order(&[i128::MIN, -256, -1, 0, 1, 256, i128::MAX]);
+
+        assert_eq!(u16::decode(&[1]), None);
+    }
+
+    #[test]
+    fn test_floats() {
+        check_order(&[
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            f64::MAX,
+            f64::INFINITY,
+        ]);
+        check_order(&[f32::NEG_INFINITY, -1.5, 0.0, 1.5, f32::INFINITY]);
+
+        assert_eq!((-0.0f64).encode(), 0.0f64.encode());
+        assert_eq!(f64::NAN.encode(), (-f64::NAN).encode());
+        assert!(f64::NAN.encode() > f64::INFINITY.encode());
+        assert!(f64::decode(&f64::NAN.encode()).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let ids = [
+            [0u8; 16],
+            [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0xFF; 16],
+        ];
+        check_order(&ids);
+    }
+
+    #[test]
+    fn test_times() {
+        check_order(&[
+            Duration::ZERO,
+            Duration::from_nanos(1),
+            Duration::from_millis(999),
+            Duration::from_secs(1),
+            Duration::MAX,
+        ]);
+        assert_eq!(Duration::decode(&[0xFF; 12]), None);
+
+        let epoch = UNIX_EPOCH;
+        check_order(&[
+            epoch - Duration::from_secs(86_400),
+            epoch - Duration::from_millis(1_500),
+            epoch - Duration::from_secs(1),
+            epoch - Duration::from_nanos(1),
+            epoch,
+            epoch + Duration::from_nanos(1),
+            epoch + Duration::from_secs(1_700_000_000),
+        ]);
+    }
+
+    #[test]
+    fn test_successor_predecessor() {
+        assert_eq!(successor(&[0x00, 0xFF]), Some(vec![0x01, 0x00]));
+        assert_eq!(successor(&[0xFF, 0xFF]), None);
+        assert_eq!(predecessor(&[0x01, 0x00]), Some(vec![0x00, 0xFF]));
+        assert_eq!(predecessor(&[0x00, 0x00]), None);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::chain;
+use crate::dense::{compressed_level_counts, encoded_bytes, level_node_counts};
+use crate::key::{truncate, Key};
+use crate::layout;
+use crate::options::Options;
+use crate::root_table;
+use crate::suffix;
+use crate::surf::Surf;
+
+// Estimate is the predicted size of a filter, as computed by Surf::estimate
+// from a sample of its keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    // keys is the number of keys the filter will be built from.
+    pub keys: usize,
+    // level_nodes holds the predicted number of nodes on each level of the
+    // trie.
+    pub level_nodes: Vec<usize>,
+    // dense_bytes is the predicted size of the LOUDS-DENSE encoding,
+    // including its rank directories, the chains of path compression, and
+    // the root table.
+    pub dense_bytes: usize,
+    // suffix_bytes is the size of the stored key suffixes, which is known
+    // exactly from the number of keys.
+    pub suffix_bytes: usize,
+    // margin is the relative error margin of the predicted sizes, e.g. 0.1
+    // for +/- 10%.
+    pub margin: f64,
+}
+
+impl Estimate {
+    // total_bytes returns the predicted size of the whole filter.
+    pub fn total_bytes(&self) -> usize {
+        self.dense_bytes + self.suffix_bytes
+    }
+
+    // fits returns whether the predicted size, including its error margin,
+    // stays within the memory limit of the given options. The predicted size
+    // covers everything Surf::new charges against the limit, so that for a
+    // sample holding all keys, fits tells exactly whether building succeeds.
+    pub fn fits(&self, options: &Options) -> bool {
+        self.total_bytes() as f64 * (1.0 + self.margin) <= options.memory_limit as f64
+    }
+}
+
+impl Surf {
+    // estimate predicts the size of a filter built from total_keys keys, of
+    // which keys_sample is a uniformly drawn sample, without building it.
+    //
+    // A node on level l stands for a prefix of l bytes shared by at least two
+    // keys, as those keys are only told apart past it when truncated. The
+    // sample is truncated to distinguishing prefixes like the full set of
+    // keys would be, which tells how densely its keys fill the prefixes of
+    // each level, from which the nodes of the full set of keys are
+    // predicted level by level, bounded by the fan-out of the level above.
+    //
+    // The error margin covers two standard deviations of the number of
+    // sampled keys sharing a prefix on each level, and is zero if the sample
+    // holds all keys.
+    //
+    // With path compression, only a sample holding all keys is estimated
+    // exactly. Otherwise the trie is estimated as if it was not compressed,
+    // which overestimates tries of keys sharing long prefixes.
+    pub fn estimate(keys_sample: &[Vec<u8>], total_keys: usize, options: &Options) -> Estimate {
+        let mut keys: Vec<Key> = keys_sample
+            .iter()
+            .map(|k| options.transform.apply(k.clone()))
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        // A detected alphabet is detected from the sample, which may lack some
+        // bytes of the full set of keys. Keys outside of a given alphabet fail
+        // to build anyway, so they are simply estimated with all bytes.
+        let alphabet = options.alphabet.resolve(&keys).unwrap_or_default();
+
+        let total_keys = total_keys.max(keys.len());
+        let root_table =
+            root_table::encoded_bytes(alphabet.width(), options.root_table_bytes as usize);
+        let records = |nodes| layout::encoded_bytes(options.layout, nodes, alphabet.width());
+        let width = options.hash_bits + options.real_bits;
+        let suffix_bytes = 8 * suffix::words(total_keys, width);
+        if total_keys == keys.len() && options.path_compression {
+            let levels = compressed_level_counts(&truncate(&keys));
+            let [nodes, _, chains, chain_labels] =
+                levels.iter().fold([0; 4], |mut total, counts| {
+                    for (t, c) in total.iter_mut().zip(counts) {
+                        *t += c;
+                    }
+                    total
+                });
+            return Estimate {
+                keys: total_keys,
+                level_nodes: levels.iter().map(|[nodes, ..]| *nodes).collect(),
+                dense_bytes: encoded_bytes(nodes, alphabet.width())
+                    + chain::encoded_bytes(nodes, chains, chain_labels)
+                    + root_table
+                    + records(nodes),
+                suffix_bytes,
+                margin: 0.0,
+            };
+        }
+        if total_keys == keys.len() {
+            let level_nodes = level_node_counts(&truncate(&keys));
+            let nodes = level_nodes.iter().sum();
+            return Estimate {
+                keys: total_keys,
+                dense_bytes: encoded_bytes(nodes, alphabet.width()) + root_table + records(nodes),
+                level_nodes,
+                suffix_bytes,
+                margin: 0.0,
+            };
+        }
+
+        let scale = total_keys as f64 / keys.len() as f64;
+        let (level_nodes, deviation) = sampled_level_nodes(&keys, scale, alphabet.width());
+
+        let level_nodes: Vec<usize> = level_nodes.iter().map(|n| n.round() as usize).collect();
+        let nodes: usize = level_nodes.iter().sum();
+        let margin = match nodes {
+            0 => 1.0,
+            _ => 2.0 * deviation / nodes as f64 + 1.0 / (keys.len() as f64).sqrt(),
+        };
+
+        Estimate {
+            keys: total_keys,
+            dense_bytes: encoded_bytes(nodes, alphabet.width()) + root_table + records(nodes),
+            level_nodes,
+            suffix_bytes,
+            margin,
+        }
+    }
+}
+
+// sampled_level_nodes predicts the number of nodes on each level of the trie
+// of scale times as many keys as the given sorted, deduplicated sample, the
+// nodes of which have the given number of edges.
+//
+// A key is truncated to its distinguishing prefix, so that the keys longer
+// than a level either share their prefix of that length with another key,
+// making up a node, or are truncated at or above the level. Truncating the
+// sample tells how many sampled keys are of either kind, which gives how
+// densely they fill the prefixes of the level. Keys are assumed to be spread
+// evenly over those prefixes, so that scaling the density up to the full
+// number of keys predicts how many prefixes end up shared.
+//
+// It also returns the standard deviation of the predicted number of nodes.
+fn sampled_level_nodes(keys: &[Key], scale: f64, width: usize) -> (Vec<f64>, f64) {
+    let truncated = truncate(keys);
+    let sample_nodes = level_node_counts(&truncated);
+
+    let mut level_nodes: Vec<f64> = Vec::new();
+    let mut deviation = 0.0;
+    for level in 0.. {
+        let longer = keys.iter().filter(|k| k.len() > level).count();
+        if longer == 0 {
+            break;
+        }
+
+        // The distinct prefixes of the level are the nodes of the sampled
+        // trie, plus one per key not sharing its prefix.
+        let alone = keys
+            .iter()
+            .zip(&truncated)
+            .filter(|(k, t)| k.len() > level && t.len() <= level)
+            .count();
+        let shared = sample_nodes.get(level).copied().unwrap_or(0);
+        let density = prefix_density((alone + shared) as f64 / longer as f64);
+
+        // With the density of each prefix scaled up, a prefix is a node if
+        // it holds at least two keys.
+        let lambda = density * scale;
+        let predicted = match density {
+            0.0 => 0.0,
+            _ => longer as f64 / density * (1.0 - (-lambda).exp() * (1.0 + lambda)),
+        };
+
+        // A level can never hold more nodes than the level above it fans out
+        // to.
+        let bound = match level {
+            0 => 1.0,
+            _ => level_nodes[level - 1] * width as f64,
+        };
+        let nodes = predicted.max(shared as f64).min(bound);
+        level_nodes.push(nodes);
+
+        // The fewer keys share each prefix, the more the number of nodes
+        // depends on the density, whose relative error is that of the number
+        // of sampled keys sharing a prefix.
+        let sharing = (longer - alone).max(1) as f64;
+        let sensitivity = match lambda {
+            0.0 => 1.0,
+            _ => (1.0
+                - lambda * lambda * (-lambda).exp() / (1.0 - (-lambda).exp() * (1.0 + lambda)))
+                .abs(),
+        };
+        deviation += nodes * sensitivity / sharing.sqrt();
+    }
+
+    (level_nodes, deviation)
+}
+
+// prefix_density returns the mean number of sampled keys per prefix, if keys
+// spread evenly over prefixes leave the given ratio of distinct prefixes per
+// key, i.e. the mu solving (1 - e^-mu) / mu = ratio.
+fn prefix_density(ratio: f64) -> f64 {
+    let ratio_at = |mu: f64| (1.0 - (-mu).exp()) / mu;
+    if ratio >= 1.0 {
+        return 0.0;
+    }
+
+    let (mut low, mut high) = (0.0f64, 1.0f64);
+    while ratio_at(high) > ratio {
+        high *= 2.0;
+    }
+    for _ in 0..64 {
+        let mid = (low + high) / 2.0;
+        match ratio_at(mid) > ratio {
+            true => low = mid,
+            false => high = mid,
+        }
+    }
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::Error;
+    use crate::layout::DenseLayout;
+    use crate::testing::random;
+
+    // random_keys returns n pseudo-random keys, as generated by a xorshift
+    // generator with the given seed.
+    fn random_keys(n: usize, seed: u64, f: impl Fn(u64) -> Vec<u8>) -> Vec<Vec<u8>> {
+        random(n, seed).into_iter().map(f).collect()
+    }
+
+    fn check_estimate(keys: Vec<Vec<u8>>, sample_every: usize) {
+        let actual = Surf::new(keys.clone(), Options::new()).unwrap().stats();
+        let actual_bytes = actual.memory.total();
+
+        let sample: Vec<Vec<u8>> = keys.iter().step_by(sample_every).cloned().collect();
+        let estimate = Surf::estimate(&sample, keys.len(), &Options::new());
+
+        let error = estimate.total_bytes().abs_diff(actual_bytes) as f64 / actual_bytes as f64;
+        assert!(
+            error <= estimate.margin,
+            "estimated {} bytes, actual {} bytes, error {:.3} exceeds margin {:.3}",
+            estimate.total_bytes(),
+            actual_bytes,
+            error,
+            estimate.margin
+        );
+    }
+
+    #[test]
+    fn test_estimate_uniform() {
+        let keys = random_keys(20_000, 0x2545F4914F6CDD1D, |x| x.to_be_bytes().to_vec());
+        check_estimate(keys, 10);
+    }
+
+    #[test]
+    fn test_estimate_skewed() {
+        let keys = random_keys(20_000, 0x9E3779B97F4A7C15, |x| {
+            format!("tenant/{:03}/object/{:08}", x % 50, (x >> 8) % 100_000_000).into_bytes()
+        });
+        check_estimate(keys, 10);
+    }
+
+    #[test]
+    fn test_estimate_full_sample() {
+        let keys = random_keys(1_000, 42, |x| x.to_be_bytes().to_vec());
+        let actual = Surf::new(keys.clone(), Options::new()).unwrap().stats();
+
+        let estimate = Surf::estimate(&keys, keys.len(), &Options::new());
+        assert_eq!(estimate.level_nodes, actual.level_nodes);
+        assert_eq!(estimate.total_bytes(), actual.memory.total());
+        assert!(estimate.fits(&Options::new()));
+
+        let options = Options {
+            layout: DenseLayout::Interleaved,
+            ..Options::new()
+        };
+        let actual = Surf::new(keys.clone(), options.clone()).unwrap().stats();
+        let estimate = Surf::estimate(&keys, keys.len(), &options);
+        assert_eq!(estimate.total_bytes(), actual.memory.total());
+    }
+
+    #[test]
+    fn test_estimate_fits() {
+        let keys = random_keys(1_000, 42, |x| x.to_be_bytes().to_vec());
+        for (root_table_bytes, layout, path_compression) in [
+            (0, DenseLayout::Bitmaps, false),
+            (2, DenseLayout::Bitmaps, false),
+            (1, DenseLayout::Interleaved, false),
+            (2, DenseLayout::Interleaved, true),
+        ] {
+            let options = Options {
+                root_table_bytes,
+                layout,
+                path_compression,
+                ..Options::new()
+            };
+            let estimate = Surf::estimate(&keys, keys.len(), &options);
+
+            for memory_limit in [estimate.total_bytes(), estimate.total_bytes() - 1] {
+                let options = Options {
+                    memory_limit,
+                    ..options.clone()
+                };
+                let built = Surf::new(keys.clone(), options.clone());
+                assert_eq!(estimate.fits(&options), built.is_ok(), "{:?}", options);
+                if let Err(e) = built {
+                    assert!(matches!(e, Error::MemoryLimitExceeded { .. }), "{:?}", e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_estimate_path_compression() {
+        let mut keys = random_keys(1_000, 7, |x| {
+            format!("https://example.com/{:03}/index.html", x % 300).into_bytes()
+        });
+        keys.sort();
+        keys.dedup();
+        let options = Options {
+            path_compression: true,
+            ..Options::new()
+        };
+        let actual = Surf::new(keys.clone(), options.clone()).unwrap().stats();
+
+        let estimate = Surf::estimate(&keys, keys.len(), &options);
+        assert_eq!(estimate.level_nodes, actual.level_nodes);
+        assert_eq!(estimate.total_bytes(), actual.memory.total());
+    }
+
+    #[test]
+    fn test_estimate_empty() {
+        // Nothing is known about the trie, but the suffixes of 1000 keys take
+        // up 1000 bytes regardless.
+        let estimate = Surf::estimate(&[], 1_000, &Options::new());
+        assert_eq!(estimate.dense_bytes, 0);
+        assert_eq!(estimate.total_bytes(), 1_000);
+        assert!(estimate.level_nodes.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+use std::fmt;
+
+use crate::iterator::Error;
+use crate::surf::{Surf, SurfRef};
+use crate::trie::{Trie, Visit};
+
+// Step is a node visited by a query, along with the edge it left it by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    // level is the level of the node.
+    pub level: usize,
+    // node is the level-order id of the node.
+    pub node: usize,
+    // is_prefix_key is the D-IsPrefixKey bit of the node, i.e. whether a
+    // stored key ends on it.
+    pub is_prefix_key: bool,
+    // chain is the chain of the node, which the query must follow before
+    // looking up an edge. It is empty unless the filter was built with path
+    // compression.
+    pub chain: Vec<u8>,
+    // edge is the byte of the edge the query looked up, or None if the query
+    // ended on the node, or diverged from its chain.
+    pub edge: Option<u8>,
+    // has_label is the D-Labels bit of the edge, i.e. whether it exists.
+    pub has_label: bool,
+    // has_child is the D-HasChild bit of the edge. An edge with a label but
+    // no child is a leaf, on which a stored key ends.
+    pub has_child: bool,
+}
+
+// SuffixCheck tells whether, and how, the suffix of a stored key took part in
+// answering a query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuffixCheck {
+    // Skipped means that no suffix was checked, as no suffix bits are stored,
+    // or no stored key came up.
+    Skipped,
+    // Passed means that the suffix did not rule out the stored key.
+    Passed,
+    // Failed means that the suffix ruled out the stored key.
+    Failed,
+}
+
+// Explanation traces how a filter answered a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    // answer is the answer of the filter.
+    pub answer: bool,
+    // path holds the nodes walked from the root, following the queried key,
+    // or the lower bound of range queries. Nodes which a root table leads
+    // past are left out.
+    pub path: Vec<Step>,
+    // matched is the stored truncated key which caused a positive answer.
+    // Any key sharing this prefix is reported to exist, unless its suffix
+    // tells otherwise.
+    pub matched: Option<Vec<u8>>,
+    // suffix tells whether the suffix of the stored key was checked.
+    pub suffix: SuffixCheck,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.path {
+            write!(
+                f,
+                "level {} node {} is_prefix_key={}",
+                step.level, step.node, step.is_prefix_key as u8
+            )?;
+            if !step.chain.is_empty() {
+                write!(f, " chain={:?}", step.chain)?;
+            }
+            match step.edge {
+                Some(edge) => writeln!(
+                    f,
+                    " edge 0x{:02X} label={} has_child={}",
+                    edge, step.has_label as u8, step.has_child as u8
+                )?,
+                None => writeln!(f, " end of query")?,
+            }
+        }
+        match &self.matched {
+            Some(key) => writeln!(f, "matched stored prefix {:?}", key)?,
+            None => writeln!(f, "matched no stored prefix")?,
+        }
+        writeln!(f, "suffix check: {:?}", self.suffix)?;
+        write!(f, "answer: {}", self.answer)
+    }
+}
+
+impl Surf {
+    // explain_get answers get for the given key, and traces how the answer
+    // came about.
+    pub fn explain_get(&self, key: Vec<u8>) -> Result<Explanation, Error> {
+        explain_get(&self.trie(), self.options.transform.apply(key))
+    }
+
+    // explain_range answers range for the given bounds, and traces how the
+    // answer came about.
+    pub fn explain_range(&self, low: Vec<u8>, high: Vec<u8>) -> Result<Explanation, Error> {
+        let transform = &self.options.transform;
+        explain_range(&self.trie(), transform.apply(low), transform.apply(high))
+    }
+}
+
+impl<'a> SurfRef<'a> {
+    // explain_get answers get for the given key, and traces how the answer
+    // came about.
+    pub fn explain_get(&self, key: Vec<u8>) -> Result<Explanation, Error> {
+        explain_get(&self.trie, self.options.transform.apply(key))
+    }
+
+    // explain_range answers range for the given bounds, and traces how the
+    // answer came about.
+    pub fn explain_range(&self, low: Vec<u8>, high: Vec<u8>) -> Result<Explanation, Error> {
+        let transform = &self.options.transform;
+        explain_range(&self.trie, transform.apply(low), transform.apply(high))
+    }
+}
+
+fn explain_get(trie: &Trie, key: Vec<u8>) -> Result<Explanation, Error> {
+    // A byte outside the alphabet is looked up as the width of the alphabet,
+    // which no edge has, and no key holding it is stored.
+    let width = trie.symbols.width();
+    let symbols: Vec<u8> = key
+        .iter()
+        .map(|b| trie.symbols.symbol(*b).unwrap_or(width as u8))
+        .collect();
+    let in_alphabet = symbols.iter().all(|s| (*s as usize) < width);
+
+    let mut visits = Vec::new();
+    let (exists, matched_key, it) = trie.lookup_traced(&symbols, Some(&mut visits))?;
+    let exists = exists && in_alphabet;
+    let answer = exists && trie.suffix_matches(&it, &symbols, &matched_key)?;
+
+    let suffix = match exists && trie.suffixes.width() > 0 {
+        false => SuffixCheck::Skipped,
+        true if answer => SuffixCheck::Passed,
+        true => SuffixCheck::Failed,
+    };
+
+    Ok(Explanation {
+        answer,
+        path: steps(trie, &visits, &key)?,
+        matched: answer.then(|| trie.symbols.decode(matched_key)),
+        suffix,
+    })
+}
+
+fn explain_range(trie: &Trie, low: Vec<u8>, high: Vec<u8>) -> Result<Explanation, Error> {
+    let symbols = trie.symbols.encode_low(low.clone());
+    let high = trie.symbols.encode_high(high);
+
+    let mut visits = Vec::new();
+    let found = trie.lookup_traced(&symbols, Some(&mut visits))?;
+    let low_stored = found.0;
+    let (answer, candidate) = match trie.next_from(&symbols, found) {
+        Ok((key, it)) => (trie.at_most(&key, &it, &high)?, Some(key)),
+        Err(Error::EndOfTrie) => (false, None),
+        Err(e) => return Err(e),
+    };
+
+    // Real suffixes are compared against the lower bound if its truncated key
+    // is stored, and against the upper bound if it shares the truncated key
+    // of the stored key found.
+    let checked = trie.suffixes.real_bits > 0
+        && (low_stored
+            || candidate
+                .as_ref()
+                .is_some_and(|c| *c <= high && high.starts_with(c)));
+    let suffix = match checked {
+        false => SuffixCheck::Skipped,
+        true if answer => SuffixCheck::Passed,
+        true => SuffixCheck::Failed,
+    };
+
+    Ok(Explanation {
+        answer,
+        path: steps(trie, &visits, &low)?,
+        matched: candidate.filter(|_| answer).map(|c| trie.symbols.decode(c)),
+        suffix,
+    })
+}
+
+// steps returns the steps of the given visits of a lookup of the given key,
+// mapped to symbols of the alphabet of the trie. The edges of symbols outside
+// the alphabet are those of the byte of the key looked up in their place.
+fn steps(trie: &Trie, visits: &[Visit], key: &[u8]) -> Result<Vec<Step>, Error> {
+    let mut steps = Vec::with_capacity(visits.len());
+    for visit in visits {
+        let chain = trie.chains.labels(visit.node)?;
+        let offset = visit.depth + chain.len();
+        let byte = |symbol: u8| match (symbol as usize) < trie.symbols.width() {
+            true => trie.symbols.byte(symbol),
+            false => key[offset],
+        };
+
+        let (edge, has_label, has_child) = match visit.edge {
+            Some((symbol, has_label, has_child)) => (Some(byte(symbol)), has_label, has_child),
+            None => (None, false, false),
+        };
+        steps.push(Step {
+            level: visit.level,
+            node: visit.node,
+            is_prefix_key: trie.is_prefix_key.get(visit.node)? == 1,
+            chain: chain.into_iter().map(|s| trie.symbols.byte(s)).collect(),
+            edge,
+            has_label,
+            has_child,
+        });
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::options::Options;
+
+    fn get_keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    fn options(hash_bits: u32, real_bits: u32) -> Options {
+        Options {
+            hash_bits,
+            real_bits,
+            ..Options::new()
+        }
+    }
+
+    #[test]
+    fn test_explain_get() {
+        let surf = Surf::new(get_keys(), options(0, 0)).unwrap();
+
+        // fast is stored as fas, so fase is a false positive.
+        let explanation = surf.explain_get(b"fase".to_vec()).unwrap();
+        assert!(explanation.answer);
+        assert_eq!(explanation.matched, Some(b"fas".to_vec()));
+        assert_eq!(explanation.suffix, SuffixCheck::Skipped);
+
+        let path = &explanation.path;
+        assert_eq!(path.len(), 3);
+        assert_eq!(
+            path[0],
+            Step {
+                level: 0,
+                node: 0,
+                is_prefix_key: false,
+                chain: vec![],
+                edge: Some(b'f'),
+                has_label: true,
+                has_child: true,
+            }
+        );
+        assert!(path[1].is_prefix_key);
+        assert_eq!(path[1].edge, Some(b'a'));
+        assert_eq!(path[2].edge, Some(b's'));
+        assert!(path[2].has_label && !path[2].has_child);
+        assert_eq!(explanation.to_string().lines().last(), Some("answer: true"));
+
+        // f ends on the node its edge leads to.
+        let explanation = surf.explain_get(b"f".to_vec()).unwrap();
+        assert!(explanation.answer);
+        assert_eq!(explanation.path.len(), 2);
+        assert_eq!(explanation.path[1].edge, None);
+        assert!(explanation.path[1].is_prefix_key);
+
+        let explanation = surf.explain_get(b"u".to_vec()).unwrap();
+        assert!(!explanation.answer);
+        assert_eq!(explanation.matched, None);
+        assert!(!explanation.path[0].has_label);
+
+        let surf = Surf::new(get_keys(), options(0, 8)).unwrap();
+        let explanation = surf.explain_get(b"fase".to_vec()).unwrap();
+        assert!(!explanation.answer);
+        assert_eq!(explanation.matched, None);
+        assert_eq!(explanation.suffix, SuffixCheck::Failed);
+
+        let explanation = surf.explain_get(b"fast".to_vec()).unwrap();
+        assert!(explanation.answer);
+        assert_eq!(explanation.suffix, SuffixCheck::Passed);
+    }
+
+    #[test]
+    fn test_explain_range() {
+        let surf = Surf::new(get_keys(), options(0, 0)).unwrap();
+
+        // trie is stored as tr, which lies within tra..=trb.
+        let explanation = surf
+            .explain_range(b"tra".to_vec(), b"trb".to_vec())
+            .unwrap();
+        assert!(explanation.answer);
+        assert_eq!(explanation.matched, Some(b"tr".to_vec()));
+        assert_eq!(explanation.suffix, SuffixCheck::Skipped);
+        let edges: Vec<Option<u8>> = explanation.path.iter().map(|s| s.edge).collect();
+        assert_eq!(edges, vec![Some(b't'), Some(b'r')]);
+
+        let explanation = surf.explain_range(b"u".to_vec(), b"z".to_vec()).unwrap();
+        assert!(!explanation.answer);
+        assert_eq!(explanation.matched, None);
+
+        let surf = Surf::new(get_keys(), options(0, 8)).unwrap();
+        let explanation = surf
+            .explain_range(b"tra".to_vec(), b"trb".to_vec())
+            .unwrap();
+        assert!(!explanation.answer);
+        assert_eq!(explanation.suffix, SuffixCheck::Failed);
+
+        let explanation = surf
+            .explain_range(b"tra".to_vec(), b"trz".to_vec())
+            .unwrap();
+        assert!(explanation.answer);
+        assert_eq!(explanation.matched, Some(b"tr".to_vec()));
+        assert_eq!(explanation.suffix, SuffixCheck::Passed);
+    }
+
+    #[test]
+    fn test_explain_chains() {
+        let keys = vec![
+            b"tenant/a/x1".to_vec(),
+            b"tenant/a/x2".to_vec(),
+            b"tenant/b".to_vec(),
+        ];
+        let options = Options {
+            path_compression: true,
+            ..options(0, 0)
+        };
+        let surf = Surf::new(keys, options).unwrap();
+
+        let explanation = surf.explain_get(b"tenant/a/x2".to_vec()).unwrap();
+        assert!(explanation.answer);
+        let path = &explanation.path;
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[0].chain, b"tenant/".to_vec());
+        assert_eq!(path[0].edge, Some(b'a'));
+        assert_eq!(path[1].level, 1);
+        assert_eq!(path[1].chain, b"/x".to_vec());
+        assert_eq!(path[1].edge, Some(b'2'));
+        assert!(path[1].has_label && !path[1].has_child);
+
+        // The query diverges from the chain of node 1.
+        let explanation = surf.explain_get(b"tenant/a/y".to_vec()).unwrap();
+        assert!(!explanation.answer);
+        assert_eq!(explanation.path.len(), 2);
+        assert_eq!(explanation.path[1].edge, None);
+        assert!(!explanation.path[1].has_label);
+    }
+
+    #[test]
+    fn test_explain_alphabet_and_root_table() {
+        let options = Options {
+            alphabet: Alphabet::Detect,
+            root_table_bytes: 1,
+            ..options(0, 0)
+        };
+        let surf = Surf::new(get_keys(), options).unwrap();
+
+        // The root table leads straight to the node of t.
+        let explanation = surf.explain_get(b"toy".to_vec()).unwrap();
+        assert!(explanation.answer);
+        let edges: Vec<Option<u8>> = explanation.path.iter().map(|s| s.edge).collect();
+        assert_eq!(edges, vec![Some(b'o'), Some(b'y')]);
+        assert_eq!(explanation.path[0].level, 1);
+
+        // z is not part of the alphabet, so no edge has it.
+        let explanation = surf.explain_get(b"toz".to_vec()).unwrap();
+        assert!(!explanation.answer);
+        let last = explanation.path.last().unwrap();
+        assert_eq!(last.edge, Some(b'z'));
+        assert!(!last.has_label);
+
+        let explanation = surf
+            .explain_range(b"tox".to_vec(), b"toz".to_vec())
+            .unwrap();
+        assert_eq!(
+            explanation.answer,
+            surf.range(b"tox".to_vec(), b"toz".to_vec()).unwrap()
+        );
+        assert_eq!(explanation.matched, Some(b"toy".to_vec()));
+    }
+}
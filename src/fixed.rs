@@ -0,0 +1,531 @@
+// Filters specialized for fixed-width integer keys.
+//
+// Keys are stored as their big-endian bytes, just like Surf would store them,
+// but queries take integers and walk the trie without allocating. As no key
+// is a prefix of another, no node is a prefix key, and the path to any leaf
+// is at most WIDTH edges long, so it is kept on the stack. Queries take the
+// same steps through the trie as those of Surf, following its root table,
+// chains and records, and mapping keys to its alphabet.
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::alphabet::SymbolMap;
+use crate::iterator::Error;
+use crate::key::{truncate, Key};
+use crate::options::Options;
+use crate::surf::Surf;
+use crate::transform::KeyTransform;
+use crate::trie::Trie;
+
+// MAX_WIDTH is the width of the widest supported keys.
+const MAX_WIDTH: usize = 8;
+
+// FixedKey is implemented by the unsigned integers a FixedSurf can store.
+pub trait FixedKey: Copy + Ord {
+    // WIDTH is the number of bytes of every key.
+    const WIDTH: usize;
+
+    // bytes returns the big-endian bytes of the key, which are followed by
+    // zeros for keys narrower than MAX_WIDTH.
+    fn bytes(self) -> [u8; MAX_WIDTH];
+}
+
+macro_rules! fixed_key {
+    ($($t:ty),*) => {$(
+        impl FixedKey for $t {
+            const WIDTH: usize = std::mem::size_of::<$t>();
+
+            fn bytes(self) -> [u8; MAX_WIDTH] {
+                ((self as u64) << (64 - <$t>::BITS)).to_be_bytes()
+            }
+        }
+    )*};
+}
+
+fixed_key!(u8, u16, u32, u64);
+
+// FixedSurf is a filter over fixed-width integer keys.
+pub struct FixedSurf<K: FixedKey> {
+    surf: Surf,
+    keys: PhantomData<K>,
+}
+
+pub type SurfU64 = FixedSurf<u64>;
+pub type SurfU32 = FixedSurf<u32>;
+
+impl<K: FixedKey> FixedSurf<K> {
+    // new builds a filter from the given keys. Options::transform must be
+    // the identity, as transformed keys would no longer be of a fixed width.
+    pub fn new(keys: impl IntoIterator<Item = K>, mut options: Options) -> Result<Self, Error> {
+        if options.transform != KeyTransform::Identity {
+            return Err(Error::CustomError(
+                "Fixed-width keys can not be transformed".to_string(),
+            ));
+        }
+
+        let mut keys: Vec<K> = keys.into_iter().collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        // Keys are stored as symbols of their alphabet, which resolve made
+        // sure they hold nothing but.
+        let keys: Vec<Key> = keys
+            .iter()
+            .map(|k| k.bytes()[..K::WIDTH].to_vec())
+            .collect();
+        options.alphabet = options.alphabet.resolve(&keys)?;
+        let symbols = SymbolMap::new(&options.alphabet);
+        let keys: Vec<Key> = keys
+            .into_iter()
+            .map(|k| symbols.encode(k).unwrap())
+            .collect();
+        let truncated = truncate(&keys);
+
+        Ok(FixedSurf {
+            surf: Surf::build(&keys, &truncated, options)?,
+            keys: PhantomData,
+        })
+    }
+
+    // inner returns the underlying filter over the big-endian bytes of the
+    // keys, e.g. to serialize it.
+    pub fn inner(&self) -> &Surf {
+        &self.surf
+    }
+
+    pub fn get(&self, key: K) -> Result<bool, Error> {
+        let trie = self.surf.trie();
+        let mut symbols = Symbols::new();
+        if !trie
+            .symbols
+            .encode_into(&key.bytes()[..K::WIDTH], &mut symbols)
+        {
+            return Ok(false);
+        }
+
+        let Walk::Leaf(cursor) = Cursor::walk(&trie, &symbols)? else {
+            return Ok(false);
+        };
+
+        // The truncated key being stored does not mean that the key itself
+        // is, which its suffix may tell.
+        Ok(trie.suffixes.width() == 0
+            || trie
+                .suffixes
+                .matches(cursor.key_index(&trie)?, &symbols, cursor.len()))
+    }
+
+    // range returns whether a key may lie within [low, high].
+    pub fn range(&self, low: K, high: K) -> Result<bool, Error> {
+        let trie = self.surf.trie();
+        let mut high_symbols = Symbols::new();
+        trie.symbols
+            .encode_high_into(&high.bytes()[..K::WIDTH], &mut high_symbols);
+
+        let Some(cursor) = Cursor::seek(&trie, low)? else {
+            return Ok(false);
+        };
+
+        let matched = cursor.key();
+        if matched > &high_symbols[..] {
+            return Ok(false);
+        }
+
+        // A stored key sharing its truncated key with the upper bound may
+        // still be greater, which its real suffix may tell.
+        Ok(trie.suffixes.real_bits == 0
+            || !high_symbols.starts_with(matched)
+            || trie
+                .suffixes
+                .compare_real(cursor.key_index(&trie)?, &high_symbols, matched.len())
+                != Ordering::Greater)
+    }
+
+    // count returns the number of stored truncated keys within [low, high].
+    pub fn count(&self, low: K, high: K) -> Result<usize, Error> {
+        let trie = self.surf.trie();
+        let mut high_symbols = Symbols::new();
+        trie.symbols
+            .encode_high_into(&high.bytes()[..K::WIDTH], &mut high_symbols);
+
+        let Some(mut cursor) = Cursor::seek(&trie, low)? else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        while cursor.key() <= &high_symbols[..] {
+            count += 1;
+            match cursor.advance(&trie, cursor.level, cursor.label() + 1)? {
+                Some(next) => cursor = next,
+                None => break,
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+// Symbols is a key of symbols, kept on the stack. Mapping an upper bound to
+// symbols may append the width of the alphabet, so it holds one symbol more
+// than the widest keys.
+#[derive(Clone, Copy)]
+struct Symbols {
+    symbols: [u8; MAX_WIDTH + 1],
+    len: usize,
+}
+
+impl Symbols {
+    fn new() -> Symbols {
+        Symbols {
+            symbols: [0; MAX_WIDTH + 1],
+            len: 0,
+        }
+    }
+}
+
+impl Extend<u8> for Symbols {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        for symbol in iter {
+            self.symbols[self.len] = symbol;
+            self.len += 1;
+        }
+    }
+}
+
+impl Deref for Symbols {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.symbols[..self.len]
+    }
+}
+
+// Walk is where Cursor::walk leaves a key.
+enum Walk {
+    // Leaf tells that the key reached the leaf edge the cursor points at, so
+    // its truncated key is stored.
+    Leaf(Cursor),
+    // Next tells that the truncated key is not stored, and that the stored
+    // keys following it are those the cursor advances to from the given
+    // label of the node of the given level.
+    Next(Cursor, usize, usize),
+    // End tells that all stored keys are lesser than the key.
+    End,
+}
+
+// Cursor points at a leaf edge of the trie, along with the path leading to
+// it. Every level of the path takes at least one symbol of the key, so there
+// are at most MAX_WIDTH of them.
+#[derive(Clone, Copy)]
+struct Cursor {
+    // nodes holds the node of each level of the path, and depths the number
+    // of symbols preceding its edge, i.e. those of the edges and chains of
+    // the nodes above and of its own chain.
+    nodes: [usize; MAX_WIDTH],
+    depths: [usize; MAX_WIDTH],
+    // symbols holds the truncated key, as far as the path goes.
+    symbols: [u8; MAX_WIDTH],
+    // level is the level of the node of the leaf edge.
+    level: usize,
+}
+
+impl Cursor {
+    // walk descends the trie along the given key of symbols, as Trie::lookup
+    // does.
+    fn walk(trie: &Trie, key: &[u8]) -> Result<Walk, Error> {
+        // An empty trie has no root node to start from.
+        if trie.labels.is_empty() {
+            return Ok(Walk::End);
+        }
+
+        let mut cursor = Cursor {
+            nodes: [0; MAX_WIDTH],
+            depths: [0; MAX_WIDTH],
+            symbols: [0; MAX_WIDTH],
+            level: 0,
+        };
+
+        // The root table leads straight to the nodes of the first symbols,
+        // while symbols it does not hold take the regular path. Nodes above
+        // those it leads to have no chain.
+        let width = trie.symbols.width();
+        let mut level = 0;
+        while level < trie.root_table.bytes.min(key.len()) {
+            match trie.root_table.child(&key[..=level], width) {
+                Some(child) => {
+                    cursor.depths[level] = level;
+                    cursor.symbols[level] = key[level];
+                    cursor.nodes[level + 1] = child;
+                }
+                None => break,
+            }
+            level += 1;
+        }
+
+        let mut depth = level;
+        loop {
+            cursor.level = level;
+
+            // The key must follow the chain of the node first, and any key
+            // diverging from it lies before or past all keys of the node.
+            let chain = trie.chains.get(cursor.nodes[level])?;
+            cursor.depths[level] = depth + chain.len();
+            for (i, offset) in chain.clone().enumerate() {
+                cursor.symbols[depth + i] = trie.chains.label(offset);
+            }
+            for offset in chain {
+                let label = trie.chains.label(offset);
+                match key.get(depth) {
+                    Some(s) if *s == label => depth += 1,
+                    Some(s) if *s > label => return Ok(cursor.up(level)),
+                    _ => return Ok(Walk::Next(cursor, level, 0)),
+                }
+            }
+
+            // No stored key ends on a node, so a key ending on one lies
+            // before all keys of the node.
+            let Some(symbol) = key.get(depth) else {
+                return Ok(Walk::Next(cursor, level, 0));
+            };
+            cursor.symbols[depth] = *symbol;
+
+            match trie.child(cursor.nodes[level], *symbol as usize) {
+                Ok(child) => {
+                    if level + 1 == MAX_WIDTH {
+                        return Err(Error::NoSuchEdge);
+                    }
+                    cursor.nodes[level + 1] = child;
+                }
+                Err(Error::IsLeaf) => return Ok(Walk::Leaf(cursor)),
+                Err(Error::NoSuchEdge) => {
+                    return Ok(Walk::Next(cursor, level, *symbol as usize + 1))
+                }
+                Err(e) => return Err(e),
+            }
+
+            level += 1;
+            depth += 1;
+        }
+    }
+
+    // seek returns a cursor at the least stored key which is no less than the
+    // given one, as far as its truncated key and real suffix tell, or None if
+    // there is none.
+    fn seek<K: FixedKey>(trie: &Trie, key: K) -> Result<Option<Cursor>, Error> {
+        let mut symbols = Symbols::new();
+        trie.symbols
+            .encode_low_into(&key.bytes()[..K::WIDTH], &mut symbols);
+
+        match Cursor::walk(trie, &symbols)? {
+            Walk::Leaf(cursor) => {
+                // The stored key is only known to be lesser if its real
+                // suffix is.
+                let lesser = trie.suffixes.real_bits > 0
+                    && trie
+                        .suffixes
+                        .compare_real(cursor.key_index(trie)?, &symbols, cursor.len())
+                        == Ordering::Less;
+
+                match lesser {
+                    true => cursor.advance(trie, cursor.level, cursor.label() + 1),
+                    false => Ok(Some(cursor)),
+                }
+            }
+            Walk::Next(cursor, level, from) => cursor.advance(trie, level, from),
+            Walk::End => Ok(None),
+        }
+    }
+
+    // up returns where the keys past all keys of the node of the given level
+    // start.
+    fn up(&self, level: usize) -> Walk {
+        match level {
+            0 => Walk::End,
+            _ => Walk::Next(
+                *self,
+                level - 1,
+                self.symbols[self.depths[level - 1]] as usize + 1,
+            ),
+        }
+    }
+
+    // len returns the length of the truncated key.
+    fn len(&self) -> usize {
+        self.depths[self.level] + 1
+    }
+
+    // key returns the truncated key the cursor points at.
+    fn key(&self) -> &[u8] {
+        &self.symbols[..self.len()]
+    }
+
+    // label returns the label of the leaf edge.
+    fn label(&self) -> usize {
+        self.symbols[self.depths[self.level]] as usize
+    }
+
+    // key_index returns the index of the key stored at the leaf edge.
+    fn key_index(&self, trie: &Trie) -> Result<usize, Error> {
+        trie.leaf_index(self.nodes[self.level], self.label())
+    }
+
+    // advance moves the cursor to the leftmost leaf edge after the labels of
+    // the given level before from, backtracking to upper levels as needed.
+    fn advance(mut self, trie: &Trie, level: usize, from: usize) -> Result<Option<Cursor>, Error> {
+        let mut level = level;
+        let mut from = from;
+
+        loop {
+            let Some(label) = trie.next_label(self.nodes[level], from) else {
+                if level == 0 {
+                    return Ok(None);
+                }
+                level -= 1;
+                from = self.symbols[self.depths[level]] as usize + 1;
+                continue;
+            };
+
+            let depth = self.depths[level];
+            self.symbols[depth] = label as u8;
+            let child = match trie.child(self.nodes[level], label) {
+                Ok(child) => child,
+                Err(Error::IsLeaf) => {
+                    self.level = level;
+                    return Ok(Some(self));
+                }
+                Err(e) => return Err(e),
+            };
+
+            // Descend to the leftmost leaf edge below the label found,
+            // following the chain of the child first.
+            level += 1;
+            if level == MAX_WIDTH {
+                return Err(Error::NoSuchEdge);
+            }
+            let mut depth = depth + 1;
+            for offset in trie.chains.get(child)? {
+                if depth == MAX_WIDTH {
+                    return Err(Error::NoSuchEdge);
+                }
+                self.symbols[depth] = trie.chains.label(offset);
+                depth += 1;
+            }
+            self.nodes[level] = child;
+            self.depths[level] = depth;
+            from = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::layout::DenseLayout;
+    use crate::testing::random;
+
+    fn random_keys(n: usize, mask: u64) -> Vec<u64> {
+        random(n, 0x2545F4914F6CDD1D)
+            .iter()
+            .map(|x| x & mask)
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_byte_keys() {
+        // Masking keeps keys close enough together for range queries to hit
+        // and miss, and the bytes of the alphabet keys within eight symbols,
+        // which queries leave.
+        let (mask, query_mask) = (0x0F0F_0000_00FF_FF00, 0x0F0F_0000_00FF_FFFF);
+        let (symbols_mask, symbols_query_mask) = (0x3131_3131_3131_3131, 0x3333_3333_3333_3333);
+
+        let variants = [
+            (mask, query_mask, Options::new()),
+            (
+                mask,
+                query_mask,
+                Options {
+                    path_compression: true,
+                    ..Options::new()
+                },
+            ),
+            (
+                mask,
+                query_mask,
+                Options {
+                    root_table_bytes: 2,
+                    layout: DenseLayout::Interleaved,
+                    ..Options::new()
+                },
+            ),
+            (
+                symbols_mask,
+                symbols_query_mask,
+                Options {
+                    alphabet: Alphabet::Detect,
+                    path_compression: true,
+                    root_table_bytes: 1,
+                    ..Options::new()
+                },
+            ),
+        ];
+
+        for (mask, query_mask, variant) in variants {
+            let keys = random_keys(500, mask);
+            let queries = random_keys(1_000, query_mask);
+
+            for (hash_bits, real_bits) in [(0, 0), (4, 4), (0, 8), (8, 0)] {
+                let options = Options {
+                    hash_bits,
+                    real_bits,
+                    ..variant.clone()
+                };
+                let fixed = SurfU64::new(keys.iter().copied(), options.clone()).unwrap();
+                let bytes = keys.iter().map(|k| k.to_be_bytes().to_vec()).collect();
+                let surf = Surf::new(bytes, options).unwrap();
+                assert_eq!(fixed.inner().levels, surf.levels);
+
+                for k in &keys {
+                    assert!(fixed.get(*k).unwrap(), "{:#x}", k);
+                }
+                for pair in queries.chunks(2) {
+                    let (low, high) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                    let (l, h) = (low.to_be_bytes().to_vec(), high.to_be_bytes().to_vec());
+
+                    assert_eq!(fixed.get(low).unwrap(), surf.get(l.clone()).unwrap().0);
+                    assert_eq!(fixed.range(low, low), surf.range(l.clone(), l.clone()));
+                    assert_eq!(fixed.range(low, high), surf.range(l.clone(), h.clone()));
+                    assert_eq!(fixed.count(low, high), surf.count(l, h));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_u32() {
+        let surf =
+            SurfU32::new([7u32, 0x0100_0000, 0x0100_0001, u32::MAX], Options::new()).unwrap();
+
+        for k in [7u32, 0x0100_0000, 0x0100_0001, u32::MAX] {
+            assert!(surf.get(k).unwrap());
+        }
+        assert!(!surf.get(0x0200_0000).unwrap());
+        assert_eq!(surf.range(0x0200_0000, 0xFEFF_FFFF), Ok(false));
+        assert_eq!(surf.range(8, 0x0100_0000), Ok(true));
+        assert_eq!(surf.count(0, u32::MAX), Ok(4));
+        assert_eq!(surf.count(0x0100_0001, 0xFEFF_FFFF), Ok(1));
+        assert_eq!(surf.inner().height(), 4);
+
+        let empty = SurfU32::new([], Options::new()).unwrap();
+        assert_eq!(empty.get(7), Ok(false));
+        assert_eq!(empty.range(0, u32::MAX), Ok(false));
+        assert_eq!(empty.count(0, u32::MAX), Ok(0));
+
+        let options = Options {
+            transform: KeyTransform::Prefix(2),
+            ..Options::new()
+        };
+        assert!(SurfU32::new([7u32], options).is_err());
+    }
+}
@@ -0,0 +1,1143 @@
+// Binary serialization of SuRF filters.
+//
+// A serialized filter is laid out as follows. All integers are little-endian,
+// and every field and section starts at an offset which is a multiple of 8
+// bytes.
+//
+//   offset  size  field
+//   0       4     magic, the bytes "SURF"
+//   4       4     format version (u32), currently 1
+//   8       4     Options::r (u32)
+//   12      4     Options::hash_bits (u32)
+//   16      4     Options::real_bits (u32)
+//   20      4     flags (u32), bit 0 being Options::path_compression, bits
+//                 1 and 2 Options::root_table_bytes, bit 3 set for
+//                 DenseLayout::Interleaved, all other bits must be zero
+//   24      8     Options::memory_limit (u64)
+//   32      8     height of the trie, i.e. number of levels (u64)
+//   40      8*h   number of nodes on each level (u64 each)
+//   40+8*h  8     length t of the encoded Options::transform in bytes (u64)
+//   48+8*h  t     Options::transform, zero-padded to a multiple of 8 bytes
+//   ...     32    Options::alphabet, as a bitmap of its bytes (4 u64)
+//   ...     4     CRC-32C of all preceding bytes (u32)
+//   ...     4     reserved, must be zero
+//
+// The transform is recorded so that a loaded filter keeps applying it to
+// queries. Its encoding is a tag byte per transform, followed by the byte
+// count of prefixes, or by the number of transforms of a chain. The bitmap of
+// the alphabet holds a bit per byte, most significant bit first, like all
+// other bitmaps. Nodes hold one D-Labels and D-HasChild bit per byte of the
+// alphabet.
+//
+// The header is followed by thirteen sections, holding the D-Labels,
+// D-HasChild and D-IsPrefixKey bitmaps, each followed by its rank directory,
+// the packed key suffixes, the chains of path compression: the D-HasChain
+// bitmap and its rank directory, the chain end offsets, and the packed chain
+// labels, the packed root table, and finally the records of the interleaved
+// layout. Filters built without path compression have empty chain sections,
+// filters without a root table an empty root table, and filters with the
+// Bitmaps layout no records. Each section consists of:
+//
+//   size  field
+//   8     length of the payload in bytes (u64), a multiple of 8
+//   len   payload, i.e. the words of the section (u64 each)
+//   4     CRC-32C of the length and payload (u32)
+//   4     reserved, must be zero
+//
+// As all sections are 8-byte aligned relative to the start of the filter, a
+// SurfRef can use them in place if the buffer itself is 8-byte aligned. The
+// payload of the records starts with as many zero words as it takes for the
+// records to start at a multiple of 64 bytes, so that they are cache-line
+// aligned in buffers which are, e.g. memory mapped files.
+//
+// Decoding never trusts the input: the checksums are verified, the lengths
+// of all sections must match the number of nodes stated in the header, and
+// the encoded tree must pass Surf::verify before any query can be run
+// against it.
+use std::io::{Read, Write};
+
+use crate::alphabet::SymbolMap;
+use crate::bitmap::{Bitmap, BitmapRef};
+use crate::chain::Chains;
+use crate::checksum::Crc32c;
+use crate::iterator::{Corruption, Error, Section};
+use crate::layout::{self, DenseLayout, Records};
+use crate::options::Options;
+use crate::rank;
+use crate::root_table::{self, RootTable};
+use crate::suffix::{self, Suffixes};
+use crate::surf::{Surf, SurfRef};
+use crate::transform::KeyTransform;
+use crate::trie::Trie;
+use crate::verify;
+
+// MAGIC identifies a serialized SuRF filter.
+pub(crate) const MAGIC: [u8; 4] = *b"SURF";
+
+// VERSION is the version of the format written by Surf::write_to.
+pub(crate) const VERSION: u32 = 1;
+
+// PATH_COMPRESSION is the header flag set for filters built with
+// Options::path_compression.
+const PATH_COMPRESSION: u32 = 1;
+
+// ROOT_TABLE_SHIFT is the offset of Options::root_table_bytes within the
+// header flags.
+const ROOT_TABLE_SHIFT: u32 = 1;
+
+// INTERLEAVED is the header flag set for filters built with
+// DenseLayout::Interleaved.
+const INTERLEAVED: u32 = 1 << 3;
+
+impl Surf {
+    // write_to serializes the filter into the given writer.
+    //
+    // The filter can be loaded again, also by a different process, using
+    // read_from or SurfRef::new.
+    pub fn write_to(&self, mut w: impl Write) -> std::io::Result<()> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&self.options.r.to_le_bytes());
+        header.extend_from_slice(&self.options.hash_bits.to_le_bytes());
+        header.extend_from_slice(&self.options.real_bits.to_le_bytes());
+        let flags = match self.options.path_compression {
+            true => PATH_COMPRESSION,
+            false => 0,
+        } | self.options.root_table_bytes << ROOT_TABLE_SHIFT
+            | match self.options.layout {
+                DenseLayout::Bitmaps => 0,
+                DenseLayout::Interleaved => INTERLEAVED,
+            };
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&(self.options.memory_limit as u64).to_le_bytes());
+
+        header.extend_from_slice(&(self.levels.len() as u64).to_le_bytes());
+        for nodes in &self.levels {
+            header.extend_from_slice(&(*nodes as u64).to_le_bytes());
+        }
+
+        let transform = self.options.transform.encode();
+        header.extend_from_slice(&(transform.len() as u64).to_le_bytes());
+        header.extend_from_slice(&transform);
+        header.resize(header.len().next_multiple_of(8), 0);
+        for word in self.symbols.words() {
+            header.extend_from_slice(&word.to_le_bytes());
+        }
+
+        let mut crc = Crc32c::new();
+        crc.update(&header);
+        w.write_all(&header)?;
+        write_trailer(&mut w, crc)?;
+
+        let mut offset = header.len() + 8;
+        for words in [
+            &self.dense_labels.data,
+            &self.dense_labels_ranks,
+            &self.dense_has_child.data,
+            &self.dense_has_child_ranks,
+            &self.dense_is_prefix_key.data,
+            &self.dense_is_prefix_key_ranks,
+            &self.suffixes,
+            &self.dense_has_chain.data,
+            &self.dense_has_chain_ranks,
+            &self.chain_ends,
+            &self.chain_labels,
+            &self.root_table,
+        ] {
+            write_section(&mut w, words)?;
+            offset += 16 + 8 * words.len();
+        }
+
+        let mut records = vec![0u64; records_padding(offset + 8, self.records.len())];
+        records.extend_from_slice(layout::words(&self.records));
+        write_section(&mut w, &records)?;
+
+        Ok(())
+    }
+
+    // read_from loads a filter which was serialized using write_to, copying
+    // it into memory.
+    pub fn read_from(r: impl Read) -> Result<Surf, Error> {
+        let parts = decode(&mut ReadSource {
+            r,
+            offset: 0,
+            crc: Crc32c::new(),
+        })?;
+
+        Ok(Surf {
+            symbols: SymbolMap::new(&parts.options.alphabet),
+            options: parts.options,
+            levels: parts.levels,
+            dense_labels: Bitmap::from_words(parts.labels),
+            dense_labels_ranks: parts.labels_ranks,
+            dense_has_child: Bitmap::from_words(parts.has_child),
+            dense_has_child_ranks: parts.has_child_ranks,
+            dense_is_prefix_key: Bitmap::from_words(parts.is_prefix_key),
+            dense_is_prefix_key_ranks: parts.is_prefix_key_ranks,
+            dense_has_chain: Bitmap::from_words(parts.has_chain),
+            dense_has_chain_ranks: parts.has_chain_ranks,
+            chain_ends: parts.chain_ends,
+            chain_labels: parts.chain_labels,
+            root_table: parts.root_table,
+            records: layout::lines(&parts.records[parts.records_padding..]),
+            suffixes: parts.suffixes,
+        })
+    }
+}
+
+impl<'a> SurfRef<'a> {
+    // new creates a filter answering queries straight from a buffer holding a
+    // filter serialized using Surf::write_to.
+    //
+    // The buffer must be 8-byte aligned, and 64-byte aligned for records to
+    // be cache-line aligned, which is e.g. the case for memory mapped files.
+    // Zero-copy loading is only supported on little-endian targets.
+    pub fn new(buf: &'a [u8]) -> Result<SurfRef<'a>, Error> {
+        if cfg!(target_endian = "big") {
+            return Err(Error::CustomError(
+                "Zero-copy loading requires a little-endian target".to_string(),
+            ));
+        }
+
+        if !(buf.as_ptr() as usize).is_multiple_of(8) {
+            return Err(Error::UnalignedBuffer);
+        }
+
+        let parts = decode(&mut SliceSource {
+            buf,
+            offset: 0,
+            crc: Crc32c::new(),
+        })?;
+
+        let sections = [
+            parts.labels,
+            parts.labels_ranks,
+            parts.has_child,
+            parts.has_child_ranks,
+            parts.is_prefix_key,
+            parts.is_prefix_key_ranks,
+            parts.suffixes,
+            parts.has_chain,
+            parts.has_chain_ranks,
+            parts.chain_ends,
+            parts.chain_labels,
+            parts.root_table,
+            &parts.records[parts.records_padding..],
+        ];
+        Ok(SurfRef {
+            trie: trie(&parts.options, sections),
+            options: parts.options,
+            levels: parts.levels,
+        })
+    }
+}
+
+// Parts holds the decoded fields of a serialized filter, with W being the
+// representation of its sections.
+struct Parts<W> {
+    options: Options,
+    levels: Vec<usize>,
+    labels: W,
+    labels_ranks: W,
+    has_child: W,
+    has_child_ranks: W,
+    is_prefix_key: W,
+    is_prefix_key_ranks: W,
+    suffixes: W,
+    has_chain: W,
+    has_chain_ranks: W,
+    chain_ends: W,
+    chain_labels: W,
+    root_table: W,
+    // records holds the records, preceded by records_padding zero words.
+    records: W,
+    records_padding: usize,
+}
+
+impl<W: AsRef<[u64]>> Parts<W> {
+    // sections returns the words of all sections, in the order they are
+    // serialized in.
+    fn sections(&self) -> [&[u64]; 13] {
+        [
+            self.labels.as_ref(),
+            self.labels_ranks.as_ref(),
+            self.has_child.as_ref(),
+            self.has_child_ranks.as_ref(),
+            self.is_prefix_key.as_ref(),
+            self.is_prefix_key_ranks.as_ref(),
+            self.suffixes.as_ref(),
+            self.has_chain.as_ref(),
+            self.has_chain_ranks.as_ref(),
+            self.chain_ends.as_ref(),
+            self.chain_labels.as_ref(),
+            self.root_table.as_ref(),
+            &self.records.as_ref()[self.records_padding..],
+        ]
+    }
+}
+
+// trie returns a view of the trie made up of the given sections, as returned
+// by Parts::sections.
+fn trie<'a>(options: &Options, sections: [&'a [u64]; 13]) -> Trie<'a> {
+    Trie {
+        labels: BitmapRef::new(sections[0], sections[1]),
+        has_child: BitmapRef::new(sections[2], sections[3]),
+        is_prefix_key: BitmapRef::new(sections[4], sections[5]),
+        suffixes: Suffixes {
+            words: sections[6],
+            hash_bits: options.hash_bits,
+            real_bits: options.real_bits,
+            symbol_bits: suffix::symbol_bits(SymbolMap::new(&options.alphabet).width()),
+        },
+        symbols: SymbolMap::new(&options.alphabet),
+        chains: Chains {
+            has_chain: BitmapRef::new(sections[7], sections[8]),
+            ends: sections[9],
+            labels: sections[10],
+        },
+        root_table: RootTable {
+            bytes: options.root_table_bytes as usize,
+            words: sections[11],
+        },
+        records: Records::new(
+            options.layout,
+            SymbolMap::new(&options.alphabet).width(),
+            sections[12],
+        ),
+    }
+}
+
+// Source is what a serialized filter is decoded from.
+//
+// Readers copy the sections into owned words, while byte slices hand out
+// the sections in place. Both keep a checksum of the bytes read.
+trait Source {
+    type Words: AsRef<[u64]>;
+
+    fn read_u32(&mut self) -> Result<u32, Error>;
+    fn read_u64(&mut self) -> Result<u64, Error>;
+    fn read_words(&mut self, count: usize) -> Result<Self::Words, Error>;
+
+    // offset returns the number of bytes read so far.
+    fn offset(&self) -> usize;
+
+    // checksum returns the checksum of all bytes read since the previous
+    // call to checksum.
+    fn checksum(&mut self) -> u32;
+}
+
+struct ReadSource<R> {
+    r: R,
+    offset: usize,
+    crc: Crc32c,
+}
+
+impl<R: Read> ReadSource<R> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0u8; N];
+        self.r.read_exact(&mut buf).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                Error::Corrupted(Corruption::Truncated)
+            } else {
+                Error::from(e)
+            }
+        })?;
+
+        self.offset += N;
+        self.crc.update(&buf);
+        Ok(buf)
+    }
+}
+
+impl<R: Read> Source for ReadSource<R> {
+    type Words = Vec<u64>;
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_words(&mut self, count: usize) -> Result<Vec<u64>, Error> {
+        // The count is not trusted to pre-allocate, the words are collected
+        // as they are read instead.
+        let mut words = Vec::new();
+        for _ in 0..count {
+            words.push(self.read_u64()?);
+        }
+
+        Ok(words)
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn checksum(&mut self) -> u32 {
+        let sum = self.crc.finish();
+        self.crc = Crc32c::new();
+        sum
+    }
+}
+
+struct SliceSource<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    crc: Crc32c,
+}
+
+impl<'a> SliceSource<'a> {
+    // take returns the next n bytes of the buffer.
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .offset
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or(Error::Corrupted(Corruption::Truncated))?;
+
+        let bytes = &self.buf[self.offset..end];
+        self.offset = end;
+        self.crc.update(bytes);
+        Ok(bytes)
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(self.take(N)?);
+        Ok(buf)
+    }
+}
+
+impl<'a> Source for SliceSource<'a> {
+    type Words = &'a [u64];
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_words(&mut self, count: usize) -> Result<&'a [u64], Error> {
+        let n = count
+            .checked_mul(8)
+            .ok_or(Error::Corrupted(Corruption::Truncated))?;
+        let bytes = self.take(n)?;
+
+        // SAFETY: Any bit pattern is a valid u64, and align_to only hands out
+        // the part of the slice which is correctly aligned.
+        let (prefix, words, suffix) = unsafe { bytes.align_to::<u64>() };
+        if !prefix.is_empty() || !suffix.is_empty() {
+            return Err(Error::UnalignedBuffer);
+        }
+
+        Ok(words)
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn checksum(&mut self) -> u32 {
+        let sum = self.crc.finish();
+        self.crc = Crc32c::new();
+        sum
+    }
+}
+
+// decode decodes a serialized filter from the given source, and checks that
+// it is well-formed.
+fn decode<S: Source>(source: &mut S) -> Result<Parts<S::Words>, Error> {
+    let magic = source.read_u32()?;
+    if magic.to_le_bytes() != MAGIC {
+        return Err(Error::BadMagic);
+    }
+
+    let version = source.read_u32()?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let r = source.read_u32()?;
+    let hash_bits = source.read_u32()?;
+    let real_bits = source.read_u32()?;
+    let flags = source.read_u32()?;
+    let memory_limit = source.read_u64()?;
+
+    let height = source.read_u64()?;
+    let mut levels = Vec::new();
+    for _ in 0..height {
+        let nodes = source.read_u64()?;
+        levels.push(usize::try_from(nodes).map_err(|_| Corruption::NodeCountOverflow)?);
+    }
+
+    let transform_length = source.read_u64()?;
+    let transform_words =
+        usize::try_from(transform_length.div_ceil(8)).map_err(|_| Corruption::Transform)?;
+    let transform_words = source.read_words(transform_words)?;
+    let mut alphabet = [0u64; 4];
+    for word in &mut alphabet {
+        *word = source.read_u64()?;
+    }
+
+    read_trailer(source, Section::Header)?;
+    let root_table_bytes = flags >> ROOT_TABLE_SHIFT & 3;
+    if root_table_bytes > 2 || flags >> 4 != 0 {
+        return Err(Corruption::ReservedField {
+            section: Section::Header,
+        }
+        .into());
+    }
+    let width = hash_bits
+        .checked_add(real_bits)
+        .filter(|w| *w <= suffix::MAX_BITS)
+        .ok_or(Corruption::SuffixWidth {
+            hash_bits,
+            real_bits,
+        })?;
+
+    // The transform is decoded once the header is known to be intact, so
+    // that corruption is reported as a checksum mismatch rather than as a
+    // malformed transform. Padding must be zero as well.
+    let transform_bytes: Vec<u8> = transform_words
+        .as_ref()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect();
+    let (transform, padding) = transform_bytes.split_at(transform_length as usize);
+    let transform = KeyTransform::decode(transform)
+        .filter(|_| padding.iter().all(|b| *b == 0))
+        .ok_or(Corruption::Transform)?;
+    let symbols = SymbolMap::from_words(alphabet);
+    if symbols.width() == 0 {
+        return Err(Corruption::Alphabet.into());
+    }
+
+    let options = Options {
+        r,
+        hash_bits,
+        real_bits,
+        memory_limit: usize::try_from(memory_limit).unwrap_or(usize::MAX),
+        transform,
+        alphabet: symbols.alphabet(),
+        path_compression: flags & PATH_COMPRESSION != 0,
+        root_table_bytes,
+        layout: match flags & INTERLEAVED {
+            0 => DenseLayout::Bitmaps,
+            _ => DenseLayout::Interleaved,
+        },
+    };
+
+    // The header states the number of nodes, which determines the length of
+    // every section.
+    let nodes = levels
+        .iter()
+        .try_fold(0usize, |sum, n| sum.checked_add(*n))
+        .filter(|n| n.checked_mul(256).is_some())
+        .ok_or(Corruption::NodeCountOverflow)?;
+    let bitmap_words = (symbols.width() * nodes).div_ceil(64);
+
+    let directory_words = bitmap_words.div_ceil(rank::BLOCK_WORDS);
+    let prefix_key_words = nodes.div_ceil(64);
+
+    let labels = read_section(source, Section::Labels, bitmap_words)?;
+    let labels_ranks = read_section(source, Section::LabelsRanks, directory_words)?;
+    let has_child = read_section(source, Section::HasChild, bitmap_words)?;
+    let has_child_ranks = read_section(source, Section::HasChildRanks, directory_words)?;
+    let is_prefix_key = read_section(source, Section::IsPrefixKey, prefix_key_words)?;
+    let is_prefix_key_ranks = read_section(
+        source,
+        Section::IsPrefixKeyRanks,
+        prefix_key_words.div_ceil(rank::BLOCK_WORDS),
+    )?;
+
+    // The bitmaps state the number of keys, which determines the length of
+    // the suffixes.
+    let keys = verify::stored_keys(labels.as_ref(), has_child.as_ref(), is_prefix_key.as_ref());
+    let suffixes = read_section(source, Section::Suffixes, suffix::words(keys, width))?;
+
+    // Filters built with path compression have a D-HasChain bit per node,
+    // which states the number of chains, the last end of which states the
+    // number of chain labels.
+    let has_chain_words = match options.path_compression {
+        true => prefix_key_words,
+        false => 0,
+    };
+    let has_chain = read_section(source, Section::HasChain, has_chain_words)?;
+    let has_chain_ranks = read_section(
+        source,
+        Section::HasChainRanks,
+        has_chain_words.div_ceil(rank::BLOCK_WORDS),
+    )?;
+    let chains = has_chain
+        .as_ref()
+        .iter()
+        .map(|w| w.count_ones() as usize)
+        .sum();
+    let chain_ends = read_section(source, Section::ChainEnds, chains)?;
+    let chain_label_words = chain_ends
+        .as_ref()
+        .last()
+        .map_or(Ok(0), |end| usize::try_from(end.div_ceil(8)))
+        .map_err(|_| Corruption::Truncated)?;
+    let chain_labels = read_section(source, Section::ChainLabels, chain_label_words)?;
+    let root_table_words =
+        root_table::encoded_bytes(symbols.width(), root_table_bytes as usize) / 8;
+    let root_table = read_section(source, Section::RootTable, root_table_words)?;
+
+    // Records are preceded by the zero words aligning them to cache lines.
+    let record_words = layout::encoded_bytes(options.layout, nodes, symbols.width()) / 8;
+    let records_padding = records_padding(source.offset() + 8, record_words);
+    let records = read_section(source, Section::Records, records_padding + record_words)?;
+    if records.as_ref()[..records_padding].iter().any(|w| *w != 0) {
+        return Err(Corruption::ReservedField {
+            section: Section::Records,
+        }
+        .into());
+    }
+
+    let parts = Parts {
+        options,
+        levels,
+        labels,
+        labels_ranks,
+        has_child,
+        has_child_ranks,
+        is_prefix_key,
+        is_prefix_key_ranks,
+        suffixes,
+        has_chain,
+        has_chain_ranks,
+        chain_ends,
+        chain_labels,
+        root_table,
+        records,
+        records_padding,
+    };
+    verify::check(&trie(&parts.options, parts.sections()), &parts.levels)
+        .map_err(Corruption::Structure)?;
+
+    Ok(parts)
+}
+
+// records_padding returns the number of zero words preceding the given
+// number of record words, whose payload starts at the given offset, to align
+// them to cache lines.
+fn records_padding(offset: usize, words: usize) -> usize {
+    match words {
+        0 => 0,
+        _ => (offset.next_multiple_of(64) - offset) / 8,
+    }
+}
+
+// write_trailer writes the checksum and reserved field ending the header and
+// every section.
+fn write_trailer(w: &mut impl Write, crc: Crc32c) -> std::io::Result<()> {
+    w.write_all(&crc.finish().to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())
+}
+
+// read_trailer reads the trailer written by write_trailer, and checks it
+// against the checksum of the bytes read since the previous trailer.
+fn read_trailer<S: Source>(source: &mut S, section: Section) -> Result<(), Error> {
+    let computed = source.checksum();
+    let stored = source.read_u32()?;
+    let reserved = source.read_u32()?;
+    source.checksum();
+
+    if stored != computed {
+        return Err(Corruption::ChecksumMismatch {
+            section,
+            stored,
+            computed,
+        }
+        .into());
+    }
+    if reserved != 0 {
+        return Err(Corruption::ReservedField { section }.into());
+    }
+
+    Ok(())
+}
+
+// write_section writes the given words, prefixed by their length in bytes and
+// followed by the section trailer.
+fn write_section(w: &mut impl Write, words: &[u64]) -> std::io::Result<()> {
+    let mut crc = Crc32c::new();
+
+    let length = ((words.len() * 8) as u64).to_le_bytes();
+    crc.update(&length);
+    w.write_all(&length)?;
+
+    for word in words {
+        let bytes = word.to_le_bytes();
+        crc.update(&bytes);
+        w.write_all(&bytes)?;
+    }
+
+    write_trailer(w, crc)
+}
+
+// read_section reads a section written by write_section, which must hold
+// exactly the given number of words.
+fn read_section<S: Source>(
+    source: &mut S,
+    section: Section,
+    words: usize,
+) -> Result<S::Words, Error> {
+    let expected = words as u64 * 8;
+    let length = source.read_u64()?;
+    if length != expected {
+        return Err(Corruption::SectionLength {
+            section,
+            expected,
+            actual: length,
+        }
+        .into());
+    }
+
+    let data = source.read_words(words)?;
+    read_trailer(source, section)?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::verify::{Invariant, Violation};
+
+    fn get_keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let options = Options {
+            r: 32,
+            hash_bits: 2,
+            real_bits: 6,
+            memory_limit: 1_000_000,
+            transform: KeyTransform::Chain(vec![KeyTransform::CaseFold, KeyTransform::Prefix(3)]),
+            alphabet: Alphabet::Bytes,
+            path_compression: false,
+            root_table_bytes: 0,
+            layout: DenseLayout::Bitmaps,
+        };
+        let surf = Surf::new(get_keys(), options.clone()).unwrap();
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        assert_eq!(&buf[..4], b"SURF");
+        assert_eq!(buf.len() % 8, 0);
+
+        let loaded = Surf::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.options, options);
+        assert_eq!(loaded.levels, surf.levels);
+        assert_eq!(loaded.dense_labels.data, surf.dense_labels.data);
+        assert_eq!(loaded.dense_has_child.data, surf.dense_has_child.data);
+        assert_eq!(
+            loaded.dense_is_prefix_key.data,
+            surf.dense_is_prefix_key.data
+        );
+
+        for k in get_keys() {
+            assert!(loaded.get(k).unwrap().0);
+        }
+        assert!(!loaded.get(b"fo".to_vec()).unwrap().0);
+
+        // The loaded filter keeps applying the transform.
+        assert!(loaded.get(b"FASTER".to_vec()).unwrap().0);
+        assert_eq!(loaded.check_transform(&options.transform), Ok(()));
+        assert_eq!(
+            loaded.check_transform(&KeyTransform::Identity),
+            Err(Error::TransformMismatch {
+                stored: options.transform.clone(),
+                expected: KeyTransform::Identity,
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip_alphabet() {
+        let keys = vec![b"c0ffee".to_vec(), b"deadbeef".to_vec(), b"f00d".to_vec()];
+        let options = Options {
+            alphabet: Alphabet::Detect,
+            ..Options::new()
+        };
+        let surf = Surf::new(keys.clone(), options).unwrap();
+        assert_eq!(
+            surf.options.alphabet,
+            Alphabet::Symbols(b"0abcdef".to_vec())
+        );
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        let loaded = Surf::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.dense_labels.data, surf.dense_labels.data);
+
+        let words = aligned(&buf);
+        let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+        for k in keys {
+            assert!(loaded.get(k.clone()).unwrap().0);
+            assert!(surf_ref.get(k).unwrap().0);
+        }
+        assert!(!surf_ref.get(b"beef".to_vec()).unwrap().0);
+        assert_eq!(
+            surf_ref.get_or_next(b"d".to_vec()).unwrap().0,
+            b"d".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_round_trip_path_compression() {
+        let keys = vec![
+            b"tenant/00001/a".to_vec(),
+            b"tenant/00001/b".to_vec(),
+            b"tenant/00002".to_vec(),
+        ];
+        let options = Options {
+            path_compression: true,
+            ..Options::new()
+        };
+        let surf = Surf::new(keys.clone(), options).unwrap();
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        assert_eq!(buf[20], 1);
+        let loaded = Surf::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.dense_has_chain.data, surf.dense_has_chain.data);
+        assert_eq!(loaded.chain_ends, surf.chain_ends);
+        assert_eq!(loaded.chain_labels, surf.chain_labels);
+
+        let words = aligned(&buf);
+        let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+        for k in keys {
+            assert!(loaded.get(k.clone()).unwrap().0);
+            assert!(surf_ref.get(k).unwrap().0);
+        }
+        assert!(!surf_ref.get(b"tenant/00003".to_vec()).unwrap().0);
+        assert_eq!(
+            surf_ref.get_or_next(b"tenant/00001/c".to_vec()).unwrap().0,
+            b"tenant/00002".to_vec()
+        );
+
+        // Unknown flags are rejected.
+        let mut header = buf.clone();
+        header[20] = 7;
+        let end = 48 + 8 * surf.height() + 8 + 32;
+        let mut crc = Crc32c::new();
+        crc.update(&header[..end]);
+        header[end..end + 4].copy_from_slice(&crc.finish().to_le_bytes());
+        assert_eq!(
+            Surf::read_from(header.as_slice()).err(),
+            Some(Error::Corrupted(Corruption::ReservedField {
+                section: Section::Header,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_round_trip_root_table() {
+        let options = Options {
+            root_table_bytes: 2,
+            path_compression: true,
+            ..Options::new()
+        };
+        let surf = Surf::new(get_keys(), options).unwrap();
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        assert_eq!(buf[20], 5);
+        let loaded = Surf::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.root_table, surf.root_table);
+
+        let words = aligned(&buf);
+        let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+        assert_eq!(surf_ref.stats().memory, surf.stats().memory);
+        for k in get_keys() {
+            assert!(surf_ref.get(k).unwrap().0);
+        }
+        assert!(!surf_ref.get(b"fo".to_vec()).unwrap().0);
+    }
+
+    #[test]
+    fn test_round_trip_interleaved() {
+        let options = Options {
+            layout: DenseLayout::Interleaved,
+            path_compression: true,
+            ..Options::new()
+        };
+        let surf = Surf::new(get_keys(), options).unwrap();
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        assert_eq!(buf[20], 9);
+        let loaded = Surf::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.records, surf.records);
+
+        // Records start at a multiple of 64 bytes within the buffer.
+        let words = aligned(&buf);
+        let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+        let records = surf_ref.trie.records.words;
+        assert_eq!(
+            (records.as_ptr() as usize - words.as_ptr() as usize) % 64,
+            0
+        );
+        assert_eq!(records, layout::words(&surf.records));
+        for k in get_keys() {
+            assert!(surf_ref.get(k).unwrap().0);
+        }
+        assert!(!surf_ref.get(b"fo".to_vec()).unwrap().0);
+
+        // The padding preceding the records must be zero.
+        let offset = records.as_ptr() as usize - words.as_ptr() as usize;
+        let end = offset + 8 * records.len();
+        let padding = (1..8)
+            .find(|p| {
+                let length = &buf[offset - 8 * p - 8..offset - 8 * p];
+                u64::from_le_bytes(length.try_into().unwrap()) as usize == end - offset + 8 * p
+            })
+            .unwrap();
+        let mut padded = buf.clone();
+        padded[offset - 8] = 1;
+        let mut crc = Crc32c::new();
+        crc.update(&padded[offset - 8 * padding - 8..end]);
+        padded[end..end + 4].copy_from_slice(&crc.finish().to_le_bytes());
+        assert_eq!(
+            Surf::read_from(padded.as_slice()).err(),
+            Some(Error::Corrupted(Corruption::ReservedField {
+                section: Section::Records,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_round_trip_empty_key() {
+        // A filter of only the empty key has a root node the key ends on.
+        for path_compression in [false, true] {
+            for layout in [DenseLayout::Bitmaps, DenseLayout::Interleaved] {
+                let options = Options {
+                    path_compression,
+                    layout,
+                    root_table_bytes: 1,
+                    ..Options::new()
+                };
+                let surf = Surf::new(vec![vec![]], options).unwrap();
+                assert_eq!(surf.levels, vec![1]);
+                assert_eq!(surf.verify(), Ok(()));
+
+                let mut buf = Vec::new();
+                surf.write_to(&mut buf).unwrap();
+                let loaded = Surf::read_from(buf.as_slice()).unwrap();
+                assert_eq!(loaded.verify(), Ok(()));
+                let words = aligned(&buf);
+                let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+                assert_eq!(surf_ref.verify(), Ok(()));
+
+                for (get, range, count) in [
+                    (
+                        surf.get(vec![]),
+                        surf.range(vec![], vec![0]),
+                        surf.count(vec![], vec![0]),
+                    ),
+                    (
+                        loaded.get(vec![]),
+                        loaded.range(vec![], vec![0]),
+                        loaded.count(vec![], vec![0]),
+                    ),
+                    (
+                        surf_ref.get(vec![]),
+                        surf_ref.range(vec![], vec![0]),
+                        surf_ref.count(vec![], vec![0]),
+                    ),
+                ] {
+                    assert!(get.unwrap().0);
+                    assert_eq!(range, Ok(true));
+                    assert_eq!(count, Ok(1));
+                }
+                assert!(!surf_ref.get(b"a".to_vec()).unwrap().0);
+                assert_eq!(surf_ref.range(b"a".to_vec(), b"z".to_vec()), Ok(false));
+            }
+        }
+    }
+
+    // aligned copies the given bytes into an 8-byte aligned buffer.
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        let mut words = vec![0u64; bytes.len().div_ceil(8)];
+        for (i, b) in bytes.iter().enumerate() {
+            words[i / 8] |= (*b as u64) << (8 * (i % 8));
+        }
+        words
+    }
+
+    fn as_bytes(words: &[u64]) -> &[u8] {
+        // SAFETY: u8 has no alignment requirements and any bit pattern is
+        // valid.
+        unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) }
+    }
+
+    #[test]
+    fn test_surf_ref() {
+        let surf = Surf::new(get_keys(), Options::new()).unwrap();
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+
+        let words = aligned(&buf);
+        let surf_ref = SurfRef::new(as_bytes(&words)).unwrap();
+        assert_eq!(surf_ref.options, surf.options);
+        assert_eq!(surf_ref.levels, surf.levels);
+
+        for k in get_keys() {
+            assert!(surf_ref.get(k).unwrap().0);
+        }
+        for k in [b"fo".to_vec(), b"a".to_vec(), b"tr".to_vec()] {
+            assert_eq!(surf_ref.get(k.clone()).unwrap().0, surf.get(k).unwrap().0);
+        }
+
+        assert_eq!(
+            SurfRef::new(&as_bytes(&words)[1..]).err(),
+            Some(Error::UnalignedBuffer)
+        );
+        assert_eq!(
+            SurfRef::new(&as_bytes(&words)[..buf.len() - 8]).err(),
+            Some(Error::Corrupted(Corruption::Truncated))
+        );
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let surf = Surf::new(get_keys(), Options::new()).unwrap();
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+
+        let mut bad_magic = buf.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            Surf::read_from(bad_magic.as_slice()).err(),
+            Some(Error::BadMagic)
+        );
+
+        let mut bad_version = buf.clone();
+        bad_version[4] = 0xFF;
+        assert_eq!(
+            Surf::read_from(bad_version.as_slice()).err(),
+            Some(Error::UnsupportedVersion(0xFF))
+        );
+
+        let truncated = &buf[..buf.len() - 1];
+        assert_eq!(
+            Surf::read_from(truncated).err(),
+            Some(Error::Corrupted(Corruption::Truncated))
+        );
+    }
+
+    #[test]
+    fn test_detect_corruption() {
+        let surf = Surf::new(get_keys(), Options::new()).unwrap();
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+
+        // Every single flipped bit is detected, be it through the checksums
+        // or through the magic and version, which are checked first.
+        for bit in 0..buf.len() * 8 {
+            let mut corrupted = buf.clone();
+            corrupted[bit / 8] ^= 0x80 >> (bit % 8);
+
+            assert!(
+                Surf::read_from(corrupted.as_slice()).is_err(),
+                "bit {}",
+                bit
+            );
+            let words = aligned(&corrupted);
+            assert!(SurfRef::new(as_bytes(&words)).is_err(), "bit {}", bit);
+        }
+
+        for n in 0..buf.len() {
+            assert!(Surf::read_from(&buf[..n]).is_err(), "length {}", n);
+            let words = aligned(&buf[..n]);
+            assert!(
+                SurfRef::new(&as_bytes(&words)[..n]).is_err(),
+                "length {}",
+                n
+            );
+        }
+    }
+
+    // rewrite serializes a filter after letting the given function tamper
+    // with it, keeping all checksums valid.
+    fn rewrite(f: impl FnOnce(&mut Surf)) -> Vec<u8> {
+        let mut surf = Surf::new(get_keys(), Options::new()).unwrap();
+        f(&mut surf);
+
+        let mut buf = Vec::new();
+        surf.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_detect_invalid_structure() {
+        let decode = |buf: Vec<u8>| Surf::read_from(buf.as_slice()).err();
+
+        // An edge to a child node which has no label.
+        let buf = rewrite(|s| s.dense_has_child.data[0] |= 1);
+        assert_eq!(
+            decode(buf),
+            Some(Error::Corrupted(Corruption::Structure(Violation {
+                invariant: Invariant::ChildHasLabel,
+                level: 0,
+                node: 0,
+                bit: 63,
+            })))
+        );
+
+        // A transform which does not exist, and an empty alphabet, behind a
+        // valid header checksum.
+        let transform = 48 + 8 * Surf::new(get_keys(), Options::new()).unwrap().height();
+        let end = transform + 8 + 32;
+        let with_header = |f: &dyn Fn(&mut [u8])| {
+            let mut buf = rewrite(|_| {});
+            f(&mut buf);
+            let mut crc = Crc32c::new();
+            crc.update(&buf[..end]);
+            buf[end..end + 4].copy_from_slice(&crc.finish().to_le_bytes());
+            buf
+        };
+        assert_eq!(
+            decode(with_header(&|buf| buf[transform] = 0xFF)),
+            Some(Error::Corrupted(Corruption::Transform))
+        );
+        assert_eq!(
+            decode(with_header(&|buf| buf[transform + 8..end].fill(0))),
+            Some(Error::Corrupted(Corruption::Alphabet))
+        );
+
+        // More levels than there are nodes in the bitmaps.
+        let buf = rewrite(|s| s.levels.push(1));
+        assert_eq!(
+            decode(buf),
+            Some(Error::Corrupted(Corruption::SectionLength {
+                section: Section::Labels,
+                expected: 8 * 4 * 6,
+                actual: 8 * 4 * 5,
+            }))
+        );
+    }
+}
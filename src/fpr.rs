@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use crate::iterator::Error;
+use crate::key::Key;
+use crate::surf::Surf;
+
+// Query is a query of a workload, as answered by a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    // Point asks whether the given key exists.
+    Point(Vec<u8>),
+    // Range asks whether any key lies within [low, high].
+    Range(Vec<u8>, Vec<u8>),
+}
+
+// Counts tallies the answers of a filter to a set of queries.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Counts {
+    // queries is the number of queries asked.
+    pub queries: usize,
+    // negatives is the number of queries whose true answer is no.
+    pub negatives: usize,
+    // false_positives is the number of negatives the filter answered yes to.
+    pub false_positives: usize,
+    // false_negatives is the number of positives the filter answered no to.
+    // A filter never gives false negatives, so this is always zero unless the
+    // filter was not built from the given keys.
+    pub false_negatives: usize,
+}
+
+impl Counts {
+    // fpr returns the false-positive rate, i.e. the fraction of negatives the
+    // filter answered yes to.
+    pub fn fpr(&self) -> f64 {
+        if self.negatives == 0 {
+            return 0.0;
+        }
+
+        self.false_positives as f64 / self.negatives as f64
+    }
+
+    fn add(&mut self, truth: bool, answer: bool) {
+        self.queries += 1;
+        if !truth {
+            self.negatives += 1;
+        }
+        if answer && !truth {
+            self.false_positives += 1;
+        }
+        if truth && !answer {
+            self.false_negatives += 1;
+        }
+    }
+}
+
+// Report breaks down the answers of a filter to a workload.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    // all holds the counts of all queries.
+    pub all: Counts,
+    // point holds the counts of point queries.
+    pub point: Counts,
+    // range holds the counts of range queries.
+    pub range: Counts,
+    // range_widths holds the counts of range queries by their width.
+    //
+    // The width of a range is the difference of its bounds, taking their first
+    // eight bytes as a big-endian number. Ranges are grouped by the number of
+    // bits of their width, so that the entry for b holds ranges whose width
+    // lies within [2^(b-1), 2^b). Ranges whose bounds share their first eight
+    // bytes have a width of zero.
+    pub range_widths: BTreeMap<u32, Counts>,
+}
+
+// analyze asks the given filter all queries of the workload, and compares its
+// answers against the keys it was built from.
+//
+// The false-positive rates are exact for the given workload, which allows
+// choosing options from the data at hand rather than from assumptions on how
+// keys and queries are distributed. The filter answers for keys as changed by
+// Options::transform, so the true answers are those for the transformed keys
+// and queries.
+pub fn analyze(keys: &[Vec<u8>], surf: &Surf, queries: &[Query]) -> Result<Report, Error> {
+    let transform = &surf.options().transform;
+    let mut keys: Vec<Key> = keys.iter().map(|k| transform.apply(k.clone())).collect();
+    keys.sort();
+    keys.dedup();
+
+    evaluate(&keys, surf, queries)
+}
+
+// evaluate is analyze for keys which are already transformed, sorted and
+// deduplicated.
+pub(crate) fn evaluate(keys: &[Key], surf: &Surf, queries: &[Query]) -> Result<Report, Error> {
+    let transform = &surf.options().transform;
+    let mut report = Report::default();
+    for query in queries {
+        match query {
+            Query::Point(key) => {
+                let truth = keys.binary_search(&transform.apply(key.clone())).is_ok();
+                let (answer, _, _) = surf.get(key.clone())?;
+
+                report.all.add(truth, answer);
+                report.point.add(truth, answer);
+            }
+            Query::Range(low, high) => {
+                let (transformed_low, transformed_high) =
+                    (transform.apply(low.clone()), transform.apply(high.clone()));
+                let first = keys.partition_point(|k| *k < transformed_low);
+                let truth = first < keys.len() && keys[first] <= transformed_high;
+                let answer = surf.range(low.clone(), high.clone())?;
+
+                report.all.add(truth, answer);
+                report.range.add(truth, answer);
+                report
+                    .range_widths
+                    .entry(width_bits(low, high))
+                    .or_default()
+                    .add(truth, answer);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// width_bits returns the number of bits of the width of the range [low, high].
+fn width_bits(low: &[u8], high: &[u8]) -> u32 {
+    let width = prefix_number(high).saturating_sub(prefix_number(low));
+    64 - width.leading_zeros()
+}
+
+// prefix_number returns the first eight bytes of the given key as a
+// big-endian number, padding shorter keys with zeroes.
+fn prefix_number(key: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    let n = key.len().min(8);
+    bytes[..n].copy_from_slice(&key[..n]);
+    u64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+    use crate::transform::KeyTransform;
+
+    #[test]
+    fn test_analyze() {
+        let keys = vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ];
+        let options = Options {
+            hash_bits: 0,
+            real_bits: 0,
+            ..Options::new()
+        };
+        let surf = Surf::new(keys.clone(), options).unwrap();
+
+        let queries = vec![
+            Query::Point(b"far".to_vec()),
+            // Truncated to fas and tr, so these are false positives.
+            Query::Point(b"fase".to_vec()),
+            Query::Point(b"tree".to_vec()),
+            Query::Point(b"tea".to_vec()),
+            Query::Range(b"fa".to_vec(), b"fb".to_vec()),
+            // Only the truncated key tr lies within these.
+            Query::Range(b"tr".to_vec(), b"trie".to_vec()),
+            Query::Range(b"tra".to_vec(), b"trb".to_vec()),
+            Query::Range(b"u".to_vec(), b"z".to_vec()),
+        ];
+        let report = analyze(&keys, &surf, &queries).unwrap();
+
+        assert_eq!(
+            report.point,
+            Counts {
+                queries: 4,
+                negatives: 3,
+                false_positives: 2,
+                false_negatives: 0,
+            }
+        );
+        assert_eq!(
+            report.range,
+            Counts {
+                queries: 4,
+                negatives: 2,
+                false_positives: 1,
+                false_negatives: 0,
+            }
+        );
+        assert_eq!(report.all.queries, 8);
+        assert_eq!(report.all.fpr(), 3.0 / 5.0);
+
+        // tra..=trb has a width of 1 << 40, u..=z one of 5 << 56.
+        assert_eq!(report.range_widths[&41].false_positives, 1);
+        assert_eq!(report.range_widths[&59].negatives, 1);
+        assert_eq!(report.range_widths[&59].fpr(), 0.0);
+    }
+
+    #[test]
+    fn test_analyze_transform() {
+        let keys = vec![b"Far".to_vec(), b"TOY".to_vec()];
+        let options = Options {
+            transform: KeyTransform::CaseFold,
+            ..Options::new()
+        };
+        let surf = Surf::new(keys.clone(), options).unwrap();
+
+        // Both queries match a key once case folded, as the filter reports.
+        let queries = vec![
+            Query::Point(b"far".to_vec()),
+            Query::Range(b"tox".to_vec(), b"toz".to_vec()),
+        ];
+        let report = analyze(&keys, &surf, &queries).unwrap();
+        assert_eq!(report.all.negatives, 0);
+        assert_eq!(report.all.false_negatives, 0);
+    }
+
+    #[test]
+    fn test_width_bits() {
+        assert_eq!(width_bits(b"a", b"a"), 0);
+        assert_eq!(width_bits(b"abcdefgh1", b"abcdefgh2"), 0);
+        assert_eq!(
+            width_bits(&[0, 0, 0, 0, 0, 0, 0, 1], &[0, 0, 0, 0, 0, 0, 0, 3]),
+            2
+        );
+        assert_eq!(width_bits(b"b", b"a"), 0);
+        assert_eq!(width_bits(&[], &[0xFF; 8]), 64);
+    }
+}
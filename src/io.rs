@@ -0,0 +1,372 @@
+// Readers for keys stored in common on-disk formats.
+//
+// A KeyReader streams the keys of its input one at a time, so that they can
+// be collected straight into the vector handed to Surf::new:
+//
+//   let keys = KeyReader::new(r, Format::Hex).collect::<Result<Vec<_>, _>>()?;
+//
+// Keys which can not be read are reported as Error::InvalidKey, along with
+// the line or record they were found at.
+use std::io::{BufRead, ErrorKind, Read};
+use std::str::FromStr;
+
+use crate::iterator::{Error, Location};
+
+// Format is the format keys are stored in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // Newline holds one raw key per line, terminated by \n.
+    Newline,
+    // Hex holds one hex-encoded key per line.
+    Hex,
+    // Base64 holds one key per line, encoded using the standard base64
+    // alphabet. Padding is optional.
+    Base64,
+    // LengthPrefixed holds binary records, each consisting of the length of
+    // the key as a little-endian u32, followed by the key.
+    LengthPrefixed,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        match s {
+            "newline" => Ok(Format::Newline),
+            "hex" => Ok(Format::Hex),
+            "base64" => Ok(Format::Base64),
+            "binary" => Ok(Format::LengthPrefixed),
+            _ => Err(Error::CustomError(format!("Unknown key format {}", s))),
+        }
+    }
+}
+
+// KeyReader reads keys of the given format from a buffered reader.
+pub struct KeyReader<R> {
+    r: R,
+    format: Format,
+    // check_order defines whether keys must be in ascending order.
+    check_order: bool,
+    // read is the number of lines or records read so far.
+    read: usize,
+    previous: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<R: BufRead> KeyReader<R> {
+    pub fn new(r: R, format: Format) -> Self {
+        KeyReader {
+            r,
+            format,
+            check_order: false,
+            read: 0,
+            previous: None,
+            done: false,
+        }
+    }
+
+    // check_order makes the reader fail on the first key which is lesser than
+    // the one before it. Equal keys are allowed, as Surf::new drops
+    // duplicates anyway.
+    pub fn check_order(mut self, check_order: bool) -> Self {
+        self.check_order = check_order;
+        self
+    }
+
+    // location returns the location of the most recently read key.
+    fn location(&self) -> Location {
+        match self.format {
+            Format::LengthPrefixed => Location::Record(self.read),
+            _ => Location::Line(self.read),
+        }
+    }
+
+    fn invalid(&self, reason: &str) -> Error {
+        Error::InvalidKey {
+            at: self.location(),
+            reason: reason.to_string(),
+        }
+    }
+
+    // read_key returns the next key, or None at the end of the input.
+    fn read_key(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.format == Format::LengthPrefixed {
+            return self.read_record();
+        }
+
+        let mut line = Vec::new();
+        if self.r.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        self.read += 1;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        // Encoded keys can not hold carriage returns, so these are taken to
+        // be part of a CRLF line ending.
+        let encoded = line.strip_suffix(b"\r").unwrap_or(&line);
+        match self.format {
+            Format::Hex => decode_hex(encoded)
+                .map(Some)
+                .ok_or_else(|| self.invalid("invalid hex")),
+            Format::Base64 => decode_base64(encoded)
+                .map(Some)
+                .ok_or_else(|| self.invalid("invalid base64")),
+            _ => Ok(Some(line)),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut len = [0u8; 4];
+        let n = read_full(&mut self.r, &mut len)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.read += 1;
+        if n < len.len() {
+            return Err(self.invalid("truncated length"));
+        }
+
+        // The length is not trusted to allocate the key up front, so that a
+        // corrupt length fails on the missing bytes rather than the
+        // allocation.
+        let len = u32::from_le_bytes(len) as usize;
+        let mut key = Vec::new();
+        (&mut self.r).take(len as u64).read_to_end(&mut key)?;
+        if key.len() < len {
+            return Err(self.invalid("truncated key"));
+        }
+        Ok(Some(key))
+    }
+}
+
+impl<R: BufRead> Iterator for KeyReader<R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let key = match self.read_key() {
+            Ok(Some(key)) => key,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                // Where the next key starts is unknown past an error.
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        if self.check_order {
+            if self.previous.as_ref().is_some_and(|p| key < *p) {
+                self.done = true;
+                return Some(Err(self.invalid("key is out of order")));
+            }
+            self.previous = Some(key.clone());
+        }
+
+        Some(Ok(key))
+    }
+}
+
+// read_full reads into buf until it is full or the reader is exhausted, and
+// returns the number of bytes read.
+fn read_full(r: &mut impl BufRead, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(n)
+}
+
+// decode_hex decodes the given hex digits, or returns None if they are not
+// valid hex.
+pub fn decode_hex(digits: &[u8]) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let digit = |d: u8| (d as char).to_digit(16).map(|v| v as u8);
+    digits
+        .chunks(2)
+        .map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?))
+        .collect()
+}
+
+// decode_base64 decodes the given base64 text, using the standard alphabet
+// with optional padding, or returns None if it is not valid base64.
+pub fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let digit = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    };
+
+    let padding = text.iter().rev().take_while(|c| **c == b'=').count();
+    if padding > 2 || (padding > 0 && !text.len().is_multiple_of(4)) {
+        return None;
+    }
+    let text = &text[..text.len() - padding];
+    if text.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut v = 0;
+        for c in chunk {
+            v = v << 6 | digit(*c)?;
+        }
+        v <<= 6 * (4 - chunk.len());
+
+        // A chunk of n digits holds n - 1 bytes. The bits of a final chunk
+        // left over past those bytes must be zero, so that each key has a
+        // single encoding.
+        if v & (0xFFFFFF >> (8 * (chunk.len() - 1))) != 0 {
+            return None;
+        }
+        out.extend_from_slice(&v.to_be_bytes()[1..chunk.len()]);
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &[u8], format: Format) -> Result<Vec<Vec<u8>>, Error> {
+        KeyReader::new(input, format).collect()
+    }
+
+    fn invalid(at: Location, reason: &str) -> Error {
+        Error::InvalidKey {
+            at,
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read_lines() {
+        assert_eq!(
+            read(b"far\nfast\n", Format::Newline),
+            Ok(vec![b"far".to_vec(), b"fast".to_vec()])
+        );
+        assert_eq!(
+            read(b"a\n\nb\r", Format::Newline),
+            Ok(vec![b"a".to_vec(), vec![], b"b\r".to_vec()])
+        );
+        assert_eq!(read(b"", Format::Newline), Ok(vec![]));
+
+        assert_eq!(
+            read(b"00ff\r\n6162\n", Format::Hex),
+            Ok(vec![vec![0x00, 0xFF], b"ab".to_vec()])
+        );
+        assert_eq!(
+            read(b"00\n0g\n", Format::Hex),
+            Err(invalid(Location::Line(2), "invalid hex"))
+        );
+
+        assert_eq!(
+            read(b"ZmFy\nZmFzdA==\nZmFzdA\n", Format::Base64),
+            Ok(vec![b"far".to_vec(), b"fast".to_vec(), b"fast".to_vec()])
+        );
+        assert_eq!(
+            read(b"ZmFy\nZm!y\n", Format::Base64),
+            Err(invalid(Location::Line(2), "invalid base64"))
+        );
+    }
+
+    #[test]
+    fn test_read_records() {
+        let mut input = Vec::new();
+        for key in [&b"a\n"[..], b"", b"xyz"] {
+            input.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            input.extend_from_slice(key);
+        }
+
+        assert_eq!(
+            read(&input, Format::LengthPrefixed),
+            Ok(vec![b"a\n".to_vec(), vec![], b"xyz".to_vec()])
+        );
+        assert_eq!(
+            read(&input[..input.len() - 1], Format::LengthPrefixed),
+            Err(invalid(Location::Record(3), "truncated key"))
+        );
+        assert_eq!(
+            read(&input[..input.len() - 5], Format::LengthPrefixed),
+            Err(invalid(Location::Record(3), "truncated length"))
+        );
+
+        // A corrupt length does not allocate the key it claims.
+        let mut corrupt = u32::MAX.to_le_bytes().to_vec();
+        corrupt.extend_from_slice(b"abc");
+        assert_eq!(
+            read(&corrupt, Format::LengthPrefixed),
+            Err(invalid(Location::Record(1), "truncated key"))
+        );
+    }
+
+    #[test]
+    fn test_check_order() {
+        let keys: Result<Vec<Vec<u8>>, Error> =
+            KeyReader::new(&b"a\nb\nb\na\nc\n"[..], Format::Newline)
+                .check_order(true)
+                .collect();
+        assert_eq!(keys, Err(invalid(Location::Line(4), "key is out of order")));
+        assert_eq!(
+            Error::InvalidKey {
+                at: Location::Line(4),
+                reason: "key is out of order".to_string()
+            }
+            .to_string(),
+            "line 4: key is out of order"
+        );
+
+        assert_eq!(read(b"b\na\n", Format::Newline).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex(b"0aFF"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(decode_hex(b""), Some(vec![]));
+        assert_eq!(decode_hex(b"abc"), None);
+        assert_eq!(decode_hex(b"zz"), None);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64(b""), Some(vec![]));
+        assert_eq!(decode_base64(b"Zg=="), Some(b"f".to_vec()));
+        assert_eq!(decode_base64(b"Zm8="), Some(b"fo".to_vec()));
+        assert_eq!(decode_base64(b"Zm9v"), Some(b"foo".to_vec()));
+        assert_eq!(decode_base64(b"Zm9vYg"), Some(b"foob".to_vec()));
+        assert_eq!(decode_base64(b"/+8A"), Some(vec![0xFF, 0xEF, 0x00]));
+
+        assert_eq!(decode_base64(b"Z"), None);
+        assert_eq!(decode_base64(b"Zg="), None);
+        assert_eq!(decode_base64(b"Z==="), None);
+        assert_eq!(decode_base64(b"Zm9v-A"), None);
+
+        // The unused low bits of a final chunk must be zero.
+        assert_eq!(decode_base64(b"Zh=="), None);
+        assert_eq!(decode_base64(b"Zh"), None);
+        assert_eq!(decode_base64(b"Zm9="), None);
+        assert_eq!(decode_base64(b"Zm9vYh"), None);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::bitmap::Bitmap;
+use crate::transform::KeyTransform;
+use crate::trie::Trie;
+use crate::verify::Violation;
 
 use std::collections::VecDeque;
 
@@ -7,15 +9,203 @@ pub enum Error {
     NoSuchEdge,
     IsLeaf,
     EndOfTrie,
+    MemoryLimitExceeded {
+        needed: usize,
+        allowed: usize,
+        level: usize,
+    },
+    BadMagic,
+    UnsupportedVersion(u32),
+    UnalignedBuffer,
+    Corrupted(Corruption),
+    Io(String),
+    // TargetUnreachable is returned by Options::tune_for if no configuration
+    // meets the target false-positive rate, along with the best rate found.
+    TargetUnreachable {
+        target: f64,
+        best: f64,
+    },
+    // InvalidKey is returned by the key readers of rsurf::io for keys which
+    // can not be read.
+    InvalidKey {
+        at: Location,
+        reason: String,
+    },
+    // TransformMismatch is returned by check_transform if a filter was built
+    // with a different key transform than the one expected.
+    TransformMismatch {
+        stored: KeyTransform,
+        expected: KeyTransform,
+    },
     CustomError(String),
 }
 
+// Location identifies a key within its input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    // Line is the 1-based number of the line holding the key.
+    Line(usize),
+    // Record is the 1-based number of the binary record holding the key.
+    Record(usize),
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Location::Line(n) => write!(f, "line {}", n),
+            Location::Record(n) => write!(f, "record {}", n),
+        }
+    }
+}
+
+// Section identifies a part of a serialized filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Header,
+    Labels,
+    LabelsRanks,
+    HasChild,
+    HasChildRanks,
+    IsPrefixKey,
+    IsPrefixKeyRanks,
+    Suffixes,
+    HasChain,
+    HasChainRanks,
+    ChainEnds,
+    ChainLabels,
+    RootTable,
+    Records,
+}
+
+impl std::fmt::Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Section::Header => write!(f, "header"),
+            Section::Labels => write!(f, "D-Labels"),
+            Section::LabelsRanks => write!(f, "D-Labels rank directory"),
+            Section::HasChild => write!(f, "D-HasChild"),
+            Section::HasChildRanks => write!(f, "D-HasChild rank directory"),
+            Section::IsPrefixKey => write!(f, "D-IsPrefixKey"),
+            Section::IsPrefixKeyRanks => write!(f, "D-IsPrefixKey rank directory"),
+            Section::Suffixes => write!(f, "suffixes"),
+            Section::HasChain => write!(f, "D-HasChain"),
+            Section::HasChainRanks => write!(f, "D-HasChain rank directory"),
+            Section::ChainEnds => write!(f, "chain ends"),
+            Section::ChainLabels => write!(f, "chain labels"),
+            Section::RootTable => write!(f, "root table"),
+            Section::Records => write!(f, "records"),
+        }
+    }
+}
+
+// Corruption describes why a serialized filter is malformed.
+#[derive(Debug, PartialEq)]
+pub enum Corruption {
+    // The filter ends before all of its sections were read.
+    Truncated,
+    // The checksum stored for a section does not match its contents.
+    ChecksumMismatch {
+        section: Section,
+        stored: u32,
+        computed: u32,
+    },
+    // A reserved field of a section is not zero.
+    ReservedField {
+        section: Section,
+    },
+    // A section does not have the length implied by the number of nodes.
+    SectionLength {
+        section: Section,
+        expected: u64,
+        actual: u64,
+    },
+    // The number of nodes on the levels of the trie does not fit in memory.
+    NodeCountOverflow,
+    // The suffixes have more bits than supported.
+    SuffixWidth {
+        hash_bits: u32,
+        real_bits: u32,
+    },
+    // The key transform stored in the header can not be decoded.
+    Transform,
+    // The alphabet stored in the header holds no bytes.
+    Alphabet,
+    // The encoded tree violates a structural invariant.
+    Structure(Violation),
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Corruption::Truncated => write!(f, "filter is truncated"),
+            Corruption::ChecksumMismatch {
+                section,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "checksum mismatch in {}: stored {:#010x}, computed {:#010x}",
+                section, stored, computed
+            ),
+            Corruption::ReservedField { section } => {
+                write!(f, "reserved field of {} is not zero", section)
+            }
+            Corruption::SectionLength {
+                section,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {} bytes long, expected {} bytes",
+                section, actual, expected
+            ),
+            Corruption::NodeCountOverflow => write!(f, "number of nodes overflows"),
+            Corruption::SuffixWidth {
+                hash_bits,
+                real_bits,
+            } => write!(
+                f,
+                "suffixes of {} hash and {} real bits are too wide",
+                hash_bits, real_bits
+            ),
+            Corruption::Transform => write!(f, "key transform is malformed"),
+            Corruption::Alphabet => write!(f, "alphabet is empty"),
+            Corruption::Structure(v) => write!(f, "{}", v),
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::NoSuchEdge => write!(f, "No such edge"),
             Error::IsLeaf => write!(f, "Is leaf"),
             Error::EndOfTrie => write!(f, "Reached end of trie"),
+            Error::MemoryLimitExceeded {
+                needed,
+                allowed,
+                level,
+            } => write!(
+                f,
+                "Memory limit exceeded at level {}: {} bytes needed, {} bytes allowed",
+                level, needed, allowed
+            ),
+            Error::BadMagic => write!(f, "Not a serialized SuRF filter"),
+            Error::UnsupportedVersion(v) => write!(f, "Unsupported format version {}", v),
+            Error::UnalignedBuffer => write!(f, "Buffer is not 8-byte aligned"),
+            Error::Corrupted(c) => write!(f, "Corrupted filter: {}", c),
+            Error::Io(s) => write!(f, "I/O error: {}", s),
+            Error::TargetUnreachable { target, best } => write!(
+                f,
+                "False-positive rate of {} is unreachable, the best found is {}",
+                target, best
+            ),
+            Error::InvalidKey { at, reason } => write!(f, "{}: {}", at, reason),
+            Error::TransformMismatch { stored, expected } => write!(
+                f,
+                "Filter was built with key transform {}, expected {}",
+                stored, expected
+            ),
             Error::CustomError(s) => write!(f, "{}", s),
         }
     }
@@ -23,85 +213,213 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err.to_string())
+    }
+}
+
+impl From<Corruption> for Error {
+    fn from(c: Corruption) -> Error {
+        Error::Corrupted(c)
+    }
+}
+
 impl From<&'static str> for Error {
     fn from(err: &'static str) -> Error {
         Error::CustomError(err.to_string())
     }
 }
 
-pub struct Iterator {
-    pub labels: Bitmap,
-    pub has_child: Bitmap,
-    pub is_prefix_key: Bitmap,
+pub struct Iterator<'a> {
+    pub(crate) trie: Trie<'a>,
     pub node_index: usize,
     pub nodes: VecDeque<usize>,
     pub next_edge: usize,
     pub edges: VecDeque<usize>,
     pub key_prefix: VecDeque<u8>,
+    // start is set if the iterator is positioned before the root node, such
+    // that the key ending on the root itself is yet to be returned.
+    pub(crate) start: bool,
 }
 
-impl Iterator {
+impl<'a> Iterator<'a> {
+    // new creates an iterator positioned at the root node of the given trie,
+    // past the chain of the root.
+    pub(crate) fn new(trie: Trie<'a>) -> Self {
+        Iterator {
+            trie,
+            node_index: 0,
+            next_edge: 0,
+            edges: VecDeque::new(),
+            nodes: VecDeque::new(),
+            key_prefix: trie.chains.labels(0).unwrap_or_default().into(),
+            start: false,
+        }
+    }
+
+    // go_to_child descends along the edge of the given symbol, which is the
+    // byte itself unless the filter was built with a smaller alphabet.
     pub fn go_to_child(&mut self, edge: u8) -> Result<(), Error> {
         self.next_edge = edge as usize;
 
-        let offset = 256 * self.node_index + edge as usize;
+        let next_node = self.trie.child(self.node_index, edge as usize)?;
+        self.enter(edge, next_node)
+    }
 
-        let has_label = self.labels.get(offset)?;
-        if has_label != 1 {
-            return Err(Error::NoSuchEdge);
-        }
+    // enter descends along the edge of the given symbol to the given child,
+    // which must be the node the edge leads to.
+    pub(crate) fn enter(&mut self, edge: u8, child: usize) -> Result<(), Error> {
+        self.key_prefix.push_back(edge);
+        self.nodes.push_back(self.node_index);
+        self.edges.push_back(edge as usize);
+
+        self.node_index = child;
+        self.next_edge = 0;
 
-        let has_child = self.has_child.get(offset)?;
-        if has_child != 1 {
-            return Err(Error::IsLeaf);
+        for offset in self.trie.chains.get(child)? {
+            self.key_prefix.push_back(self.trie.chains.label(offset));
         }
 
-        let next_node = self.has_child.rank(1, offset)?;
+        Ok(())
+    }
 
-        self.key_prefix.push_back(self.next_edge as u8);
-        self.nodes.push_back(self.node_index);
-        self.edges.push_back(edge as usize);
+    // go_to_parent ascends to the parent of the current node, positioned at
+    // the edge following the one leading to the current node.
+    pub(crate) fn go_to_parent(&mut self) -> Result<(), Error> {
+        let chain = self.trie.chains.get(self.node_index)?.len();
+        self.key_prefix.truncate(self.key_prefix.len() - chain - 1);
 
-        self.node_index = next_node;
-        self.next_edge = 0;
+        self.node_index = self.nodes.pop_back().unwrap();
+        self.next_edge = self.edges.pop_back().unwrap() + 1;
 
         Ok(())
     }
 
-    pub fn next_key(&mut self) -> Result<Vec<u8>, Error> {
-        loop {
-            for _ in self.next_edge..256 {
-                match self.go_to_child(self.next_edge as u8) {
-                    Ok(_) => {
-                        let is_prefix_key = self.is_prefix_key.get(self.node_index)?;
+    // back_out leaves the current node after a key diverged from its chain,
+    // positioned such that the next key is the first key of the node if the
+    // key was lesser, and the first key past all keys of the node otherwise.
+    pub(crate) fn back_out(&mut self, past: bool) -> Result<(), Error> {
+        if self.node_index == 0 {
+            match past {
+                true => self.next_edge = self.trie.symbols.width(),
+                false => self.start = true,
+            }
+            return Ok(());
+        }
 
-                        if is_prefix_key == 1 {
-                            let key = self.key_prefix.iter().cloned().collect();
+        self.go_to_parent()?;
+        if !past {
+            self.next_edge -= 1;
+        }
+        Ok(())
+    }
+
+    // prev_key steps back over the stored key preceding the iterator, i.e.
+    // the key next_key returned last, and returns it. Calling next_key
+    // afterwards returns the same key again. Once there is no key left
+    // before the iterator, Error::EndOfTrie is returned.
+    pub fn prev_key(&mut self) -> Result<Vec<u8>, Error> {
+        let key = self.prev_symbols()?;
+        Ok(self.trie.symbols.decode(key))
+    }
 
-                            return Ok(key);
-                        }
+    // prev_symbols steps back over the preceding stored key like prev_key,
+    // but returns it as a key of symbols.
+    pub(crate) fn prev_symbols(&mut self) -> Result<Vec<u8>, Error> {
+        if self.trie.labels.is_empty() || self.start {
+            return Err(Error::EndOfTrie);
+        }
+
+        loop {
+            // The greatest key below the last edge before the iterator comes
+            // first, or the key ending on the node itself if there is no
+            // such edge.
+            if let Some(label) = self.trie.prev_label(self.node_index, self.next_edge) {
+                self.next_edge = label;
+                match self.trie.child(self.node_index, label) {
+                    Ok(child) => {
+                        self.enter(label as u8, child)?;
+                        self.next_edge = self.trie.symbols.width();
+                        continue;
                     }
-                    Err(e) => {
-                        if e.to_string() == "Cannot move to non-existent edge" {
-                            continue;
-                        } else if e.to_string() == "Cannot move to leaf node" {
-                            let mut key: Vec<u8> = self.key_prefix.iter().cloned().collect();
-                            key.push(self.next_edge as u8);
-                            self.next_edge += 1;
-                            return Ok(key);
-                        } else {
-                            return Err(e);
-                        }
+                    Err(Error::IsLeaf) => {
+                        let mut key: Vec<u8> = self.key_prefix.iter().cloned().collect();
+                        key.push(label as u8);
+                        return Ok(key);
                     }
+                    Err(e) => return Err(e),
                 }
             }
 
+            let key = match self.trie.is_prefix_key.get(self.node_index)? {
+                1 => Some(self.key_prefix.iter().cloned().collect()),
+                _ => None,
+            };
+
+            // Leaving the node puts the iterator before the edge leading to
+            // it, which next_key then takes again.
             if self.node_index == 0 {
-                return Err(Error::EndOfTrie);
+                self.start = true;
             } else {
-                self.node_index = self.nodes.pop_back().unwrap();
-                self.next_edge = self.edges.pop_back().unwrap() + 1;
-                self.key_prefix.pop_back();
+                self.go_to_parent()?;
+                self.next_edge -= 1;
+            }
+            match key {
+                Some(key) => return Ok(key),
+                None if self.start => return Err(Error::EndOfTrie),
+                None => {}
+            }
+        }
+    }
+
+    pub fn next_key(&mut self) -> Result<Vec<u8>, Error> {
+        let key = self.next_symbols()?;
+        Ok(self.trie.symbols.decode(key))
+    }
+
+    // next_symbols advances to the next stored key like next_key, but returns
+    // it as a key of symbols.
+    pub(crate) fn next_symbols(&mut self) -> Result<Vec<u8>, Error> {
+        if self.trie.labels.is_empty() {
+            return Err(Error::EndOfTrie);
+        }
+
+        // A key ending on the root itself comes before all others.
+        if self.start {
+            self.start = false;
+            if self.trie.is_prefix_key.get(0)? == 1 {
+                return Ok(self.key_prefix.iter().cloned().collect());
+            }
+        }
+
+        loop {
+            let Some(label) = self.trie.next_label(self.node_index, self.next_edge) else {
+                if self.node_index == 0 {
+                    return Err(Error::EndOfTrie);
+                }
+                self.go_to_parent()?;
+                continue;
+            };
+
+            self.next_edge = label;
+            match self.go_to_child(self.next_edge as u8) {
+                Ok(_) => {
+                    let is_prefix_key = self.trie.is_prefix_key.get(self.node_index)?;
+
+                    if is_prefix_key == 1 {
+                        let key = self.key_prefix.iter().cloned().collect();
+
+                        return Ok(key);
+                    }
+                }
+                Err(Error::IsLeaf) => {
+                    let mut key: Vec<u8> = self.key_prefix.iter().cloned().collect();
+                    key.push(self.next_edge as u8);
+                    self.next_edge += 1;
+                    return Ok(key);
+                }
+                Err(e) => return Err(e),
             }
         }
     }
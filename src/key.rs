@@ -1,37 +1,6 @@
 // Key represents a single key that can be stored in a LOUDS-encoded FST tree.
 pub(crate) type Key = Vec<u8>;
 
-// KeyOps defines methods that can be used on Key types.
-pub(crate) trait KeyOps {
-    // Less compares two keys lexicographically.
-    //
-    // It compares pairs of corresponding bytes of the two keys at the same index.
-    // If the two bytes differ, the key with the lesser byte is considered lesser.
-    //
-    // If all pairs of corresponding bytes are equal, the key with the lesser
-    // length is considered lesser.
-    //
-    // If the two keys are equal, neither is considered lesser than the other.
-    fn less(&self, other: &[u8]) -> bool;
-}
-
-impl KeyOps for Vec<u8> {
-    fn less(&self, other: &[u8]) -> bool {
-        let min_length = std::cmp::min(self.len(), other.len());
-
-        for i in 0..min_length {
-            match self[i].cmp(&other[i]) {
-                std::cmp::Ordering::Less => return true,
-                std::cmp::Ordering::Greater => return false,
-                std::cmp::Ordering::Equal => continue,
-            }
-        }
-
-        // Shared prefix is equal, so self is lesser if it is shorter
-        self.len() < other.len()
-    }
-}
-
 // Truncate truncates the list of keys such that they are still uniquely
 // identifiable.
 //
@@ -0,0 +1,267 @@
+// Dense node layouts.
+//
+// LOUDS-DENSE stores the D-Labels and D-HasChild bits of all nodes in two
+// separate bitmaps, and finds the child of an edge by a rank on D-HasChild,
+// which reads its rank directory. Descending along one edge thus touches
+// three far apart cache lines, plus the one of D-IsPrefixKey at the end of a
+// key.
+//
+// The interleaved layout additionally stores one record per node, holding
+// everything a step down the trie needs: the number of D-HasChild bits set
+// before the node, i.e. the base of the ranks of its edges, its
+// D-IsPrefixKey bit, and its D-Labels and D-HasChild blocks. Records are
+// laid out as follows, all bits being most significant first:
+//
+//   word        field
+//   0           D-IsPrefixKey bit, followed by the 63 bit child-rank base
+//   1..=k       D-Labels block of the node, k being width.div_ceil(64)
+//   k+1..=2k    D-HasChild block of the node
+//
+// Records are padded to a power of two words, or to whole cache lines for
+// wide alphabets, and are stored cache-line aligned. A step down the trie
+// then touches one or two cache lines. The bitmaps remain the canonical
+// encoding of the trie, which the records are checked against, and from
+// which iteration and suffix lookups are answered.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bitops::{leading_ones_mask, single_one_mask};
+use crate::iterator::Error;
+use crate::trie::Trie;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DenseLayout {
+    // Bitmaps stores nodes in the D-Labels and D-HasChild bitmaps only.
+    #[default]
+    Bitmaps,
+    // Interleaved also stores one cache-line aligned record per node.
+    Interleaved,
+}
+
+// Layouts are parsed from "bitmaps" or "interleaved".
+impl FromStr for DenseLayout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DenseLayout, Error> {
+        match s {
+            "bitmaps" => Ok(DenseLayout::Bitmaps),
+            "interleaved" => Ok(DenseLayout::Interleaved),
+            _ => Err(Error::CustomError(format!("Unknown layout {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for DenseLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DenseLayout::Bitmaps => write!(f, "bitmaps"),
+            DenseLayout::Interleaved => write!(f, "interleaved"),
+        }
+    }
+}
+
+// LINE_WORDS is the number of words of a cache line.
+pub(crate) const LINE_WORDS: usize = 8;
+
+// PREFIX_KEY is the bit of the first word of a record holding its
+// D-IsPrefixKey bit.
+const PREFIX_KEY: u64 = 0x8000000000000000;
+
+// Line is a cache line worth of record words, which keeps owned records
+// aligned to cache lines.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C, align(64))]
+pub(crate) struct Line(pub(crate) [u64; LINE_WORDS]);
+
+// words returns the words of the given lines.
+pub(crate) fn words(lines: &[Line]) -> &[u64] {
+    // SAFETY: Line is a plain array of words, without any padding.
+    unsafe { std::slice::from_raw_parts(lines.as_ptr() as *const u64, lines.len() * LINE_WORDS) }
+}
+
+// lines copies the given words, whose number must be a multiple of
+// LINE_WORDS, into lines.
+pub(crate) fn lines(words: &[u64]) -> Vec<Line> {
+    words
+        .chunks_exact(LINE_WORDS)
+        .map(|chunk| Line(chunk.try_into().unwrap()))
+        .collect()
+}
+
+// stride returns the number of words of each record of nodes of the given
+// width, which is 0 for layouts without records.
+pub(crate) fn stride(layout: DenseLayout, width: usize) -> usize {
+    let words = 1 + 2 * width.div_ceil(64);
+    match layout {
+        DenseLayout::Bitmaps => 0,
+        DenseLayout::Interleaved if words <= LINE_WORDS => words.next_power_of_two(),
+        DenseLayout::Interleaved => words.next_multiple_of(LINE_WORDS),
+    }
+}
+
+// encoded_bytes returns the number of bytes taken up by the records of the
+// given number of nodes of the given width, padded to whole cache lines.
+pub(crate) fn encoded_bytes(layout: DenseLayout, nodes: usize, width: usize) -> usize {
+    8 * (nodes * stride(layout, width)).next_multiple_of(LINE_WORDS)
+}
+
+// Records is a read-only view of the records of a trie, which holds no words
+// for tries with the Bitmaps layout.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Records<'a> {
+    // stride is the number of words of each record, as given by stride, and
+    // blocks the number of words of each of its blocks.
+    pub(crate) stride: usize,
+    blocks: usize,
+    pub(crate) words: &'a [u64],
+}
+
+impl<'a> Records<'a> {
+    pub(crate) fn new(layout: DenseLayout, width: usize, words: &'a [u64]) -> Records<'a> {
+        Records {
+            stride: stride(layout, width),
+            blocks: width.div_ceil(64),
+            words,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // record returns the words of the record of the given node.
+    fn record(&self, node: usize) -> Result<&'a [u64], Error> {
+        self.words
+            .get(self.stride * node..self.stride * (node + 1))
+            .ok_or(Error::CustomError(format!("No record for node {}", node)))
+    }
+
+    // child returns the node the edge of the given symbol of the given node
+    // leads to, failing like Iterator::go_to_child for edges without a label
+    // or child.
+    pub(crate) fn child(&self, node: usize, symbol: usize) -> Result<usize, Error> {
+        let record = self.record(node)?;
+        let (word, mask) = (symbol / 64, single_one_mask((symbol % 64) as u32));
+        if word >= self.blocks || record[1 + word] & mask == 0 {
+            return Err(Error::NoSuchEdge);
+        }
+
+        let has_child = &record[1 + self.blocks..];
+        if has_child[word] & mask == 0 {
+            return Err(Error::IsLeaf);
+        }
+
+        let before: usize = has_child[..word]
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum();
+        let within = has_child[word] & leading_ones_mask((symbol % 64) as u32 + 1);
+        Ok((record[0] & !PREFIX_KEY) as usize + before + within.count_ones() as usize)
+    }
+
+    // is_prefix_key returns whether a key ends on the given node.
+    pub(crate) fn is_prefix_key(&self, node: usize) -> Result<bool, Error> {
+        Ok(self.record(node)?[0] & PREFIX_KEY != 0)
+    }
+}
+
+// build returns the records of the given number of nodes of the given trie,
+// in the given layout.
+pub(crate) fn build(trie: &Trie, layout: DenseLayout, nodes: usize) -> Result<Vec<Line>, Error> {
+    let width = trie.symbols.width();
+    let stride = stride(layout, width);
+    let blocks = width.div_ceil(64);
+    if stride == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut words = vec![0u64; encoded_bytes(layout, nodes, width) / 8];
+
+    let mut base = 0u64;
+    for (node, record) in words.chunks_exact_mut(stride).take(nodes).enumerate() {
+        if base & PREFIX_KEY != 0 {
+            return Err(Error::CustomError(
+                "Records address at most 2^63 children".to_string(),
+            ));
+        }
+        record[0] = base;
+        if trie.is_prefix_key.get(node)? == 1 {
+            record[0] |= PREFIX_KEY;
+        }
+
+        for symbol in 0..width {
+            let bit = width * node + symbol;
+            let mask = single_one_mask((symbol % 64) as u32);
+            if trie.labels.get(bit)? == 1 {
+                record[1 + symbol / 64] |= mask;
+            }
+            if trie.has_child.get(bit)? == 1 {
+                record[1 + blocks + symbol / 64] |= mask;
+                base += 1;
+            }
+        }
+    }
+
+    Ok(lines(&words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::options::Options;
+    use crate::surf::Surf;
+
+    #[test]
+    fn test_stride() {
+        let interleaved = |width| stride(DenseLayout::Interleaved, width);
+        assert_eq!(interleaved(4), 4);
+        assert_eq!(interleaved(64), 4);
+        assert_eq!(interleaved(100), 8);
+        assert_eq!(interleaved(192), 8);
+        assert_eq!(interleaved(256), 16);
+        assert_eq!(stride(DenseLayout::Bitmaps, 256), 0);
+    }
+
+    #[test]
+    fn test_records() {
+        let keys = vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ];
+        for alphabet in [Alphabet::Bytes, Alphabet::Detect] {
+            let options = Options {
+                layout: DenseLayout::Interleaved,
+                alphabet,
+                ..Options::new()
+            };
+            let surf = Surf::new(keys.clone(), options).unwrap();
+            assert_eq!(surf.records.as_ptr() as usize % 64, 0);
+
+            // Records lead to the same children as ranks on D-HasChild.
+            let trie = surf.trie();
+            let width = trie.symbols.width();
+            for node in 0..surf.levels.iter().sum() {
+                assert_eq!(
+                    trie.records.is_prefix_key(node),
+                    Ok(trie.is_prefix_key.get(node) == Ok(1))
+                );
+                for symbol in 0..width {
+                    let bit = width * node + symbol;
+                    let expected = match (trie.labels.get(bit), trie.has_child.get(bit)) {
+                        (Ok(0), _) => Err(Error::NoSuchEdge),
+                        (_, Ok(0)) => Err(Error::IsLeaf),
+                        _ => Ok(trie.has_child.rank(1, bit).unwrap()),
+                    };
+                    assert_eq!(trie.records.child(node, symbol), expected);
+                }
+            }
+        }
+    }
+}
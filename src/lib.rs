@@ -1,7 +1,37 @@
+pub mod alphabet;
+pub mod binary;
 pub mod bitmap;
 pub mod bitops;
+mod casefold;
+mod chain;
+mod checksum;
+pub mod degrade;
 pub mod dense;
+mod dump;
+pub mod encode;
+pub mod estimate;
+pub mod explain;
+pub mod fixed;
+mod format;
+pub mod fpr;
+pub mod io;
 pub mod iterator;
 pub mod key;
+pub mod layout;
+mod memory;
 pub mod options;
+mod rank;
+mod root_table;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod stats;
+mod suffix;
 pub mod surf;
+#[cfg(test)]
+mod testing;
+pub mod transform;
+mod trie;
+mod tune;
+pub mod tuple;
+pub mod typed;
+pub mod verify;
@@ -0,0 +1,373 @@
+// rsurf builds SuRF filters from files of keys, and queries and inspects
+// filters serialized using Surf::write_to.
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use rsurf::io::{decode_hex, Format, KeyReader};
+use rsurf::iterator::{Corruption, Error};
+use rsurf::options::Options;
+use rsurf::surf::Surf;
+
+const USAGE: &str = "\
+usage: rsurf <command> [options] <args>
+
+commands:
+  build [options] <keys> <filter>  build a filter from a file of keys
+      --format <format>            format of the keys file: newline (default),
+                                   hex, base64, or binary (u32 little-endian
+                                   length followed by the key, per key)
+      --check-order                fail unless the keys are sorted
+      --hash-bits <n>              hash suffix bits per key (default 4)
+      --real-bits <n>              real suffix bits per key (default 4)
+      --memory-limit <bytes>       memory limit (default 256000000)
+      --transform <transforms>     comma-separated key transforms applied to
+                                   keys and queries: casefold,
+                                   ascii-lowercase, or prefix:<n>
+      --alphabet <alphabet>        bytes keys are made of: bytes (default),
+                                   detect, or symbols:<bytes>, e.g.
+                                   symbols:ACGT
+      --compress-paths             leave chains of single-child nodes out of
+                                   the trie
+      --root-table <bytes>         index a table of nodes by the first 1 or 2
+                                   key bytes (default 0, no table)
+      --layout <layout>            dense node layout: bitmaps (default), or
+                                   interleaved for cache-line aligned records
+  get [--hex] <filter> <key>       print whether the key may exist
+  range [--hex] <filter> <low> <high>
+                                   print whether any key may lie in [low, high]
+  stats <filter>                   print structural statistics
+  dump [--dot] <filter>            print the stored truncated keys and bitmaps,
+                                   or the trie as a Graphviz graph
+  verify <filter>                  check the structural integrity of a filter
+
+Keys given on the command line are taken as UTF-8, or hex-decoded with --hex.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args, &mut std::io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rsurf: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// run runs the command given by the arguments, writing what it prints to
+// out.
+fn run(args: &[String], out: &mut impl Write) -> Result<(), Error> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(usage("missing command"));
+    };
+
+    match command.as_str() {
+        "build" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "--format",
+                    "--hash-bits",
+                    "--real-bits",
+                    "--memory-limit",
+                    "--transform",
+                    "--alphabet",
+                    "--root-table",
+                    "--layout",
+                ],
+                &["--check-order", "--compress-paths"],
+            )?;
+            let [keys_path, filter_path] = args.positional()?;
+
+            let mut options = Options::new();
+            if let Some(v) = args.value("--hash-bits") {
+                options.hash_bits = number(v)?;
+            }
+            if let Some(v) = args.value("--real-bits") {
+                options.real_bits = number(v)?;
+            }
+            if let Some(v) = args.value("--memory-limit") {
+                options.memory_limit = number(v)?;
+            }
+            if let Some(v) = args.value("--transform") {
+                options.transform = v.parse()?;
+            }
+            if let Some(v) = args.value("--alphabet") {
+                options.alphabet = v.parse()?;
+            }
+            options.path_compression = args.flag("--compress-paths");
+            if let Some(v) = args.value("--root-table") {
+                options.root_table_bytes = number(v)?;
+            }
+            if let Some(v) = args.value("--layout") {
+                options.layout = v.parse()?;
+            }
+
+            let format: Format = args.value("--format").unwrap_or("newline").parse()?;
+            let keys = KeyReader::new(BufReader::new(File::open(keys_path)?), format)
+                .check_order(args.flag("--check-order"))
+                .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+            let surf = Surf::new(keys, options)?;
+            let mut w = BufWriter::new(File::create(filter_path)?);
+            surf.write_to(&mut w)?;
+            w.flush()?;
+        }
+        "get" => {
+            let args = Args::parse(args, &[], &["--hex"])?;
+            let [filter_path, key] = args.positional()?;
+
+            let surf = load(filter_path)?;
+            let (exists, _, _) = surf.get(key_arg(key, args.flag("--hex"))?)?;
+            writeln!(out, "{}", exists)?;
+        }
+        "range" => {
+            let args = Args::parse(args, &[], &["--hex"])?;
+            let [filter_path, low, high] = args.positional()?;
+
+            let surf = load(filter_path)?;
+            let hex = args.flag("--hex");
+            let found = surf.range(key_arg(low, hex)?, key_arg(high, hex)?)?;
+            writeln!(out, "{}", found)?;
+        }
+        "stats" => {
+            let args = Args::parse(args, &[], &[])?;
+            let [filter_path] = args.positional()?;
+
+            print_stats(&load(filter_path)?, out)?;
+        }
+        "dump" => {
+            let args = Args::parse(args, &[], &["--dot"])?;
+            let [filter_path] = args.positional()?;
+
+            let surf = load(filter_path)?;
+            match args.flag("--dot") {
+                true => write!(out, "{}", surf.to_dot()?)?,
+                false => write!(out, "{}", surf.dump()?)?,
+            }
+        }
+        "verify" => {
+            let args = Args::parse(args, &[], &[])?;
+            let [filter_path] = args.positional()?;
+
+            // Loading a filter checks its structure already, but the command
+            // does not rely on that.
+            let surf = load(filter_path)?;
+            surf.verify().map_err(Corruption::Structure)?;
+            writeln!(out, "ok")?;
+        }
+        "help" | "--help" | "-h" => writeln!(out, "{}", USAGE)?,
+        command => return Err(usage(&format!("unknown command {}", command))),
+    }
+
+    Ok(())
+}
+
+// Args holds the parsed arguments of a command.
+struct Args<'a> {
+    values: Vec<(&'a str, &'a str)>,
+    flags: Vec<&'a str>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    // parse splits the given arguments into options taking a value, flags
+    // and positional arguments.
+    fn parse(args: &'a [String], value_options: &[&str], flags: &[&str]) -> Result<Self, Error> {
+        let mut parsed = Args {
+            values: Vec::new(),
+            flags: Vec::new(),
+            positional: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if value_options.contains(&arg.as_str()) {
+                let Some(value) = args.next() else {
+                    return Err(usage(&format!("missing value for {}", arg)));
+                };
+                parsed.values.push((arg, value));
+            } else if flags.contains(&arg.as_str()) {
+                parsed.flags.push(arg);
+            } else if arg.starts_with("--") {
+                return Err(usage(&format!("unknown option {}", arg)));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn value(&self, name: &str) -> Option<&'a str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    // positional returns the positional arguments, of which there must be
+    // exactly N.
+    fn positional<const N: usize>(&self) -> Result<[&'a str; N], Error> {
+        self.positional.clone().try_into().map_err(|_| {
+            usage(&format!(
+                "expected {} arguments, got {}",
+                N,
+                self.positional.len()
+            ))
+        })
+    }
+}
+
+fn usage(message: &str) -> Error {
+    Error::CustomError(format!("{}\n\n{}", message, USAGE))
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, Error> {
+    s.parse()
+        .map_err(|_| Error::CustomError(format!("invalid number {}", s)))
+}
+
+// load reads the filter serialized into the given file.
+fn load(path: &str) -> Result<Surf, Error> {
+    Surf::read_from(BufReader::new(File::open(path)?))
+}
+
+// key_arg returns the key given on the command line.
+fn key_arg(arg: &str, hex: bool) -> Result<Vec<u8>, Error> {
+    match hex {
+        true => decode_hex(arg.as_bytes())
+            .ok_or_else(|| Error::CustomError(format!("invalid hex key {}", arg))),
+        false => Ok(arg.as_bytes().to_vec()),
+    }
+}
+
+fn print_stats(surf: &Surf, out: &mut impl Write) -> Result<(), Error> {
+    let stats = surf.stats();
+    let list = |values: &[usize]| {
+        values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    writeln!(out, "keys            {}", stats.keys)?;
+    writeln!(out, "height          {}", stats.height)?;
+    writeln!(out, "level nodes     {}", list(&stats.level_nodes))?;
+    writeln!(out, "key lengths     {}", list(&stats.key_lengths))?;
+    writeln!(out, "hash bits       {}", surf.options().hash_bits)?;
+    writeln!(out, "real bits       {}", surf.options().real_bits)?;
+    writeln!(out, "key transform   {}", surf.options().transform)?;
+    writeln!(out, "alphabet size   {}", surf.options().alphabet.width())?;
+    writeln!(out, "path compressed {}", surf.options().path_compression)?;
+    writeln!(
+        out,
+        "root table      over {} key bytes",
+        surf.options().root_table_bytes
+    )?;
+    writeln!(out, "dense layout    {}", surf.options().layout)?;
+    writeln!(out, "memory")?;
+    writeln!(out, "  labels        {}", stats.memory.labels)?;
+    writeln!(out, "  has_child     {}", stats.memory.has_child)?;
+    writeln!(out, "  is_prefix_key {}", stats.memory.is_prefix_key)?;
+    writeln!(out, "  chains        {}", stats.memory.chains)?;
+    writeln!(out, "  root_table    {}", stats.memory.root_table)?;
+    writeln!(out, "  records       {}", stats.memory.records)?;
+    writeln!(out, "  suffixes      {}", stats.memory.suffixes)?;
+    writeln!(out, "  rank/select   {}", stats.memory.rank_select)?;
+    writeln!(out, "  total         {}", stats.memory.total())?;
+    writeln!(out, "bits per key    {:.2}", stats.bits_per_key())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let args: Vec<String> = ["--hex", "filter", "--format", "hex", "key"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let parsed = Args::parse(&args, &["--format"], &["--hex"]).unwrap();
+
+        assert!(parsed.flag("--hex"));
+        assert_eq!(parsed.value("--format"), Some("hex"));
+        assert_eq!(parsed.positional().unwrap(), ["filter", "key"]);
+        assert!(parsed.positional::<1>().is_err());
+
+        assert!(Args::parse(&args, &[], &["--hex"]).is_err());
+    }
+
+    // run_command runs the given command, and returns what it printed.
+    fn run_command(args: &[&str]) -> Result<String, Error> {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_commands() {
+        let dir = std::env::temp_dir().join(format!("rsurf-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let keys = dir.join("keys.txt");
+        let filter = dir.join("keys.surf");
+        let (keys, filter) = (keys.to_str().unwrap(), filter.to_str().unwrap());
+        std::fs::write(keys, "far\nfast\ntop\ntoy\n").unwrap();
+
+        let built = run_command(&["build", "--root-table", "1", keys, filter]);
+        assert_eq!(built.unwrap(), "");
+
+        let stats = run_command(&["stats", filter]).unwrap();
+        assert!(stats.contains("keys            4\n"), "{}", stats);
+        assert!(
+            stats.contains("root table      over 1 key bytes\n"),
+            "{}",
+            stats
+        );
+        let table = Surf::read_from(std::fs::File::open(filter).unwrap())
+            .unwrap()
+            .stats()
+            .memory
+            .root_table;
+        assert!(
+            stats.contains(&format!("  root_table    {}\n", table)),
+            "{}",
+            stats
+        );
+
+        assert_eq!(run_command(&["get", filter, "fast"]).unwrap(), "true\n");
+        assert_eq!(run_command(&["get", filter, "dog"]).unwrap(), "false\n");
+        assert_eq!(
+            run_command(&["get", "--hex", filter, "746f70"]).unwrap(),
+            "true\n"
+        );
+        assert_eq!(
+            run_command(&["range", filter, "g", "s"]).unwrap(),
+            "false\n"
+        );
+        assert_eq!(
+            run_command(&["range", filter, "g", "tp"]).unwrap(),
+            "true\n"
+        );
+        assert_eq!(run_command(&["verify", filter]).unwrap(), "ok\n");
+
+        // A corrupt filter fails to verify.
+        let mut bytes = std::fs::read(filter).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        std::fs::write(filter, bytes).unwrap();
+        assert!(run_command(&["verify", filter]).is_err());
+
+        assert!(run_command(&["get", filter]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
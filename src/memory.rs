@@ -0,0 +1,104 @@
+use crate::iterator::Error;
+
+// bitmap_bytes returns the number of bytes a bitmap of the given number of
+// bits occupies. Bitmaps are backed by 64-bit words, so partially used words
+// count in full.
+pub(crate) fn bitmap_bytes(bits: usize) -> usize {
+    bits.div_ceil(64) * 8
+}
+
+// Budget is the single memory accounting shared by all structures making up a
+// SuRF filter.
+//
+// Structures charge the bytes they need to the level of the trie they belong
+// to. Once everything has been charged, check verifies the total against the
+// limit configured through Options::memory_limit.
+pub(crate) struct Budget {
+    // limit is the maximum number of bytes which may be used, in total.
+    limit: usize,
+    // levels holds the number of bytes charged to each level of the trie.
+    levels: Vec<usize>,
+}
+
+impl Budget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Budget {
+            limit,
+            levels: Vec::new(),
+        }
+    }
+
+    // charge accounts for `bytes` bytes being used by a structure at the given
+    // level of the trie.
+    pub(crate) fn charge(&mut self, level: usize, bytes: usize) {
+        if level >= self.levels.len() {
+            self.levels.resize(level + 1, 0);
+        }
+        self.levels[level] += bytes;
+    }
+
+    // used returns the number of bytes charged so far.
+    pub(crate) fn used(&self) -> usize {
+        self.levels.iter().sum()
+    }
+
+    // check verifies that the charged bytes fit within the limit.
+    //
+    // If they do not, the returned error states the total number of bytes
+    // needed, as well as the first level at which the limit was exceeded.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        let needed = self.used();
+        if needed <= self.limit {
+            return Ok(());
+        }
+
+        let mut used = 0;
+        let mut level = 0;
+        for (l, bytes) in self.levels.iter().enumerate() {
+            used += bytes;
+            if used > self.limit {
+                level = l;
+                break;
+            }
+        }
+
+        Err(Error::MemoryLimitExceeded {
+            needed,
+            allowed: self.limit,
+            level,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_bytes() {
+        assert_eq!(bitmap_bytes(0), 0);
+        assert_eq!(bitmap_bytes(1), 8);
+        assert_eq!(bitmap_bytes(64), 8);
+        assert_eq!(bitmap_bytes(65), 16);
+        assert_eq!(bitmap_bytes(256), 32);
+    }
+
+    #[test]
+    fn test_budget_check() {
+        let mut budget = Budget::new(100);
+        budget.charge(0, 40);
+        budget.charge(1, 40);
+        assert_eq!(budget.check(), Ok(()));
+
+        budget.charge(2, 30);
+        budget.charge(3, 10);
+        assert_eq!(
+            budget.check(),
+            Err(Error::MemoryLimitExceeded {
+                needed: 120,
+                allowed: 100,
+                level: 2,
+            })
+        );
+    }
+}
@@ -1,3 +1,9 @@
+use crate::alphabet::Alphabet;
+use crate::layout::DenseLayout;
+use crate::transform::KeyTransform;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Options {
     // R is the ratio between the sizes of the sparse and dense LOUDS
     // encodings.
@@ -13,6 +19,10 @@ pub struct Options {
     // As such, reducing R leads to more levels being encoded as dense,
     // improving performance at the cost of space efficiency.
     //
+    // LOUDS-SPARSE is not implemented yet, so all levels are encoded as
+    // dense and R has no effect. It is stored with the filter all the same,
+    // so that filters keep their R once the sparse encoding exists.
+    //
     // The default is 64.
     pub r: u32,
 
@@ -43,8 +53,55 @@ pub struct Options {
 
     // MemoryLimit sets the memory limit, in bytes, of the LOUDS encoding.
     //
+    // Building a filter which would need more memory than this fails with
+    // Error::MemoryLimitExceeded.
+    //
     // The default is 256 MB
     pub memory_limit: usize,
+
+    // Transform normalizes keys, e.g. by case folding them. It is applied to
+    // the keys the filter is built from, as well as to the keys and bounds
+    // of all queries, and is stored in serialized filters.
+    //
+    // The default is KeyTransform::Identity.
+    pub transform: KeyTransform,
+
+    // Alphabet is the set of bytes keys are made of, each of which is an
+    // edge of every dense node. Keys over a small alphabet, e.g. hex digits,
+    // take far less memory when nodes only have edges for those bytes.
+    // Alphabet::Detect uses the bytes occurring in the keys. Built filters
+    // hold the alphabet they ended up using.
+    //
+    // The default is Alphabet::Bytes.
+    pub alphabet: Alphabet,
+
+    // PathCompression leaves chains of nodes with a single child, on which
+    // no key ends, out of the tree, and stores their labels with the node
+    // they lead to instead. Keys sharing long prefixes, e.g. URLs or
+    // "tenant/00000/..." paths, then take far fewer levels and less memory.
+    //
+    // The default is false.
+    pub path_compression: bool,
+
+    // RootTableBytes is the number of leading key bytes, at most 2, indexing
+    // a table of the nodes they lead to. Queries then skip the rank calls of
+    // their first steps, at the cost of one 4-byte entry per byte of the
+    // alphabet, plus one per pair of bytes for 2. This only pays off for
+    // large filters, e.g. 2 bytes take 257 KB for the full byte alphabet.
+    // The table counts towards MemoryLimit.
+    //
+    // The default is 0, i.e. no table.
+    pub root_table_bytes: u32,
+
+    // Layout governs how dense nodes are stored. DenseLayout::Interleaved
+    // adds a cache-line aligned record per node to the bitmaps, from which
+    // point lookups descend touching one or two cache lines per step. This
+    // about triples the memory of the nodes, e.g. for the full byte alphabet
+    // a record takes 128 bytes next to about 64 bytes of bitmaps. The
+    // records count towards MemoryLimit.
+    //
+    // The default is DenseLayout::Bitmaps.
+    pub layout: DenseLayout,
 }
 
 impl Default for Options {
@@ -60,6 +117,11 @@ impl Options {
             hash_bits: 4,
             real_bits: 4,
             memory_limit: 256_000_000,
+            transform: KeyTransform::Identity,
+            alphabet: Alphabet::Bytes,
+            path_compression: false,
+            root_table_bytes: 0,
+            layout: DenseLayout::Bitmaps,
         }
     }
 }
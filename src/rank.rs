@@ -0,0 +1,69 @@
+use crate::bitops::leading_ones_mask;
+
+// BLOCK_WORDS is the number of 64-bit words covered by each entry of a rank
+// directory.
+pub(crate) const BLOCK_WORDS: usize = 8;
+
+// directory builds the rank directory of a bitmap with the given words.
+//
+// The i-th entry holds the number of ones in the first i * BLOCK_WORDS words
+// of the bitmap. This allows rank queries to count at most BLOCK_WORDS words,
+// rather than all words up to the queried bit.
+pub(crate) fn directory(data: &[u64]) -> Vec<u64> {
+    let mut ranks = Vec::with_capacity(data.len().div_ceil(BLOCK_WORDS));
+    let mut ones = 0;
+
+    for block in data.chunks(BLOCK_WORDS) {
+        ranks.push(ones);
+        ones += block.iter().map(|w| w.count_ones() as u64).sum::<u64>();
+    }
+
+    ranks
+}
+
+// directory_bytes returns the number of bytes the rank directory of a bitmap
+// with the given number of bits occupies.
+pub(crate) fn directory_bytes(bits: usize) -> usize {
+    bits.div_ceil(64).div_ceil(BLOCK_WORDS) * 8
+}
+
+// rank1 returns the number of ones in the bits [0, idx] of the given words,
+// using their rank directory.
+//
+// idx must be within the bitmap, and the directory must have been built from
+// the same words.
+pub(crate) fn rank1(data: &[u64], ranks: &[u64], idx: usize) -> usize {
+    let word = idx / 64;
+    let block = word / BLOCK_WORDS;
+
+    let mut count = ranks[block] as usize;
+    for w in &data[block * BLOCK_WORDS..word] {
+        count += w.count_ones() as usize;
+    }
+
+    let mask = leading_ones_mask((idx % 64 + 1) as u32);
+    count + (data[word] & mask).count_ones() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank1() {
+        let data: Vec<u64> = (0..20u64)
+            .map(|i| i.wrapping_mul(0x9E3779B97F4A7C15))
+            .collect();
+        let ranks = directory(&data);
+        assert_eq!(ranks.len(), 3);
+        assert_eq!(directory_bytes(20 * 64), 24);
+
+        let mut expected = 0;
+        for idx in 0..data.len() * 64 {
+            if data[idx / 64] & (0x8000000000000000 >> (idx % 64)) != 0 {
+                expected += 1;
+            }
+            assert_eq!(rank1(&data, &ranks, idx), expected, "rank1({})", idx);
+        }
+    }
+}
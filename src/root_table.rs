@@ -0,0 +1,190 @@
+// Root tables, leading straight from the first key bytes to the nodes they
+// lead to.
+//
+// Every query starts out at the root, and descends along the edges of its
+// first bytes, each of which takes a rank on D-HasChild. A root table maps
+// the first symbol of a key, or its first two symbols, directly to the node
+// reached on level 1 or 2, skipping these rank calls at the cost of one entry
+// per symbol, plus one per pair of symbols.
+//
+// Entries are node IDs packed two to a word, starting with the less
+// significant half. The root is the child of no edge, so an entry of 0 tells
+// that the symbols do not lead to a node, in which case queries take the
+// regular path. This is also the case for nodes below a chain, which must be
+// followed symbol by symbol.
+use crate::iterator::Error;
+use crate::trie::Trie;
+
+// RootTable is a read-only view of the root table of a trie.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RootTable<'a> {
+    // bytes is the number of leading symbols indexing the table, which is
+    // 0 for tries without a table.
+    pub(crate) bytes: usize,
+    pub(crate) words: &'a [u64],
+}
+
+impl<'a> RootTable<'a> {
+    // child returns the node the given first one or two symbols of a key lead
+    // to, or None if the table does not hold it.
+    pub(crate) fn child(&self, symbols: &[u8], width: usize) -> Option<usize> {
+        if symbols.iter().any(|s| *s as usize >= width) {
+            return None;
+        }
+
+        let index = match symbols {
+            [a] => *a as usize,
+            [a, b] => width + width * *a as usize + *b as usize,
+            _ => return None,
+        };
+        match self.words.get(index / 2).map(|_| entry(self.words, index)) {
+            None | Some(0) => None,
+            Some(child) => Some(child as usize),
+        }
+    }
+}
+
+// entries returns the number of entries of a table indexed by the given
+// number of leading symbols of the given width.
+pub(crate) fn entries(width: usize, bytes: usize) -> usize {
+    match bytes {
+        0 => 0,
+        1 => width,
+        _ => width + width * width,
+    }
+}
+
+// encoded_bytes returns the number of bytes taken up by a table indexed by
+// the given number of leading symbols of the given width.
+pub(crate) fn encoded_bytes(width: usize, bytes: usize) -> usize {
+    8 * entries(width, bytes).div_ceil(2)
+}
+
+// entry returns the entry at the given index of the packed words.
+pub(crate) fn entry(words: &[u64], index: usize) -> u32 {
+    (words[index / 2] >> (32 * (index % 2))) as u32
+}
+
+// build returns the packed root table of the given trie, indexed by the given
+// number of leading symbols.
+pub(crate) fn build(trie: &Trie, bytes: usize) -> Result<Vec<u64>, Error> {
+    let width = trie.symbols.width();
+    let mut entries = vec![0u32; self::entries(width, bytes)];
+
+    // Nodes below a chain can not be told from the leading symbols alone.
+    let no_chain = |node| trie.chains.get(node).map(|chain| chain.is_empty());
+    if bytes > 0 && !trie.labels.is_empty() && no_chain(0)? {
+        for a in 0..width {
+            let child = child(trie, 0, a)?;
+            entries[a] = node_id(child)?;
+
+            if bytes == 2 && child != 0 && no_chain(child)? {
+                for b in 0..width {
+                    entries[width + width * a + b] = node_id(self::child(trie, child, b)?)?;
+                }
+            }
+        }
+    }
+
+    let mut words = vec![0u64; entries.len().div_ceil(2)];
+    for (i, e) in entries.iter().enumerate() {
+        words[i / 2] |= (*e as u64) << (32 * (i % 2));
+    }
+    Ok(words)
+}
+
+// child returns the node the edge of the given symbol of the given node leads
+// to, or 0 if there is none.
+fn child(trie: &Trie, node: usize, symbol: usize) -> Result<usize, Error> {
+    let bit = trie.symbols.width() * node + symbol;
+    match trie.has_child.get(bit)? {
+        1 => Ok(trie.has_child.rank(1, bit)?),
+        _ => Ok(0),
+    }
+}
+
+fn node_id(node: usize) -> Result<u32, Error> {
+    u32::try_from(node)
+        .map_err(|_| Error::CustomError("Root tables address at most 2^32 nodes".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::iterator::Error;
+    use crate::options::Options;
+    use crate::surf::Surf;
+
+    fn keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    fn get_surf(root_table_bytes: u32) -> Surf {
+        let options = Options {
+            root_table_bytes,
+            ..Options::new()
+        };
+        Surf::new(keys(), options).unwrap()
+    }
+
+    #[test]
+    fn test_root_table() {
+        let surf = get_surf(2);
+        let table = surf.trie().root_table;
+        assert_eq!(surf.root_table.len(), (256 + 256 * 256) / 2);
+
+        // f and t lead to nodes 1 and 2, fa and to to nodes 3 and 4, while s,
+        // tr and fas end on leaf edges.
+        assert_eq!(table.child(b"f", 256), Some(1));
+        assert_eq!(table.child(b"t", 256), Some(2));
+        assert_eq!(table.child(b"fa", 256), Some(3));
+        assert_eq!(table.child(b"to", 256), Some(4));
+        for symbols in [&b"s"[..], b"x", b"tr", b"sa"] {
+            assert_eq!(table.child(symbols, 256), None, "{:?}", symbols);
+        }
+
+        let surf = get_surf(1);
+        let table = surf.trie().root_table;
+        assert_eq!(surf.root_table.len(), 128);
+        assert_eq!(table.child(b"t", 256), Some(2));
+        assert_eq!(table.child(b"to", 256), None);
+
+        assert!(get_surf(0).root_table.is_empty());
+    }
+
+    #[test]
+    fn test_root_table_memory_limit() {
+        // The root table counts towards the memory limit, and is charged to
+        // the root level before anything is built.
+        let without = get_surf(0).stats().memory.total();
+        let table = super::encoded_bytes(256, 2);
+        let build = |memory_limit| {
+            let options = Options {
+                root_table_bytes: 2,
+                memory_limit,
+                ..Options::new()
+            };
+            Surf::new(keys(), options)
+        };
+
+        let surf = build(without + table).unwrap();
+        assert_eq!(surf.stats().memory.total(), without + table);
+
+        // With a byte less, the last level is the first to no longer fit.
+        assert_eq!(
+            build(without + table - 1).err(),
+            Some(Error::MemoryLimitExceeded {
+                needed: without + table,
+                allowed: without + table - 1,
+                level: surf.height() - 1,
+            })
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// Serde support for Options, Bitmap and Surf, enabled by the "serde" feature.
+//
+// The words of bitmaps are serialized as a single byte array holding the
+// words in little-endian order, which compact formats store as one blob
+// rather than as a list of numbers. Filters are verified when deserialized,
+// just like filters loaded through Surf::read_from.
+use std::fmt;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::alphabet::SymbolMap;
+use crate::bitmap::Bitmap;
+use crate::layout;
+use crate::options::Options;
+use crate::surf::Surf;
+
+// WordsRef serializes borrowed words as a byte array.
+struct WordsRef<'a>(&'a [u64]);
+
+impl Serialize for WordsRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = self.0.iter().flat_map(|w| w.to_le_bytes()).collect();
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+// Words deserializes words serialized by WordsRef.
+struct Words(Vec<u64>);
+
+impl<'de> Deserialize<'de> for Words {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(WordsVisitor)
+    }
+}
+
+struct WordsVisitor;
+
+impl<'de> Visitor<'de> for WordsVisitor {
+    type Value = Words;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte array holding 64-bit words")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Words, E> {
+        if !v.len().is_multiple_of(8) {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+
+        let words = v
+            .chunks_exact(8)
+            .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        Ok(Words(words))
+    }
+
+    // Self-describing formats without a native byte type, such as JSON,
+    // represent byte arrays as sequences.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Words, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(b) = seq.next_element::<u8>()? {
+            bytes.push(b);
+        }
+
+        self.visit_bytes(&bytes)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Bitmap")]
+struct BitmapRepr<'a> {
+    capacity: usize,
+    data: WordsRef<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Bitmap")]
+struct BitmapData {
+    capacity: usize,
+    data: Words,
+}
+
+impl Serialize for Bitmap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BitmapRepr {
+            capacity: self.capacity,
+            data: WordsRef(&self.data),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bitmap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = BitmapData::deserialize(deserializer)?;
+
+        let mut bitmap = Bitmap::from_words(repr.data.0);
+        bitmap.capacity = repr.capacity;
+        Ok(bitmap)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Surf")]
+struct SurfRepr<'a> {
+    options: &'a Options,
+    levels: &'a [usize],
+    labels: &'a Bitmap,
+    labels_ranks: WordsRef<'a>,
+    has_child: &'a Bitmap,
+    has_child_ranks: WordsRef<'a>,
+    is_prefix_key: &'a Bitmap,
+    is_prefix_key_ranks: WordsRef<'a>,
+    suffixes: WordsRef<'a>,
+    has_chain: &'a Bitmap,
+    has_chain_ranks: WordsRef<'a>,
+    chain_ends: WordsRef<'a>,
+    chain_labels: WordsRef<'a>,
+    root_table: WordsRef<'a>,
+    records: WordsRef<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Surf")]
+struct SurfData {
+    options: Options,
+    levels: Vec<usize>,
+    labels: Bitmap,
+    labels_ranks: Words,
+    has_child: Bitmap,
+    has_child_ranks: Words,
+    is_prefix_key: Bitmap,
+    is_prefix_key_ranks: Words,
+    suffixes: Words,
+    has_chain: Bitmap,
+    has_chain_ranks: Words,
+    chain_ends: Words,
+    chain_labels: Words,
+    root_table: Words,
+    records: Words,
+}
+
+impl Serialize for Surf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SurfRepr {
+            options: &self.options,
+            levels: &self.levels,
+            labels: &self.dense_labels,
+            labels_ranks: WordsRef(&self.dense_labels_ranks),
+            has_child: &self.dense_has_child,
+            has_child_ranks: WordsRef(&self.dense_has_child_ranks),
+            is_prefix_key: &self.dense_is_prefix_key,
+            is_prefix_key_ranks: WordsRef(&self.dense_is_prefix_key_ranks),
+            suffixes: WordsRef(&self.suffixes),
+            has_chain: &self.dense_has_chain,
+            has_chain_ranks: WordsRef(&self.dense_has_chain_ranks),
+            chain_ends: WordsRef(&self.chain_ends),
+            chain_labels: WordsRef(&self.chain_labels),
+            root_table: WordsRef(&self.root_table),
+            records: WordsRef(layout::words(&self.records)),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Surf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SurfData::deserialize(deserializer)?;
+        if repr.records.0.len() % layout::LINE_WORDS != 0 {
+            return Err(de::Error::custom("records do not fill whole cache lines"));
+        }
+
+        let surf = Surf {
+            symbols: SymbolMap::new(&repr.options.alphabet),
+            options: repr.options,
+            levels: repr.levels,
+            dense_labels: repr.labels,
+            dense_labels_ranks: repr.labels_ranks.0,
+            dense_has_child: repr.has_child,
+            dense_has_child_ranks: repr.has_child_ranks.0,
+            dense_is_prefix_key: repr.is_prefix_key,
+            dense_is_prefix_key_ranks: repr.is_prefix_key_ranks.0,
+            dense_has_chain: repr.has_chain,
+            dense_has_chain_ranks: repr.has_chain_ranks.0,
+            chain_ends: repr.chain_ends.0,
+            chain_labels: repr.chain_labels.0,
+            root_table: repr.root_table.0,
+            records: layout::lines(&repr.records.0),
+            suffixes: repr.suffixes.0,
+        };
+        surf.verify().map_err(de::Error::custom)?;
+
+        Ok(surf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let surf = Surf::new(get_keys(), Options::new()).unwrap();
+
+        let json = serde_json::to_string(&surf).unwrap();
+        let loaded: Surf = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.levels, surf.levels);
+        assert_eq!(loaded.dense_labels, surf.dense_labels);
+        assert_eq!(loaded.dense_has_child, surf.dense_has_child);
+        assert_eq!(loaded.dense_has_child_ranks, surf.dense_has_child_ranks);
+        assert_eq!(loaded.dense_is_prefix_key, surf.dense_is_prefix_key);
+        assert_eq!(loaded.suffixes, surf.suffixes);
+        for k in get_keys() {
+            assert!(loaded.get(k).unwrap().0);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_path_compression() {
+        let options = Options {
+            path_compression: true,
+            ..Options::new()
+        };
+        let keys = vec![b"tenant/a/x1".to_vec(), b"tenant/b".to_vec()];
+        let surf = Surf::new(keys.clone(), options).unwrap();
+
+        let json = serde_json::to_string(&surf).unwrap();
+        let loaded: Surf = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.dense_has_chain, surf.dense_has_chain);
+        assert_eq!(loaded.chain_ends, surf.chain_ends);
+        assert_eq!(loaded.chain_labels, surf.chain_labels);
+        for k in keys {
+            assert!(loaded.get(k).unwrap().0);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_interleaved() {
+        let options = Options {
+            layout: layout::DenseLayout::Interleaved,
+            ..Options::new()
+        };
+        let surf = Surf::new(get_keys(), options).unwrap();
+
+        let json = serde_json::to_string(&surf).unwrap();
+        let loaded: Surf = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.options, surf.options);
+        assert_eq!(loaded.records, surf.records);
+        for k in get_keys() {
+            assert!(loaded.get(k).unwrap().0);
+        }
+    }
+
+    #[test]
+    fn test_bitmap_as_bytes() {
+        let mut bitmap = Bitmap::new(128, 256);
+        bitmap.set(7).unwrap();
+
+        let value = serde_json::to_value(&bitmap).unwrap();
+        let data = value["data"].as_array().unwrap();
+        assert_eq!(data.len(), 16);
+        assert_eq!(data[7], 0x01);
+        assert_eq!(value["capacity"], 256);
+
+        let loaded: Bitmap = serde_json::from_value(value).unwrap();
+        assert_eq!(loaded, bitmap);
+    }
+
+    #[test]
+    fn test_reject_invalid() {
+        let mut surf = Surf::new(get_keys(), Options::new()).unwrap();
+        surf.dense_has_child.data[0] |= 1;
+
+        let json = serde_json::to_string(&surf).unwrap();
+        let err = serde_json::from_str::<Surf>(&json).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("D-HasChild bit is set without its label"));
+    }
+}
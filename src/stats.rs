@@ -0,0 +1,203 @@
+use crate::bitops::range_masks;
+use crate::surf::{Surf, SurfRef};
+use crate::trie::Trie;
+
+// Stats describes the structure of a built filter, and what it costs in
+// memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    // keys is the number of keys stored in the filter. Duplicate keys are
+    // only stored once.
+    pub keys: usize,
+    // height is the number of levels of the trie.
+    pub height: usize,
+    // level_nodes holds the number of nodes on each level of the trie.
+    pub level_nodes: Vec<usize>,
+    // key_lengths is the histogram of the lengths of the truncated keys
+    // stored in the trie. The i-th entry holds the number of keys truncated
+    // to i bytes.
+    pub key_lengths: Vec<usize>,
+    // memory is the number of bytes used by each component of the filter.
+    pub memory: MemoryUsage,
+}
+
+// MemoryUsage holds the number of bytes used by each component of a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MemoryUsage {
+    pub labels: usize,
+    pub has_child: usize,
+    pub is_prefix_key: usize,
+    // chains is the memory used by D-HasChain and the chains of path
+    // compression, excluding its rank directory.
+    pub chains: usize,
+    // root_table is the memory used by the root table.
+    pub root_table: usize,
+    // records is the memory used by the records of the interleaved layout.
+    pub records: usize,
+    // suffixes is the memory used by stored key suffixes.
+    pub suffixes: usize,
+    // rank_select is the memory used by rank and select directories.
+    pub rank_select: usize,
+}
+
+impl MemoryUsage {
+    // total returns the number of bytes used by all components.
+    pub fn total(&self) -> usize {
+        self.labels
+            + self.has_child
+            + self.is_prefix_key
+            + self.chains
+            + self.root_table
+            + self.records
+            + self.suffixes
+            + self.rank_select
+    }
+}
+
+impl Stats {
+    // bits_per_key returns the number of bits the filter uses per stored key.
+    pub fn bits_per_key(&self) -> f64 {
+        if self.keys == 0 {
+            return 0.0;
+        }
+
+        (self.memory.total() * 8) as f64 / self.keys as f64
+    }
+}
+
+impl Surf {
+    // stats returns structural statistics of the filter, as well as a
+    // breakdown of the memory it uses.
+    pub fn stats(&self) -> Stats {
+        compute(&self.trie(), &self.levels)
+    }
+}
+
+impl<'a> SurfRef<'a> {
+    // stats returns structural statistics of the filter, as well as a
+    // breakdown of the memory it uses.
+    pub fn stats(&self) -> Stats {
+        compute(&self.trie, &self.levels)
+    }
+}
+
+// compute gathers the statistics of the given trie, whose levels hold the
+// given number of nodes.
+fn compute(trie: &Trie, levels: &[usize]) -> Stats {
+    let labels = trie.labels.words();
+    let has_child = trie.has_child.words();
+
+    // Keys end either on an edge without a child, or on a node which is a
+    // prefix key. The former are one byte longer than the depth of the node
+    // they are on, which is its level unless chains lie above it.
+    let nodes: usize = levels.iter().sum();
+    let chain = |node| trie.chains.get(node).map_or(0, |chain| chain.len());
+    let mut depths = Vec::with_capacity(nodes);
+    if nodes > 0 {
+        depths.push(chain(0));
+    }
+
+    let mut key_lengths = vec![0; levels.len() + 1];
+    let mut add = |length: usize, keys: usize| {
+        if key_lengths.len() <= length {
+            key_lengths.resize(length + 1, 0);
+        }
+        key_lengths[length] += keys;
+    };
+    let width = trie.symbols.width();
+    for node in 0..nodes {
+        let depth = depths[node];
+        if trie.is_prefix_key.get(node) == Ok(1) {
+            add(depth, 1);
+        }
+
+        for (w, mask) in range_masks(width * node, width * (node + 1)) {
+            let leaves = labels[w] & !has_child[w] & mask;
+            add(depth + 1, leaves.count_ones() as usize);
+
+            // Children are numbered in level order, so the next child is the
+            // next node whose depth is yet to be known.
+            for _ in 0..(has_child[w] & mask).count_ones() {
+                depths.push(depth + 1 + chain(depths.len()));
+            }
+        }
+    }
+
+    while key_lengths.len() > 1 && key_lengths.last() == Some(&0) {
+        key_lengths.pop();
+    }
+
+    Stats {
+        keys: key_lengths.iter().sum(),
+        height: levels.len(),
+        level_nodes: levels.to_vec(),
+        key_lengths,
+        memory: MemoryUsage {
+            labels: labels.len() * 8,
+            has_child: has_child.len() * 8,
+            is_prefix_key: trie.is_prefix_key.words().len() * 8,
+            chains: (trie.chains.has_chain.words().len()
+                + trie.chains.ends.len()
+                + trie.chains.labels.len())
+                * 8,
+            root_table: trie.root_table.words.len() * 8,
+            records: trie.records.words.len() * 8,
+            suffixes: trie.suffixes.words.len() * 8,
+            rank_select: (trie.labels.ranks().len()
+                + trie.has_child.ranks().len()
+                + trie.is_prefix_key.ranks().len()
+                + trie.chains.has_chain.ranks().len())
+                * 8,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+
+    #[test]
+    fn test_stats() {
+        let keys = vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ];
+        let surf = Surf::new(keys, Options::new()).unwrap();
+        let stats = surf.stats();
+
+        // The keys are truncated to f, far, fas, s, top, toy and tr.
+        assert_eq!(stats.keys, 7);
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.level_nodes, vec![1, 2, 2]);
+        assert_eq!(stats.key_lengths, vec![0, 2, 1, 4]);
+        assert_eq!(
+            stats.memory,
+            MemoryUsage {
+                labels: 5 * 32,
+                has_child: 5 * 32,
+                is_prefix_key: 8,
+                chains: 0,
+                root_table: 0,
+                records: 0,
+                suffixes: 8,
+                rank_select: (3 + 3 + 1) * 8,
+            }
+        );
+        assert_eq!(stats.memory.total(), 392);
+        assert_eq!(stats.bits_per_key(), 392.0 * 8.0 / 7.0);
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let stats = Surf::new(vec![], Options::new()).unwrap().stats();
+        assert_eq!(stats.keys, 0);
+        assert_eq!(stats.key_lengths, vec![0]);
+        assert_eq!(stats.bits_per_key(), 0.0);
+    }
+}
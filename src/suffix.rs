@@ -0,0 +1,273 @@
+// Key suffixes, which lower the false-positive rate of a filter beyond what
+// the truncated keys stored in its trie provide.
+//
+// Every stored key is given a suffix of hash_bits bits of a hash of the whole
+// key, followed by real_bits bits of the key itself, starting right after
+// the truncated prefix stored in the trie. Keys are made of symbols of the
+// alphabet of the trie, each of which takes up as many real bits as the
+// greatest symbol needs, e.g. eight for all bytes, or one for a trie over
+// bits. The suffixes of all keys are
+// packed into one array of words, ordered like the keys are in the trie: in
+// level order, with the key ending on a node coming before the keys ending on
+// its leaf edges.
+use std::cmp::Ordering;
+
+use crate::iterator::Error;
+use crate::key::Key;
+use crate::trie::Trie;
+
+// MAX_BITS is the maximum number of bits of a suffix.
+pub(crate) const MAX_BITS: u32 = 64;
+
+// Suffixes is a read-only view of the packed suffixes of a trie.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Suffixes<'a> {
+    pub(crate) words: &'a [u64],
+    pub(crate) hash_bits: u32,
+    pub(crate) real_bits: u32,
+    // symbol_bits is the number of real bits of each symbol, as given by
+    // symbol_bits.
+    pub(crate) symbol_bits: u32,
+}
+
+impl<'a> Suffixes<'a> {
+    // width returns the number of bits of each suffix.
+    pub(crate) fn width(&self) -> u32 {
+        self.hash_bits + self.real_bits
+    }
+
+    // get returns the suffix of the key with the given index.
+    pub(crate) fn get(&self, index: usize) -> u64 {
+        read(self.words, index, self.width())
+    }
+
+    // matches returns whether the suffix of the key with the given index is
+    // that of the given key, whose first len bytes are stored in the trie.
+    pub(crate) fn matches(&self, index: usize, key: &[u8], len: usize) -> bool {
+        self.get(index) == value(key, len, self.hash_bits, self.real_bits, self.symbol_bits)
+    }
+
+    // compare_real compares the real suffix of the key with the given index
+    // to the same bits of the given key, whose first len bytes are stored in
+    // the trie.
+    //
+    // If the stored bits are lesser or greater, so is the stored key. If they
+    // are equal, the keys may still differ further on.
+    pub(crate) fn compare_real(&self, index: usize, key: &[u8], len: usize) -> Ordering {
+        let stored = self.get(index) & mask(self.real_bits);
+        stored.cmp(&real(key, len, self.real_bits, self.symbol_bits))
+    }
+}
+
+// symbol_bits returns the number of bits taken up by each symbol of an
+// alphabet of the given width.
+pub(crate) fn symbol_bits(width: usize) -> u32 {
+    width.saturating_sub(1).max(1).ilog2() + 1
+}
+
+// words returns the number of words needed to store the suffixes of the
+// given number of keys.
+pub(crate) fn words(keys: usize, width: u32) -> usize {
+    (keys * width as usize).div_ceil(64)
+}
+
+// encode returns the packed suffixes of the given sorted keys, which are
+// stored in the given trie truncated to the given keys.
+pub(crate) fn encode(
+    trie: &Trie,
+    keys: &[Key],
+    truncated: &[Key],
+    hash_bits: u32,
+    real_bits: u32,
+) -> Result<Vec<u64>, Error> {
+    let width = hash_bits + real_bits;
+    let mut words = vec![0u64; self::words(keys.len(), width)];
+    if width == 0 {
+        return Ok(words);
+    }
+    let symbol_bits = symbol_bits(trie.symbols.width());
+
+    for (key, truncated) in keys.iter().zip(truncated) {
+        let (exists, matched_key, it) = trie.lookup(truncated)?;
+        if !exists {
+            return Err(Error::CustomError(format!(
+                "Key {:?} is not stored in the trie",
+                truncated
+            )));
+        }
+
+        let index = trie.key_index(&it, &matched_key)?;
+        write(
+            &mut words,
+            index,
+            width,
+            value(key, truncated.len(), hash_bits, real_bits, symbol_bits),
+        );
+    }
+
+    Ok(words)
+}
+
+// value returns the suffix of the given key, whose first len symbols are
+// stored in the trie.
+pub(crate) fn value(
+    key: &[u8],
+    len: usize,
+    hash_bits: u32,
+    real_bits: u32,
+    symbol_bits: u32,
+) -> u64 {
+    let hash = hash(key) & mask(hash_bits);
+    let real = real(key, len, real_bits, symbol_bits);
+
+    match real_bits {
+        MAX_BITS => real,
+        _ => hash << real_bits | real,
+    }
+}
+
+// hash returns a 64-bit hash of the given key.
+//
+// The hash is part of the serialized filter, so it must never change. It is
+// FNV-1a, followed by the finalizer of MurmurHash3 to mix the low bits which
+// suffixes consist of.
+pub(crate) fn hash(key: &[u8]) -> u64 {
+    let mut h: u64 = 0xCBF29CE484222325;
+    for b in key {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001B3);
+    }
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51AFD7ED558CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CEB9FE1A85EC53);
+    h ^ (h >> 33)
+}
+
+// real returns the given number of bits of the key, starting at its symbol
+// with index from, each symbol taking up symbol_bits bits. Keys which end
+// before are padded with zeroes.
+//
+// A symbol which does not fit, i.e. the width of the alphabet ending a bound
+// past all symbols, is taken as all ones, and so are all symbols after it.
+pub(crate) fn real(key: &[u8], from: usize, bits: u32, symbol_bits: u32) -> u64 {
+    let symbols = bits.div_ceil(symbol_bits) as usize;
+
+    let mut v: u128 = 0;
+    let mut past = false;
+    for i in 0..symbols {
+        let s = key.get(from + i).copied().unwrap_or(0) as u128;
+        past |= s >> symbol_bits != 0;
+        v = v << symbol_bits
+            | match past {
+                true => mask(symbol_bits) as u128,
+                false => s,
+            };
+    }
+
+    (v >> (symbol_bits as usize * symbols - bits as usize)) as u64
+}
+
+// mask returns a mask of the given number of low bits.
+pub(crate) fn mask(bits: u32) -> u64 {
+    match bits {
+        MAX_BITS => u64::MAX,
+        _ => (1 << bits) - 1,
+    }
+}
+
+// read returns the value of the given width stored at the given index.
+pub(crate) fn read(words: &[u64], index: usize, width: u32) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+
+    let bit = index * width as usize;
+    let (word, offset) = (bit / 64, (bit % 64) as u32);
+
+    // Values are stored most significant bit first, and may span two words.
+    let high = (words[word] as u128) << 64;
+    let low = words.get(word + 1).copied().unwrap_or(0) as u128;
+    let v = (high | low) << offset >> (128 - width);
+    v as u64
+}
+
+// write stores the value of the given width at the given index.
+pub(crate) fn write(words: &mut [u64], index: usize, width: u32, value: u64) {
+    if width == 0 {
+        return;
+    }
+
+    let bit = index * width as usize;
+    let (word, offset) = (bit / 64, (bit % 64) as u32);
+
+    let shift = 128 - width - offset;
+    let v = ((value & mask(width)) as u128) << shift;
+    let m = (mask(width) as u128) << shift;
+
+    words[word] = words[word] & !((m >> 64) as u64) | (v >> 64) as u64;
+    if offset + width > 64 {
+        words[word + 1] = words[word + 1] & !(m as u64) | v as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        for width in [1, 3, 7, 13, 31, 64] {
+            let n = 50;
+            let mut words = vec![0u64; words(n, width)];
+            let value_of = |i: usize| (i as u64).wrapping_mul(0x9E3779B97F4A7C15) & mask(width);
+
+            for i in 0..n {
+                write(&mut words, i, width, value_of(i));
+            }
+            for i in 0..n {
+                assert_eq!(read(&words, i, width), value_of(i), "width {}", width);
+            }
+        }
+    }
+
+    #[test]
+    fn test_real() {
+        assert_eq!(real(b"far", 2, 4, 8), 0x7);
+        assert_eq!(real(b"far", 2, 8, 8), b'r' as u64);
+        assert_eq!(real(b"far", 2, 12, 8), (b'r' as u64) << 4);
+        assert_eq!(real(b"far", 3, 8, 8), 0);
+        assert_eq!(
+            real(b"abcdefgh", 0, 64, 8),
+            u64::from_be_bytes(*b"abcdefgh")
+        );
+        assert_eq!(real(b"far", 0, 0, 8), 0);
+
+        // Symbols of narrower alphabets are packed, and symbols past the
+        // alphabet fill all bits after them.
+        assert_eq!(real(&[1, 0, 1, 1], 1, 3, 1), 0b011);
+        assert_eq!(real(&[1, 0, 1, 1], 1, 5, 1), 0b01100);
+        assert_eq!(real(&[3, 1, 2], 0, 4, 2), 0b1101);
+        assert_eq!(real(&[0, 2, 0], 0, 3, 1), 0b011);
+    }
+
+    #[test]
+    fn test_symbol_bits() {
+        assert_eq!(symbol_bits(256), 8);
+        assert_eq!(symbol_bits(200), 8);
+        assert_eq!(symbol_bits(4), 2);
+        assert_eq!(symbol_bits(3), 2);
+        assert_eq!(symbol_bits(2), 1);
+        assert_eq!(symbol_bits(1), 1);
+    }
+
+    #[test]
+    fn test_value() {
+        let h = hash(b"fast");
+        assert_eq!(value(b"fast", 3, 4, 0, 8), h & 0xF);
+        assert_eq!(value(b"fast", 3, 0, 4, 8), 0x7);
+        assert_eq!(value(b"fast", 3, 4, 4, 8), (h & 0xF) << 4 | 0x7);
+        assert_eq!(value(b"fast", 3, 0, 0, 8), 0);
+    }
+}
@@ -1,136 +1,323 @@
-use std::collections::VecDeque;
-
+use crate::alphabet::SymbolMap;
 use crate::bitmap::Bitmap;
+use crate::chain::Chains;
 use crate::dense::Builder;
 use crate::iterator::{Error, Iterator};
 use crate::key::{truncate, Key};
+use crate::layout::{self, Line, Records};
 use crate::options::Options;
+use crate::root_table::{self, RootTable};
+use crate::suffix::{self, Suffixes};
+use crate::transform::KeyTransform;
+use crate::trie::Trie;
 
 pub struct Surf {
-    dense_labels: Bitmap,
-    dense_has_child: Bitmap,
-    dense_is_prefix_key: Bitmap,
+    // options are the options the filter was built with.
+    pub(crate) options: Options,
+    // levels holds the number of nodes on each level of the trie.
+    pub(crate) levels: Vec<usize>,
+    // symbols maps the bytes of keys to the edges of nodes, as given by
+    // Options::alphabet.
+    pub(crate) symbols: SymbolMap,
+
+    pub(crate) dense_labels: Bitmap,
+    pub(crate) dense_labels_ranks: Vec<u64>,
+    pub(crate) dense_has_child: Bitmap,
+    pub(crate) dense_has_child_ranks: Vec<u64>,
+    pub(crate) dense_is_prefix_key: Bitmap,
+    pub(crate) dense_is_prefix_key_ranks: Vec<u64>,
+
+    // dense_has_chain and the chain fields hold the chains of a filter built
+    // with Options::path_compression, and are empty otherwise.
+    pub(crate) dense_has_chain: Bitmap,
+    pub(crate) dense_has_chain_ranks: Vec<u64>,
+    pub(crate) chain_ends: Vec<u64>,
+    pub(crate) chain_labels: Vec<u64>,
+
+    // root_table holds the packed root table, as configured by
+    // Options::root_table_bytes.
+    pub(crate) root_table: Vec<u64>,
+
+    // records holds the records of all nodes of a filter built with
+    // DenseLayout::Interleaved, and is empty otherwise.
+    pub(crate) records: Vec<Line>,
+
+    // suffixes holds the packed suffixes of all keys, as configured by
+    // Options::hash_bits and Options::real_bits.
+    pub(crate) suffixes: Vec<u64>,
 }
 
 impl Surf {
-    pub fn new(raw_keys: Vec<Vec<u8>>, options: Options) -> Result<Surf, Error> {
-        // Convert raw_keys to keys
-        let mut keys: Vec<Key> = raw_keys;
-        keys.sort();
+    pub fn new(raw_keys: Vec<Vec<u8>>, mut options: Options) -> Result<Surf, Error> {
+        let keys = prepare(raw_keys, &mut options)?;
 
         // Truncate keys
-        keys = truncate(&keys);
-
-        let mut dense_builder = Builder::new(options.memory_limit);
-        dense_builder.build(&keys)?;
-
-        let dense_labels = dense_builder.labels;
-        let dense_has_child = dense_builder.has_child;
-        let dense_is_prefix_key = dense_builder.is_prefix_key;
-
-        Ok(Surf {
-            dense_labels,
-            dense_has_child,
-            dense_is_prefix_key,
-        })
-    }
-
-    pub fn get(&mut self, key: Vec<u8>) -> Result<(bool, Vec<u8>, Iterator), Error> {
-        let mut it = Iterator {
-            labels: self.dense_labels.clone(),
-            has_child: self.dense_has_child.clone(),
-            is_prefix_key: self.dense_is_prefix_key.clone(),
-            node_index: 0,
-            next_edge: 0,
-            edges: VecDeque::new(),
-            nodes: VecDeque::new(),
-            key_prefix: VecDeque::new(),
-        };
+        let truncated = truncate(&keys);
 
-        for i in 0..key.len() {
-            let key_byte = key[i];
-
-            match it.go_to_child(key_byte) {
-                Ok(_) => {}
-                Err(e) => {
-                    if e == Error::NoSuchEdge {
-                        // No edge with this value, so the key doesn't exist.
-                        return Ok((false, vec![], it));
-                    } else if e == Error::IsLeaf {
-                        // We attempted to enter a leaf node, so the key exists
-                        return Ok((true, key[..=i].to_vec(), it));
-                    } else {
-                        // Non-specific error, e.g. issue with bitmap access
-                        return Err(e);
-                    }
-                }
-            }
+        Surf::build(&keys, &truncated, options)
+    }
+
+    // build builds a filter from the given sorted, deduplicated keys, which
+    // are stored in the trie as the given truncated keys. The keys must be
+    // made of symbols of the alphabet of the given options.
+    pub(crate) fn build(keys: &[Key], truncated: &[Key], options: Options) -> Result<Surf, Error> {
+        let width = options.hash_bits.checked_add(options.real_bits);
+        if width.is_none_or(|w| w > suffix::MAX_BITS) {
+            return Err(Error::CustomError(format!(
+                "Suffixes may have at most {} bits, got {} hash and {} real bits",
+                suffix::MAX_BITS,
+                options.hash_bits,
+                options.real_bits
+            )));
         }
 
-        // If we get until here, then we traversed the whole key. To determine
-        // whether the key exists, we now must check if our current node has
-        // is_prefix_key set to true.
-        match self.dense_is_prefix_key.get(it.node_index) {
-            Ok(is_prefix_key) => {
-                if is_prefix_key == 1 {
-                    Ok((true, key, it))
-                } else {
-                    Ok((false, vec![], it))
-                }
-            }
-            Err(e) => Err(Error::CustomError(format!(
-                "Error accessing bit in D-IsPrefixKey: {}",
-                e
-            ))),
+        if options.root_table_bytes > 2 {
+            return Err(Error::CustomError(format!(
+                "Root tables are indexed by at most 2 bytes, got {}",
+                options.root_table_bytes
+            )));
+        }
+
+        let symbols = SymbolMap::new(&options.alphabet);
+        let mut dense_builder = Builder::new(options.memory_limit, width.unwrap(), symbols.width());
+        dense_builder.root_table_bytes = options.root_table_bytes as usize;
+        dense_builder.layout = options.layout;
+        match options.path_compression {
+            true => dense_builder.build_compressed(truncated)?,
+            false => dense_builder.build(truncated)?,
         }
-    }
 
-    pub fn get_or_next(&mut self, key: Vec<u8>) -> Result<(Vec<u8>, Iterator), Error> {
-        let (exists, matched_key, mut it) = self.get(key)?;
+        let mut surf = Surf {
+            options,
+            levels: dense_builder.level_nodes,
+            symbols,
+            dense_labels: dense_builder.labels,
+            dense_labels_ranks: dense_builder.labels_ranks,
+            dense_has_child: dense_builder.has_child,
+            dense_has_child_ranks: dense_builder.has_child_ranks,
+            dense_is_prefix_key: dense_builder.is_prefix_key,
+            dense_is_prefix_key_ranks: dense_builder.is_prefix_key_ranks,
+            dense_has_chain: dense_builder.has_chain,
+            dense_has_chain_ranks: dense_builder.has_chain_ranks,
+            chain_ends: dense_builder.chain_ends,
+            chain_labels: dense_builder.chain_labels,
+            root_table: Vec::new(),
+            records: Vec::new(),
+            suffixes: Vec::new(),
+        };
+        surf.root_table = root_table::build(&surf.trie(), surf.options.root_table_bytes as usize)?;
+        let nodes = surf.levels.iter().sum();
+        surf.records = layout::build(&surf.trie(), surf.options.layout, nodes)?;
+        surf.suffixes = suffix::encode(
+            &surf.trie(),
+            keys,
+            truncated,
+            surf.options.hash_bits,
+            surf.options.real_bits,
+        )?;
 
-        if exists {
-            it.next_edge += 1;
-            Ok((matched_key, it))
-        } else {
-            match it.next_key() {
-                Ok(larger_key) => Ok((larger_key, it)),
-                Err(e) => Err(e),
-            }
+        Ok(surf)
+    }
+
+    // trie returns a read-only view of the filter's tree, which answers all
+    // queries.
+    pub(crate) fn trie(&self) -> Trie<'_> {
+        Trie {
+            labels: self.dense_labels.frozen(&self.dense_labels_ranks),
+            has_child: self.dense_has_child.frozen(&self.dense_has_child_ranks),
+            is_prefix_key: self
+                .dense_is_prefix_key
+                .frozen(&self.dense_is_prefix_key_ranks),
+            suffixes: Suffixes {
+                words: &self.suffixes,
+                hash_bits: self.options.hash_bits,
+                real_bits: self.options.real_bits,
+                symbol_bits: suffix::symbol_bits(self.symbols.width()),
+            },
+            symbols: self.symbols,
+            chains: Chains {
+                has_chain: self.dense_has_chain.frozen(&self.dense_has_chain_ranks),
+                ends: &self.chain_ends,
+                labels: &self.chain_labels,
+            },
+            root_table: RootTable {
+                bytes: self.options.root_table_bytes as usize,
+                words: &self.root_table,
+            },
+            records: Records::new(
+                self.options.layout,
+                self.symbols.width(),
+                layout::words(&self.records),
+            ),
         }
     }
 
-    pub fn range(&mut self, low: Vec<u8>, high: Vec<u8>) -> Result<bool, Error> {
-        let (matched_key, _) = self.get_or_next(low)?;
+    // options returns the options the filter was built with.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
 
-        if matched_key <= high {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+    // height returns the number of levels of the trie.
+    pub fn height(&self) -> usize {
+        self.levels.len()
     }
 
-    pub fn count(&mut self, low: Vec<u8>, high: Vec<u8>) -> Result<usize, Error> {
-        let (matched_key, mut it) = self.get_or_next(low)?;
+    // check_transform fails with Error::TransformMismatch unless the filter
+    // was built with the given key transform, e.g. to make sure a loaded
+    // filter normalizes keys the way its users expect.
+    pub fn check_transform(&self, expected: &KeyTransform) -> Result<(), Error> {
+        check_transform(&self.options, expected)
+    }
 
-        let mut count = 0;
-        let high_key = high;
-        let mut cur_key = matched_key;
-        while cur_key <= high_key {
-            count += 1;
+    pub fn get(&self, key: Vec<u8>) -> Result<(bool, Vec<u8>, Iterator<'_>), Error> {
+        get(self.trie(), &self.options, key)
+    }
 
-            match it.next_key() {
-                Ok(next_key) => cur_key = next_key,
-                Err(_) => break,
-            }
-        }
+    pub fn get_or_next(&self, key: Vec<u8>) -> Result<(Vec<u8>, Iterator<'_>), Error> {
+        get_or_next(self.trie(), &self.options, key)
+    }
 
-        Ok(count)
+    pub fn range(&self, low: Vec<u8>, high: Vec<u8>) -> Result<bool, Error> {
+        let (low, high) = bounds(&self.trie(), &self.options, low, high);
+        self.trie().range(low, high)
+    }
+
+    pub fn count(&self, low: Vec<u8>, high: Vec<u8>) -> Result<usize, Error> {
+        let (low, high) = bounds(&self.trie(), &self.options, low, high);
+        self.trie().count(low, high)
     }
 }
 
+// SurfRef is a read-only filter which answers queries straight from a
+// serialized buffer, as written by Surf::write_to.
+//
+// The buffer is checked once when the SurfRef is created, after which no
+// part of it is copied. This allows e.g. memory-mapped filters to be queried
+// without loading them into memory first.
+pub struct SurfRef<'a> {
+    // options are the options the filter was built with.
+    pub(crate) options: Options,
+    // levels holds the number of nodes on each level of the trie.
+    pub(crate) levels: Vec<usize>,
+
+    pub(crate) trie: Trie<'a>,
+}
+
+impl<'a> SurfRef<'a> {
+    // options returns the options the filter was built with.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    // height returns the number of levels of the trie.
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    // check_transform fails with Error::TransformMismatch unless the filter
+    // was built with the given key transform, e.g. to make sure a loaded
+    // filter normalizes keys the way its users expect.
+    pub fn check_transform(&self, expected: &KeyTransform) -> Result<(), Error> {
+        check_transform(&self.options, expected)
+    }
+
+    pub fn get(&self, key: Vec<u8>) -> Result<(bool, Vec<u8>, Iterator<'a>), Error> {
+        get(self.trie, &self.options, key)
+    }
+
+    pub fn get_or_next(&self, key: Vec<u8>) -> Result<(Vec<u8>, Iterator<'a>), Error> {
+        get_or_next(self.trie, &self.options, key)
+    }
+
+    pub fn range(&self, low: Vec<u8>, high: Vec<u8>) -> Result<bool, Error> {
+        let (low, high) = bounds(&self.trie, &self.options, low, high);
+        self.trie.range(low, high)
+    }
+
+    pub fn count(&self, low: Vec<u8>, high: Vec<u8>) -> Result<usize, Error> {
+        let (low, high) = bounds(&self.trie, &self.options, low, high);
+        self.trie.count(low, high)
+    }
+}
+
+// prepare transforms the given raw keys and maps them to symbols, resolving
+// the alphabet of the given options on the way, and returns them sorted and
+// deduplicated, as Surf::build takes them.
+pub(crate) fn prepare(raw_keys: Vec<Vec<u8>>, options: &mut Options) -> Result<Vec<Key>, Error> {
+    let keys: Vec<Key> = raw_keys
+        .into_iter()
+        .map(|k| options.transform.apply(k))
+        .collect();
+
+    // Keys are stored as symbols of their alphabet, which resolve made sure
+    // they hold nothing but.
+    options.alphabet = options.alphabet.resolve(&keys)?;
+    let symbols = SymbolMap::new(&options.alphabet);
+    let mut keys: Vec<Key> = keys
+        .into_iter()
+        .map(|k| symbols.encode(k).unwrap())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    Ok(keys)
+}
+
+// get transforms the given key and maps it to symbols before looking it up,
+// and maps the stored key found back to bytes. A key holding a byte outside
+// the alphabet can not be stored.
+fn get<'a>(
+    trie: Trie<'a>,
+    options: &Options,
+    key: Vec<u8>,
+) -> Result<(bool, Vec<u8>, Iterator<'a>), Error> {
+    let Some(key) = trie.symbols.encode(options.transform.apply(key)) else {
+        return Ok((false, vec![], Iterator::new(trie)));
+    };
+
+    let (exists, matched_key, it) = trie.get(key)?;
+    Ok((exists, trie.symbols.decode(matched_key), it))
+}
+
+fn get_or_next<'a>(
+    trie: Trie<'a>,
+    options: &Options,
+    key: Vec<u8>,
+) -> Result<(Vec<u8>, Iterator<'a>), Error> {
+    let key = trie.symbols.encode_low(options.transform.apply(key));
+    let (key, it) = trie.get_or_next(key)?;
+    Ok((trie.symbols.decode(key), it))
+}
+
+// bounds returns the given range bounds, transformed and mapped to symbols.
+pub(crate) fn bounds(
+    trie: &Trie,
+    options: &Options,
+    low: Vec<u8>,
+    high: Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    let transform = &options.transform;
+    (
+        trie.symbols.encode_low(transform.apply(low)),
+        trie.symbols.encode_high(transform.apply(high)),
+    )
+}
+
+fn check_transform(options: &Options, expected: &KeyTransform) -> Result<(), Error> {
+    if options.transform != *expected {
+        return Err(Error::TransformMismatch {
+            stored: options.transform.clone(),
+            expected: expected.clone(),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::layout::DenseLayout;
+    use crate::testing::{assert_same_answers, random, Xorshift};
 
     #[test]
     fn test_get() {
@@ -141,15 +328,16 @@ mod tests {
             vec![0xFF, 0x42, 0x70, 0x71],
         ];
 
-        let mut surf = match Surf::new(keys.clone(), Options::new()) {
+        let surf = match Surf::new(keys.clone(), Options::new()) {
             Ok(surf) => surf,
             Err(e) => panic!("Error creating SuRF store: {:?}", e),
         };
+        assert_eq!(surf.verify(), Ok(()));
 
         for k in &keys {
             match surf.get(k.clone()) {
                 Ok(exists) => {
-                    assert_eq!(exists.0, true);
+                    assert!(exists.0);
                 }
                 Err(e) => panic!("Error looking up key: {:?}", e),
             }
@@ -160,10 +348,529 @@ mod tests {
         for k in non_existent_keys {
             match surf.get(k) {
                 Ok(exists) => {
-                    assert_eq!(exists.0, false);
+                    assert!(!exists.0);
                 }
                 Err(e) => panic!("Error looking up key: {:?}", e),
             }
         }
     }
+
+    fn get_range_keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    #[test]
+    fn test_range() {
+        let surf = Surf::new(get_range_keys(), Options::new()).unwrap();
+
+        let ranges: Vec<(&[u8], &[u8], bool)> = vec![
+            (b"a", b"b", false),
+            (b"a", b"f", true),
+            (b"fa", b"fb", true),
+            (b"g", b"r", false),
+            (b"g", b"s", true),
+            (b"tp", b"tq", false),
+            (b"tp", b"ts", true),
+            (b"u", b"z", false),
+        ];
+        for (low, high, expected) in ranges {
+            assert_eq!(
+                surf.range(low.to_vec(), high.to_vec()),
+                Ok(expected),
+                "range {:?}..={:?}",
+                low,
+                high
+            );
+        }
+
+        let empty = Surf::new(vec![], Options::new()).unwrap();
+        assert_eq!(empty.range(b"a".to_vec(), b"z".to_vec()), Ok(false));
+    }
+
+    #[test]
+    fn test_count() {
+        let surf = Surf::new(get_range_keys(), Options::new()).unwrap();
+
+        // Keys are counted as truncated, i.e. fas and tr.
+        let ranges: Vec<(&[u8], &[u8], usize)> = vec![
+            (b"", b"z", 7),
+            (b"f", b"f", 1),
+            (b"f", b"fz", 3),
+            (b"far", b"far", 1),
+            (b"t", b"u", 3),
+            (b"u", b"z", 0),
+        ];
+        for (low, high, expected) in ranges {
+            assert_eq!(
+                surf.count(low.to_vec(), high.to_vec()),
+                Ok(expected),
+                "count {:?}..={:?}",
+                low,
+                high
+            );
+        }
+    }
+
+    #[test]
+    fn test_next_key() {
+        // Edges on either side of word boundaries, and nodes holding a key
+        // of their own.
+        let keys: Vec<Vec<u8>> = vec![
+            vec![0x00],
+            vec![0x3F],
+            vec![0x40],
+            vec![0x40, 0x00],
+            vec![0x40, 0xFF],
+            vec![0x7F],
+            vec![0x80],
+            vec![0xBF, 0x3F],
+            vec![0xBF, 0xC0],
+            vec![0xFF],
+        ];
+        let surf = Surf::new(keys.clone(), Options::new()).unwrap();
+
+        let (first, mut it) = surf.get_or_next(vec![]).unwrap();
+        let mut stored = vec![first];
+        while let Ok(key) = it.next_key() {
+            stored.push(key);
+        }
+        assert_eq!(stored, keys);
+        assert_eq!(it.next_key(), Err(Error::EndOfTrie));
+
+        let (next, mut it) = surf.get_or_next(vec![0x40, 0x01]).unwrap();
+        assert_eq!(next, vec![0x40, 0xFF]);
+        assert_eq!(it.next_key(), Ok(vec![0x7F]));
+    }
+
+    #[test]
+    fn test_prev_key() {
+        let mut keys = vec![b"".to_vec(), b"tenant/".to_vec()];
+        for r in random(200, 0x2545F4914F6CDD1D) {
+            keys.push(format!("tenant/{:05}/object/{}", r % 40, r % 7919).into_bytes());
+            keys.push(r.to_be_bytes()[..(r % 4) as usize + 1].to_vec());
+        }
+
+        for (path_compression, layout) in [
+            (false, DenseLayout::Bitmaps),
+            (true, DenseLayout::Bitmaps),
+            (false, DenseLayout::Interleaved),
+        ] {
+            let options = Options {
+                path_compression,
+                layout,
+                root_table_bytes: 1,
+                ..Options::new()
+            };
+            let surf = Surf::new(keys.clone(), options).unwrap();
+
+            // Stepping back from the end returns all keys of a forward scan,
+            // in reverse.
+            let (first, mut it) = surf.get_or_next(vec![]).unwrap();
+            let mut stored = vec![first];
+            while let Ok(key) = it.next_key() {
+                stored.push(key);
+            }
+            let mut reversed = vec![];
+            while let Ok(key) = it.prev_key() {
+                reversed.push(key);
+            }
+            reversed.reverse();
+            assert_eq!(reversed, stored);
+            assert_eq!(it.prev_key(), Err(Error::EndOfTrie));
+            assert_eq!(it.next_key(), Ok(stored[0].clone()));
+
+            // prev_key returns the key next_key returned last, and the one
+            // before it next.
+            for (i, key) in stored.iter().enumerate().skip(1) {
+                let (found, mut it) = surf.get_or_next(key.clone()).unwrap();
+                assert_eq!(&found, key);
+                assert_eq!(it.prev_key().as_ref(), Ok(key));
+                assert_eq!(it.prev_key().as_ref(), Ok(&stored[i - 1]));
+                assert_eq!(it.next_key().as_ref(), Ok(&stored[i - 1]));
+                assert_eq!(it.next_key().as_ref(), Ok(key));
+            }
+        }
+    }
+
+    #[test]
+    fn test_suffixes() {
+        let options = |hash_bits, real_bits| Options {
+            hash_bits,
+            real_bits,
+            ..Options::new()
+        };
+
+        // fast is truncated to fas, so only its real suffix tells it apart
+        // from fase.
+        let surf = Surf::new(get_range_keys(), options(0, 8)).unwrap();
+        assert!(surf.get(b"fast".to_vec()).unwrap().0);
+        assert!(!surf.get(b"fase".to_vec()).unwrap().0);
+        assert_eq!(surf.range(b"fasu".to_vec(), b"fasz".to_vec()), Ok(false));
+        assert_eq!(surf.range(b"fasa".to_vec(), b"fase".to_vec()), Ok(false));
+        assert_eq!(surf.range(b"fase".to_vec(), b"fasu".to_vec()), Ok(true));
+
+        let surf = Surf::new(get_range_keys(), options(8, 0)).unwrap();
+        assert!(surf.get(b"fast".to_vec()).unwrap().0);
+        assert!(!surf.get(b"fase".to_vec()).unwrap().0);
+
+        assert!(Surf::new(get_range_keys(), options(32, 33)).is_err());
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut random = Xorshift::new(0x2545F4914F6CDD1D);
+        let mut random_key = move || {
+            let r = random.next_u64();
+            let len = (r % 6) as usize;
+            (0..len)
+                .map(|i| b"abc"[((r >> (8 + 2 * i)) % 3) as usize])
+                .collect::<Vec<u8>>()
+        };
+
+        let keys: Vec<Vec<u8>> = (0..300).map(|_| random_key()).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        for (hash_bits, real_bits) in [(0, 0), (4, 0), (0, 4), (4, 4), (3, 13), (64, 0), (0, 64)] {
+            let options = Options {
+                hash_bits,
+                real_bits,
+                ..Options::new()
+            };
+            let surf = Surf::new(keys.clone(), options).unwrap();
+            assert_eq!(surf.verify(), Ok(()));
+
+            for k in &keys {
+                assert!(surf.get(k.clone()).unwrap().0, "{:?}", k);
+            }
+
+            for _ in 0..300 {
+                let (a, b) = (random_key(), random_key());
+                let (low, high) = if a <= b { (a, b) } else { (b, a) };
+
+                let first = sorted.partition_point(|k| *k < low);
+                if first < sorted.len() && sorted[first] <= high {
+                    assert_eq!(
+                        surf.range(low.clone(), high.clone()),
+                        Ok(true),
+                        "{:?}..={:?} with {} hash and {} real bits",
+                        low,
+                        high,
+                        hash_bits,
+                        real_bits
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        let options = Options {
+            hash_bits: 0,
+            real_bits: 8,
+            transform: KeyTransform::Chain(vec![
+                KeyTransform::Prefix(4),
+                KeyTransform::AsciiLowercase,
+            ]),
+            ..Options::new()
+        };
+        let keys = vec![b"FAST".to_vec(), b"fastest".to_vec(), b"Trie".to_vec()];
+        let surf = Surf::new(keys, options).unwrap();
+        assert_eq!(surf.count(vec![], vec![0xFF]), Ok(2));
+
+        // Queries are transformed just like the keys were.
+        for k in [&b"fast"[..], b"FASTER", b"trie", b"TRIES"] {
+            assert!(surf.get(k.to_vec()).unwrap().0, "{:?}", k);
+        }
+        assert!(!surf.get(b"TX".to_vec()).unwrap().0);
+        assert_eq!(surf.range(b"FASTA".to_vec(), b"FAT".to_vec()), Ok(true));
+        assert_eq!(surf.range(b"TS".to_vec(), b"TZ".to_vec()), Ok(false));
+        assert_eq!(surf.get_or_next(b"G".to_vec()).unwrap().0, b"t".to_vec());
+    }
+
+    #[test]
+    fn test_alphabet() {
+        let mut random = Xorshift::new(0x9E3779B97F4A7C15);
+        let mut random_key = |digits: &[u8]| {
+            let r = random.next_u64();
+            (0..(r % 9) as usize)
+                .map(|i| digits[((r >> (8 + 6 * i)) % digits.len() as u64) as usize])
+                .collect::<Vec<u8>>()
+        };
+
+        let hex = b"0123456789abcdef";
+        let keys: Vec<Vec<u8>> = (0..500).map(|_| random_key(hex)).collect();
+        let options = |alphabet| Options {
+            hash_bits: 0,
+            real_bits: 0,
+            alphabet,
+            ..Options::new()
+        };
+        let bytes = Surf::new(keys.clone(), options(Alphabet::Bytes)).unwrap();
+        let detected = Surf::new(keys.clone(), options(Alphabet::Detect)).unwrap();
+        assert_eq!(detected.options.alphabet, Alphabet::Symbols(hex.to_vec()));
+        assert_eq!(detected.verify(), Ok(()));
+
+        // Nodes hold 16 rather than 256 bits.
+        let nodes: usize = detected.levels.iter().sum();
+        let (b, d) = (bytes.stats().memory, detected.stats().memory);
+        assert_eq!(b.labels, 32 * nodes);
+        assert_eq!(d.labels, 8 * (16 * nodes).div_ceil(64));
+        assert_eq!(d.has_child, d.labels);
+
+        // Without suffixes, both filters store the same truncated keys, so
+        // they answer range queries alike, also for bounds holding bytes
+        // outside the alphabet.
+        let queries = b"0123456789abcdef/:g";
+        for _ in 0..1000 {
+            let (a, b) = (random_key(queries), random_key(queries));
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+
+            // A key holding a byte outside the alphabet can not be stored,
+            // even if it extends a stored truncated key.
+            let exists = low.iter().all(|b| hex.contains(b)) && bytes.get(low.clone()).unwrap().0;
+            assert_eq!(detected.get(low.clone()).unwrap().0, exists, "{:?}", low);
+            let next = |surf: &Surf| surf.get_or_next(low.clone()).map(|(key, _)| key);
+            assert_eq!(next(&detected), next(&bytes), "{:?}", low);
+            assert_eq!(
+                detected.range(low.clone(), high.clone()),
+                bytes.range(low.clone(), high.clone()),
+                "{:?}..={:?}",
+                low,
+                high
+            );
+            assert_eq!(
+                detected.count(low.clone(), high.clone()),
+                bytes.count(low.clone(), high.clone()),
+                "{:?}..={:?}",
+                low,
+                high
+            );
+        }
+
+        // Iterators hand out keys of bytes.
+        let (first, mut it) = detected.get_or_next(vec![]).unwrap();
+        let mut stored = vec![first];
+        while let Ok(key) = it.next_key() {
+            stored.push(key);
+        }
+        assert_eq!(stored.len(), detected.stats().keys);
+        assert!(stored.iter().flatten().all(|b| hex.contains(b)));
+
+        // Suffixes still rule out keys, and never rule out stored ones.
+        let dna = Surf::new(
+            vec![b"GATTACA".to_vec(), b"GATTAGA".to_vec(), b"CAT".to_vec()],
+            Options {
+                alphabet: Alphabet::Symbols(b"ACGT".to_vec()),
+                ..Options::new()
+            },
+        )
+        .unwrap();
+        for k in [&b"GATTACA"[..], b"GATTAGA", b"CAT"] {
+            assert!(dna.get(k.to_vec()).unwrap().0, "{:?}", k);
+        }
+        assert!(!dna.get(b"GATTANA".to_vec()).unwrap().0);
+        assert_eq!(dna.range(b"D".to_vec(), b"F".to_vec()), Ok(false));
+        assert_eq!(
+            dna.range(b"GATTACB".to_vec(), b"GATTAGZ".to_vec()),
+            Ok(true)
+        );
+
+        let options = Options {
+            alphabet: Alphabet::Symbols(b"ACGT".to_vec()),
+            ..Options::new()
+        };
+        assert!(Surf::new(vec![b"GAUTACA".to_vec()], options).is_err());
+    }
+
+    #[test]
+    fn test_path_compression() {
+        let mut keys = vec![b"tenant/".to_vec()];
+        for r in random(300, 0xD1B54A32D192ED03) {
+            keys.push(format!("tenant/{:05}/object/{}", r % 40, r % 7919).into_bytes());
+            keys.push(format!("https://example.com/{}/index.html", r % 50).into_bytes());
+        }
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        let queries = [
+            &b""[..],
+            b"a",
+            b"https://",
+            b"https://example.com/",
+            b"https://example.com/7",
+            b"https://example.com/7/index.htm",
+            b"https://example.org",
+            b"tenant",
+            b"tenant/",
+            b"tenant/00",
+            b"tenant/00012/object/5",
+            b"tenant/00012/objects",
+            b"tenant/00039/object/~",
+            b"tenant/1",
+            b"z",
+        ];
+
+        for alphabet in [Alphabet::Bytes, Alphabet::Detect] {
+            let options = |path_compression| Options {
+                alphabet: alphabet.clone(),
+                path_compression,
+                ..Options::new()
+            };
+            let plain = Surf::new(keys.clone(), options(false)).unwrap();
+            let compressed = Surf::new(keys.clone(), options(true)).unwrap();
+            assert_eq!(compressed.verify(), Ok(()));
+            assert!(compressed.height() < plain.height());
+            assert!(compressed.stats().memory.total() < plain.stats().memory.total());
+
+            for k in &keys {
+                assert!(compressed.get(k.clone()).unwrap().0, "{:?}", k);
+            }
+
+            // Both filters store the same truncated keys and suffixes, so
+            // they answer all queries alike.
+            let ranges = queries
+                .iter()
+                .flat_map(|low| queries.iter().map(move |high| (*low, *high)))
+                .filter(|(low, high)| low <= high);
+            assert_same_answers(&compressed, &plain, ranges);
+
+            let (first, mut it) = compressed.get_or_next(vec![]).unwrap();
+            let mut stored = vec![first];
+            while let Ok(key) = it.next_key() {
+                stored.push(key);
+            }
+            assert_eq!(stored.len(), sorted.len());
+            for (stored, key) in stored.iter().zip(&sorted) {
+                assert!(key.starts_with(stored), "{:?} {:?}", stored, key);
+            }
+        }
+
+        // A single key is a single chain.
+        let options = Options {
+            path_compression: true,
+            ..Options::new()
+        };
+        let single = Surf::new(vec![b"tenant/00001".to_vec()], options).unwrap();
+        assert_eq!(single.height(), 1);
+        assert!(single.get(b"tenant/00001".to_vec()).unwrap().0);
+        assert!(!single.get(b"tenant/00002".to_vec()).unwrap().0);
+        assert_eq!(single.count(b"t".to_vec(), b"u".to_vec()), Ok(1));
+    }
+
+    #[test]
+    fn test_root_table() {
+        let (keys, queries) = abcz_keys(0x94D049BB133111EB);
+
+        for (alphabet, path_compression) in [
+            (Alphabet::Bytes, false),
+            (Alphabet::Detect, false),
+            (Alphabet::Bytes, true),
+        ] {
+            let options = |root_table_bytes| Options {
+                alphabet: alphabet.clone(),
+                path_compression,
+                root_table_bytes,
+                ..Options::new()
+            };
+            let plain = Surf::new(keys.clone(), options(0)).unwrap();
+
+            for bytes in [1, 2] {
+                let surf = Surf::new(keys.clone(), options(bytes)).unwrap();
+                assert_eq!(surf.verify(), Ok(()));
+
+                let width = surf.symbols.width();
+                let entries = match bytes {
+                    1 => width,
+                    _ => width + width * width,
+                };
+                assert_eq!(surf.stats().memory.root_table, 8 * entries.div_ceil(2));
+                assert_eq!(
+                    surf.stats().memory.total() - surf.stats().memory.root_table,
+                    plain.stats().memory.total()
+                );
+
+                // The table only takes shortcuts, so that all answers stay
+                // the same.
+                assert_same_answers(&surf, &plain, reversed_pairs(&queries));
+            }
+        }
+
+        let options = Options {
+            root_table_bytes: 3,
+            ..Options::new()
+        };
+        assert!(Surf::new(keys, options).is_err());
+    }
+
+    #[test]
+    fn test_interleaved_layout() {
+        let (keys, queries) = abcz_keys(0x2545F4914F6CDD1D);
+
+        for (alphabet, path_compression, root_table_bytes) in [
+            (Alphabet::Bytes, false, 0),
+            (Alphabet::Detect, false, 0),
+            (Alphabet::Bytes, true, 0),
+            (Alphabet::Bytes, false, 1),
+        ] {
+            let options = |layout| Options {
+                alphabet: alphabet.clone(),
+                path_compression,
+                root_table_bytes,
+                layout,
+                ..Options::new()
+            };
+            let plain = Surf::new(keys.clone(), options(DenseLayout::Bitmaps)).unwrap();
+            let surf = Surf::new(keys.clone(), options(DenseLayout::Interleaved)).unwrap();
+            assert_eq!(surf.verify(), Ok(()));
+
+            let nodes: usize = surf.levels.iter().sum();
+            let stride = layout::stride(DenseLayout::Interleaved, surf.symbols.width());
+            let memory = surf.stats().memory;
+            assert_eq!(memory.records, 8 * (nodes * stride).next_multiple_of(8));
+            assert_eq!(
+                memory.total() - memory.records,
+                plain.stats().memory.total()
+            );
+
+            // Records only store the bitmaps once more, so that all answers
+            // stay the same.
+            assert_same_answers(&surf, &plain, reversed_pairs(&queries));
+        }
+    }
+
+    // abcz_keys returns 400 keys and 400 queries of up to 6 bytes out of
+    // "abcz", as drawn from a xorshift generator with the given seed.
+    fn abcz_keys(seed: u64) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let mut random = Xorshift::new(seed);
+        let mut random_key = move || {
+            let r = random.next_u64();
+            (0..(r % 7) as usize)
+                .map(|i| b"abcz"[((r >> (8 + 2 * i)) % 4) as usize])
+                .collect::<Vec<u8>>()
+        };
+        let keys = (0..400).map(|_| random_key()).collect();
+        let queries = (0..400).map(|_| random_key()).collect();
+        (keys, queries)
+    }
+
+    // reversed_pairs pairs the given queries with those in reverse order,
+    // as the bounds of ranges.
+    fn reversed_pairs(queries: &[Vec<u8>]) -> impl std::iter::Iterator<Item = (&[u8], &[u8])> {
+        queries
+            .iter()
+            .zip(queries.iter().rev())
+            .map(|(low, high)| (&low[..], &high[..]))
+    }
 }
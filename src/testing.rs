@@ -0,0 +1,51 @@
+// Helpers shared by the tests of several modules.
+use crate::surf::Surf;
+
+// Xorshift is a xorshift64 generator, which tests draw pseudo-random keys and
+// queries from, so that they are the same on every run.
+pub(crate) struct Xorshift(u64);
+
+impl Xorshift {
+    // new returns a generator with the given seed, which must not be zero.
+    pub(crate) fn new(seed: u64) -> Xorshift {
+        Xorshift(seed)
+    }
+
+    // next_u64 returns the next pseudo-random number.
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// random returns n pseudo-random numbers, as generated by a xorshift
+// generator with the given seed.
+pub(crate) fn random(n: usize, seed: u64) -> Vec<u64> {
+    let mut random = Xorshift::new(seed);
+    (0..n).map(|_| random.next_u64()).collect()
+}
+
+// assert_same_answers asserts that the given filter answers get and
+// get_or_next for the lower bounds of all given ranges, and range and count
+// for the ranges themselves, just like the given plain filter does. Options
+// which only change how the trie is stored, rather than which truncated keys
+// and suffixes it holds, must not change any answer.
+pub(crate) fn assert_same_answers<'a>(
+    surf: &Surf,
+    plain: &Surf,
+    ranges: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+) {
+    for (low, high) in ranges {
+        let get = |s: &Surf| s.get(low.to_vec()).map(|(exists, key, _)| (exists, key));
+        assert_eq!(get(surf), get(plain), "{:?}", low);
+        let next = |s: &Surf| s.get_or_next(low.to_vec()).map(|(key, _)| key);
+        assert_eq!(next(surf), next(plain), "{:?}", low);
+
+        let range = |s: &Surf| s.range(low.to_vec(), high.to_vec());
+        assert_eq!(range(surf), range(plain), "{:?}..={:?}", low, high);
+        let count = |s: &Surf| s.count(low.to_vec(), high.to_vec());
+        assert_eq!(count(surf), count(plain), "{:?}..={:?}", low, high);
+    }
+}
@@ -0,0 +1,294 @@
+// Transforms normalizing keys before they are stored or looked up.
+//
+// A filter applies the transform of its options to every key handed to
+// Surf::new, and to every key and bound handed to its queries. Both sides
+// thus always agree, and the transform is part of the serialized filter, so
+// that a filter loaded elsewhere keeps applying it.
+//
+// Queries are answered over transformed keys: get(k) asks whether any stored
+// key has the same transform as k, and range(low, high) whether any
+// transformed key lies between the transformed bounds. For transforms which
+// preserve the order of keys, such as Prefix, range queries never miss a key
+// of the original range. Lowercasing and case folding do not preserve it, e.g.
+// "B" < "a" but "b" > "a", so ranges should then be thought of in terms of
+// lowercase or folded keys.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::casefold::CASE_FOLDING;
+use crate::iterator::Error;
+
+// MAX_DEPTH is the maximum nesting of chains accepted by decode, which keeps
+// malformed filters from exhausting the stack.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyTransform {
+    // Identity leaves keys as they are.
+    #[default]
+    Identity,
+    // AsciiLowercase maps the ASCII letters A-Z to a-z, leaving all other
+    // bytes as they are.
+    AsciiLowercase,
+    // CaseFold applies full Unicode case folding to keys which are valid
+    // UTF-8, so that keys differing only in case, e.g. "STRASSE" and
+    // "straße", have the same transform. Keys which are not valid UTF-8 are
+    // lowercased as ASCII.
+    CaseFold,
+    // Prefix keeps the first n bytes of keys, e.g. a fixed-length tenant or
+    // table id.
+    Prefix(usize),
+    // Chain applies the given transforms one after another.
+    Chain(Vec<KeyTransform>),
+}
+
+impl KeyTransform {
+    // apply returns the transform of the given key.
+    pub fn apply(&self, mut key: Vec<u8>) -> Vec<u8> {
+        match self {
+            KeyTransform::Identity => key,
+            KeyTransform::AsciiLowercase => {
+                key.make_ascii_lowercase();
+                key
+            }
+            KeyTransform::CaseFold => match String::from_utf8(key) {
+                Ok(s) => case_fold(&s).into_bytes(),
+                Err(e) => {
+                    let mut key = e.into_bytes();
+                    key.make_ascii_lowercase();
+                    key
+                }
+            },
+            KeyTransform::Prefix(n) => {
+                key.truncate(*n);
+                key
+            }
+            KeyTransform::Chain(transforms) => transforms.iter().fold(key, |k, t| t.apply(k)),
+        }
+    }
+
+    // encode returns the binary encoding of the transform, which is stored in
+    // the header of serialized filters.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            KeyTransform::Identity => out.push(0),
+            KeyTransform::AsciiLowercase => out.push(1),
+            KeyTransform::CaseFold => out.push(2),
+            KeyTransform::Prefix(n) => {
+                out.push(3);
+                out.extend_from_slice(&(*n as u64).to_le_bytes());
+            }
+            KeyTransform::Chain(transforms) => {
+                out.push(4);
+                out.extend_from_slice(&(transforms.len() as u64).to_le_bytes());
+                for t in transforms {
+                    t.encode_into(out);
+                }
+            }
+        }
+    }
+
+    // decode returns the transform of the given encoding, or None if it is
+    // malformed.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<KeyTransform> {
+        let mut bytes = bytes;
+        let transform = decode_from(&mut bytes, 0)?;
+        bytes.is_empty().then_some(transform)
+    }
+}
+
+// case_fold returns the full case folding of the given string, which folds
+// each character on its own.
+fn case_fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match CASE_FOLDING.binary_search_by_key(&c, |(c, _)| *c) {
+            Ok(i) => folded.push_str(CASE_FOLDING[i].1),
+            Err(_) => folded.push(c),
+        }
+    }
+    folded
+}
+
+fn decode_from(bytes: &mut &[u8], depth: usize) -> Option<KeyTransform> {
+    let (tag, rest) = bytes.split_first()?;
+    *bytes = rest;
+
+    let mut read_u64 = || -> Option<u64> {
+        let (n, rest) = bytes.split_first_chunk::<8>()?;
+        *bytes = rest;
+        Some(u64::from_le_bytes(*n))
+    };
+
+    match tag {
+        0 => Some(KeyTransform::Identity),
+        1 => Some(KeyTransform::AsciiLowercase),
+        2 => Some(KeyTransform::CaseFold),
+        3 => Some(KeyTransform::Prefix(
+            usize::try_from(read_u64()?).unwrap_or(usize::MAX),
+        )),
+        4 if depth < MAX_DEPTH => {
+            // Every transform takes at least one byte, which bounds the count
+            // before anything is allocated for it.
+            let count = read_u64()?;
+            if count > bytes.len() as u64 {
+                return None;
+            }
+
+            let mut transforms = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                transforms.push(decode_from(bytes, depth + 1)?);
+            }
+            Some(KeyTransform::Chain(transforms))
+        }
+        _ => None,
+    }
+}
+
+// Transforms are written as they are parsed, e.g. "casefold,prefix:8" for a
+// chain case folding keys and keeping the first 8 bytes.
+impl fmt::Display for KeyTransform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyTransform::Identity => write!(f, "identity"),
+            KeyTransform::AsciiLowercase => write!(f, "ascii-lowercase"),
+            KeyTransform::CaseFold => write!(f, "casefold"),
+            KeyTransform::Prefix(n) => write!(f, "prefix:{}", n),
+            KeyTransform::Chain(transforms) => {
+                for (i, t) in transforms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for KeyTransform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<KeyTransform, Error> {
+        if s.contains(',') {
+            let transforms = s.split(',').map(str::parse).collect::<Result<_, _>>()?;
+            return Ok(KeyTransform::Chain(transforms));
+        }
+
+        match s {
+            "identity" => Ok(KeyTransform::Identity),
+            "ascii-lowercase" => Ok(KeyTransform::AsciiLowercase),
+            "casefold" => Ok(KeyTransform::CaseFold),
+            _ => s
+                .strip_prefix("prefix:")
+                .and_then(|n| n.parse().ok())
+                .map(KeyTransform::Prefix)
+                .ok_or_else(|| Error::CustomError(format!("Unknown key transform {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let apply = |t: &KeyTransform, key: &[u8]| t.apply(key.to_vec());
+
+        assert_eq!(apply(&KeyTransform::Identity, b"FaR"), b"FaR");
+        assert_eq!(
+            apply(&KeyTransform::AsciiLowercase, b"FaR\xC3\x89"),
+            b"far\xC3\x89"
+        );
+        assert_eq!(
+            apply(&KeyTransform::CaseFold, "FaRÉ".as_bytes()),
+            "faré".as_bytes()
+        );
+        // Full case folding goes beyond lowercasing.
+        for (key, folded) in [
+            ("STRASSE", "strasse"),
+            ("straße", "strasse"),
+            ("ẞ", "ss"),
+            ("ﬁ", "fi"),
+            ("ΣΊΣΥΦΟΣ", "σίσυφοσ"),
+            ("σίσυφος", "σίσυφοσ"),
+            ("ᏣᎳᎩ", "ᏣᎳᎩ"),
+            ("ꮳꮃꭹ", "ᏣᎳᎩ"),
+        ] {
+            assert_eq!(
+                apply(&KeyTransform::CaseFold, key.as_bytes()),
+                folded.as_bytes()
+            );
+        }
+        assert_eq!(apply(&KeyTransform::CaseFold, b"FaR\xFF"), b"far\xFF");
+        assert_eq!(apply(&KeyTransform::Prefix(2), b"far"), b"fa");
+        assert_eq!(apply(&KeyTransform::Prefix(8), b"far"), b"far");
+
+        let chain = KeyTransform::Chain(vec![KeyTransform::Prefix(2), KeyTransform::CaseFold]);
+        assert_eq!(apply(&chain, b"FAR"), b"fa");
+    }
+
+    #[test]
+    fn test_case_folding_table() {
+        assert!(CASE_FOLDING.windows(2).all(|w| w[0].0 < w[1].0));
+        // Folding is idempotent.
+        for (c, folded) in CASE_FOLDING {
+            assert_eq!(case_fold(folded), folded, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let transforms = [
+            KeyTransform::Identity,
+            KeyTransform::AsciiLowercase,
+            KeyTransform::CaseFold,
+            KeyTransform::Prefix(16),
+            KeyTransform::Chain(vec![]),
+            KeyTransform::Chain(vec![
+                KeyTransform::CaseFold,
+                KeyTransform::Chain(vec![KeyTransform::Prefix(4)]),
+            ]),
+        ];
+        for t in transforms {
+            assert_eq!(KeyTransform::decode(&t.encode()), Some(t));
+        }
+
+        assert_eq!(KeyTransform::decode(&[]), None);
+        assert_eq!(KeyTransform::decode(&[5]), None);
+        assert_eq!(KeyTransform::decode(&[0, 0]), None);
+        assert_eq!(KeyTransform::decode(&[3, 1]), None);
+        assert_eq!(KeyTransform::decode(&[4, 0xFF, 0, 0, 0, 0, 0, 0, 0]), None);
+
+        let mut nested = KeyTransform::Identity;
+        for _ in 0..=MAX_DEPTH {
+            nested = KeyTransform::Chain(vec![nested]);
+        }
+        assert_eq!(KeyTransform::decode(&nested.encode()), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let chain = KeyTransform::Chain(vec![KeyTransform::CaseFold, KeyTransform::Prefix(8)]);
+        assert_eq!(
+            "casefold,prefix:8".parse::<KeyTransform>(),
+            Ok(chain.clone())
+        );
+        assert_eq!(chain.to_string(), "casefold,prefix:8");
+
+        for t in ["identity", "ascii-lowercase", "casefold", "prefix:0"] {
+            assert_eq!(t.parse::<KeyTransform>().unwrap().to_string(), t);
+        }
+        assert!("prefix:x".parse::<KeyTransform>().is_err());
+        assert!("upper".parse::<KeyTransform>().is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp::Ordering;
+
+use crate::alphabet::SymbolMap;
+use crate::bitmap::BitmapRef;
+use crate::bitops::{next_one, prev_one};
+use crate::chain::Chains;
+use crate::iterator::{Error, Iterator};
+use crate::layout::Records;
+use crate::root_table::RootTable;
+use crate::suffix::Suffixes;
+
+// Trie is a read-only view of a LOUDS-DENSE encoded FST tree.
+//
+// Its nodes have one edge per symbol of its alphabet, and all keys it is
+// queried with are keys of symbols, as mapped by SymbolMap. Tries built with
+// path compression also hold chains, which keys reaching a node must follow
+// before its edges, and tries may have a root table leading straight to the
+// nodes of their first levels, as well as interleaved records of their nodes.
+// It borrows the words of its bitmaps, which allows the same queries to be
+// answered both by filters owning their bitmaps, and by filters referring to
+// a serialized buffer.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Trie<'a> {
+    pub(crate) labels: BitmapRef<'a>,
+    pub(crate) has_child: BitmapRef<'a>,
+    pub(crate) is_prefix_key: BitmapRef<'a>,
+    pub(crate) suffixes: Suffixes<'a>,
+    pub(crate) symbols: SymbolMap,
+    pub(crate) chains: Chains<'a>,
+    pub(crate) root_table: RootTable<'a>,
+    pub(crate) records: Records<'a>,
+}
+
+// Visit is a node visited by Trie::lookup_traced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Visit {
+    // level is the level of the node, and node its level-order id.
+    pub(crate) level: usize,
+    pub(crate) node: usize,
+    // depth is the number of symbols of the key preceding the chain of the
+    // node.
+    pub(crate) depth: usize,
+    // edge is the symbol of the edge looked up, along with its D-Labels and
+    // D-HasChild bits, or None if the key ended on the node, or diverged
+    // from its chain.
+    pub(crate) edge: Option<(u8, bool, bool)>,
+}
+
+// record appends the given visit to the given visits, if any.
+fn record(visits: &mut Option<&mut Vec<Visit>>, visit: Visit) {
+    if let Some(visits) = visits {
+        visits.push(visit);
+    }
+}
+
+impl<'a> Trie<'a> {
+    pub(crate) fn get(&self, key: Vec<u8>) -> Result<(bool, Vec<u8>, Iterator<'a>), Error> {
+        let (exists, matched_key, it) = self.lookup(&key)?;
+
+        // The truncated key being stored does not mean that the key itself
+        // is, which its suffix may tell.
+        if exists && !self.suffix_matches(&it, &key, &matched_key)? {
+            return Ok((false, vec![], it));
+        }
+
+        Ok((exists, matched_key, it))
+    }
+
+    // suffix_matches returns whether the suffix of the stored truncated key
+    // matched_key, which lookup left the given iterator at, is that of the
+    // given key. Without suffix bits, any key matches.
+    pub(crate) fn suffix_matches(
+        &self,
+        it: &Iterator,
+        key: &[u8],
+        matched_key: &[u8],
+    ) -> Result<bool, Error> {
+        if self.suffixes.width() == 0 {
+            return Ok(true);
+        }
+
+        let index = self.key_index(it, matched_key)?;
+        Ok(self.suffixes.matches(index, key, matched_key.len()))
+    }
+
+    // lookup walks the trie along the given key, and returns whether a
+    // truncated key which is a prefix of it is stored, disregarding suffixes.
+    pub(crate) fn lookup(&self, key: &[u8]) -> Result<(bool, Vec<u8>, Iterator<'a>), Error> {
+        self.lookup_traced(key, None)
+    }
+
+    // lookup_traced is lookup, which also appends the nodes it visits to the
+    // given visits, if any. Nodes the root table leads past are not visited.
+    pub(crate) fn lookup_traced(
+        &self,
+        key: &[u8],
+        mut visits: Option<&mut Vec<Visit>>,
+    ) -> Result<(bool, Vec<u8>, Iterator<'a>), Error> {
+        let mut it = Iterator::new(*self);
+
+        // An empty trie has no root node to start from.
+        if self.labels.is_empty() {
+            return Ok((false, vec![], it));
+        }
+
+        // The root table leads straight to the nodes of the first symbols,
+        // while symbols it does not hold take the regular path.
+        let mut i = 0;
+        let width = self.symbols.width();
+        while i < self.root_table.bytes.min(key.len()) {
+            match self.root_table.child(&key[..=i], width) {
+                Some(child) => it.enter(key[i], child)?,
+                None => break,
+            }
+            i += 1;
+        }
+
+        let mut level = i;
+        loop {
+            let mut visit = Visit {
+                level,
+                node: it.node_index,
+                depth: i,
+                edge: None,
+            };
+
+            // The key must follow the chain of the node first, and any key
+            // diverging from it lies before or past all keys of the node.
+            for offset in self.chains.get(it.node_index)? {
+                let label = self.chains.label(offset);
+                match key.get(i) {
+                    Some(b) if *b == label => i += 1,
+                    diverging => {
+                        record(&mut visits, visit);
+                        it.back_out(diverging.is_some_and(|b| *b > label))?;
+                        return Ok((false, vec![], it));
+                    }
+                }
+            }
+            if i == key.len() {
+                record(&mut visits, visit);
+                break;
+            }
+
+            let key_byte = key[i];
+            let child = it.go_to_child(key_byte);
+            visit.edge = Some((key_byte, child != Err(Error::NoSuchEdge), child.is_ok()));
+            record(&mut visits, visit);
+            match child {
+                Ok(_) => {}
+                Err(e) => {
+                    if e == Error::NoSuchEdge {
+                        // No edge with this value, so the key doesn't exist.
+                        return Ok((false, vec![], it));
+                    } else if e == Error::IsLeaf {
+                        // We attempted to enter a leaf node, so the key exists
+                        return Ok((true, key[..=i].to_vec(), it));
+                    } else {
+                        // Non-specific error, e.g. issue with bitmap access
+                        return Err(e);
+                    }
+                }
+            }
+            i += 1;
+            level += 1;
+        }
+
+        // If we get until here, then we traversed the whole key. To determine
+        // whether the key exists, we now must check if our current node has
+        // is_prefix_key set to true.
+        if !self.records.is_empty() {
+            return match self.records.is_prefix_key(it.node_index)? {
+                true => Ok((true, key.to_vec(), it)),
+                false => Ok((false, vec![], it)),
+            };
+        }
+        match self.is_prefix_key.get(it.node_index) {
+            Ok(is_prefix_key) => {
+                if is_prefix_key == 1 {
+                    Ok((true, key.to_vec(), it))
+                } else {
+                    Ok((false, vec![], it))
+                }
+            }
+            Err(e) => Err(Error::CustomError(format!(
+                "Error accessing bit in D-IsPrefixKey: {}",
+                e
+            ))),
+        }
+    }
+
+    pub(crate) fn get_or_next(&self, key: Vec<u8>) -> Result<(Vec<u8>, Iterator<'a>), Error> {
+        let found = self.lookup(&key)?;
+        self.next_from(&key, found)
+    }
+
+    // next_from finishes get_or_next for the given key, given what lookup
+    // found for it.
+    pub(crate) fn next_from(
+        &self,
+        key: &[u8],
+        (exists, matched_key, mut it): (bool, Vec<u8>, Iterator<'a>),
+    ) -> Result<(Vec<u8>, Iterator<'a>), Error> {
+        if exists {
+            // The stored key is only known to be lesser if its real suffix
+            // is.
+            let lesser = self.suffixes.real_bits > 0 && {
+                let index = self.key_index(&it, &matched_key)?;
+                self.suffixes.compare_real(index, key, matched_key.len()) == Ordering::Less
+            };
+
+            // A key ending on a leaf edge leaves the iterator on that edge,
+            // while a key ending on a node is followed by its children.
+            if matched_key.len() > it.key_prefix.len() {
+                it.next_edge += 1;
+            }
+
+            if !lesser {
+                return Ok((matched_key, it));
+            }
+        }
+
+        match it.next_symbols() {
+            Ok(larger_key) => Ok((larger_key, it)),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn range(&self, low: Vec<u8>, high: Vec<u8>) -> Result<bool, Error> {
+        match self.get_or_next(low) {
+            Ok((matched_key, it)) => self.at_most(&matched_key, &it, &high),
+            Err(Error::EndOfTrie) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // at_most returns whether the stored key with the truncated key
+    // matched_key, which the given iterator was left at, may be at most
+    // high.
+    pub(crate) fn at_most(
+        &self,
+        matched_key: &[u8],
+        it: &Iterator,
+        high: &[u8],
+    ) -> Result<bool, Error> {
+        if matched_key > high {
+            return Ok(false);
+        }
+
+        // A stored key sharing its truncated key with the upper bound may
+        // still be greater, which its real suffix may tell.
+        if self.suffixes.real_bits > 0 && high.starts_with(matched_key) {
+            let index = self.key_index(it, matched_key)?;
+            if self.suffixes.compare_real(index, high, matched_key.len()) == Ordering::Greater {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub(crate) fn count(&self, low: Vec<u8>, high: Vec<u8>) -> Result<usize, Error> {
+        let (matched_key, mut it) = match self.get_or_next(low) {
+            Ok(found) => found,
+            Err(Error::EndOfTrie) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut count = 0;
+        let high_key = high;
+        let mut cur_key = matched_key;
+        while cur_key <= high_key {
+            count += 1;
+
+            match it.next_symbols() {
+                Ok(next_key) => cur_key = next_key,
+                Err(_) => break,
+            }
+        }
+
+        Ok(count)
+    }
+
+    // check_key_width checks that the trie may hold keys of the given number
+    // of symbols only. Such keys are never prefixes of one another, so no key
+    // ends on a node, and none of them is truncated to more symbols.
+    pub(crate) fn check_key_width(&self, width: usize) -> Result<(), Error> {
+        let mismatch =
+            || Error::CustomError(format!("Filter holds keys of other than {} symbols", width));
+        if self.is_prefix_key.words().iter().any(|w| *w != 0) {
+            return Err(mismatch());
+        }
+
+        let (mut key, mut it) = match self.get_or_next(vec![]) {
+            Ok(found) => found,
+            Err(Error::EndOfTrie) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        loop {
+            if key.len() > width {
+                return Err(mismatch());
+            }
+            key = match it.next_symbols() {
+                Ok(key) => key,
+                Err(Error::EndOfTrie) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+        }
+    }
+
+    // child returns the node the edge of the given symbol of the given node
+    // leads to, read from the record of the node if the trie has records. It
+    // fails with NoSuchEdge for edges without a label, and with IsLeaf for
+    // edges without a child.
+    pub(crate) fn child(&self, node: usize, symbol: usize) -> Result<usize, Error> {
+        let width = self.symbols.width();
+        if symbol >= width {
+            return Err(Error::NoSuchEdge);
+        }
+        if !self.records.is_empty() {
+            return self.records.child(node, symbol);
+        }
+
+        let bit = width * node + symbol;
+        if self.labels.get(bit)? == 0 {
+            return Err(Error::NoSuchEdge);
+        }
+        if self.has_child.get(bit)? == 0 {
+            return Err(Error::IsLeaf);
+        }
+        Ok(self.has_child.rank(1, bit)?)
+    }
+
+    // next_label returns the least label of the given node which is no less
+    // than from. Edges without a label are skipped a word at a time, rather
+    // than probed one by one.
+    pub(crate) fn next_label(&self, node: usize, from: usize) -> Option<usize> {
+        let width = self.symbols.width();
+        let start = width * node + from.min(width);
+        next_one(self.labels.words(), start, width * node + width).map(|bit| bit - width * node)
+    }
+
+    // prev_label returns the greatest label of the given node which is less
+    // than before, skipping edges without a label a word at a time like
+    // next_label.
+    pub(crate) fn prev_label(&self, node: usize, before: usize) -> Option<usize> {
+        let width = self.symbols.width();
+        let end = width * node + before.min(width);
+        prev_one(self.labels.words(), width * node, end).map(|bit| bit - width * node)
+    }
+
+    // leaf_index returns the index of the key ending on the leaf edge of the
+    // given symbol of the given node.
+    pub(crate) fn leaf_index(&self, node: usize, symbol: usize) -> Result<usize, Error> {
+        let bit = self.symbols.width() * node + symbol;
+        let leaves_before = self.labels.rank(1, bit)? - self.has_child.rank(1, bit)?;
+        Ok(leaves_before + self.is_prefix_key.rank(1, node)? - 1)
+    }
+
+    // key_index returns the index of the stored key which the given iterator
+    // was left at, as returned by lookup or next_symbols.
+    //
+    // Keys are numbered in level order, with the key ending on a node coming
+    // before the keys ending on its leaf edges.
+    pub(crate) fn key_index(&self, it: &Iterator, key: &[u8]) -> Result<usize, Error> {
+        let node = it.node_index;
+        if key.len() > it.key_prefix.len() {
+            return self.leaf_index(node, *key.last().unwrap() as usize);
+        }
+
+        // Keys ending on leaf edges are edges with a label, but no child.
+        let leaves = match node {
+            0 => 0,
+            _ => {
+                let bit = self.symbols.width() * node - 1;
+                self.labels.rank(1, bit)? - self.has_child.rank(1, bit)?
+            }
+        };
+        Ok(leaves + self.is_prefix_key.rank(1, node)? - 1)
+    }
+}
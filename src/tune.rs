@@ -0,0 +1,179 @@
+use crate::fpr::{evaluate, Query};
+use crate::iterator::Error;
+use crate::key::{truncate, Key};
+use crate::options::Options;
+use crate::suffix;
+use crate::surf::Surf;
+
+impl Options {
+    // tune_for returns the options with the fewest suffix bits for which a
+    // filter built from the given keys answers the sample queries with a
+    // false-positive rate of at most target_fpr, e.g. 0.01 for 1%.
+    //
+    // All splits of the suffix into hash and real bits are considered, which
+    // covers filters with hash suffixes, real suffixes or both. Among splits
+    // of the same size, the one with the most real bits wins, as those also
+    // serve range queries. To tune for range scans only, pass only range
+    // queries.
+    //
+    // The trie is the same for all suffixes, so it is built only once. As all
+    // levels are encoded using LOUDS-DENSE, R has no effect on the filter and
+    // is left at its default, as is the memory limit.
+    //
+    // If no configuration meets the target, Error::TargetUnreachable is
+    // returned along with the lowest false-positive rate found.
+    pub fn tune_for(
+        keys: &[Vec<u8>],
+        sample_queries: &[Query],
+        target_fpr: f64,
+    ) -> Result<Options, Error> {
+        let mut keys: Vec<Key> = keys.to_vec();
+        keys.sort();
+        keys.dedup();
+
+        let truncated = truncate(&keys);
+        let mut surf = Surf::build(&keys, &truncated, with_suffix(0, 0))?;
+
+        let mut best_fpr = f64::INFINITY;
+        let mut fpr = |hash_bits: u32, real_bits: u32| -> Result<f64, Error> {
+            surf.suffixes = suffix::encode(&surf.trie(), &keys, &truncated, hash_bits, real_bits)?;
+            surf.options = with_suffix(hash_bits, real_bits);
+
+            let fpr = evaluate(&keys, &surf, sample_queries)?.all.fpr();
+            best_fpr = best_fpr.min(fpr);
+            Ok(fpr)
+        };
+
+        // Adding bits of either kind never raises the false-positive rate, so
+        // the fewest real bits meeting the target can only shrink as hash bits
+        // are added. This allows walking down the edge of the region of
+        // configurations which meet it, rather than searching all of them.
+        let mut best: Option<(u32, u32)> = None;
+        let mut real_bits = suffix::MAX_BITS;
+        for hash_bits in 0..=suffix::MAX_BITS {
+            if best.is_some_and(|(h, r)| hash_bits >= h + r) {
+                break;
+            }
+
+            real_bits = real_bits.min(suffix::MAX_BITS - hash_bits);
+            if fpr(hash_bits, real_bits)? > target_fpr {
+                continue;
+            }
+            while real_bits > 0 && fpr(hash_bits, real_bits - 1)? <= target_fpr {
+                real_bits -= 1;
+            }
+
+            if best.is_none_or(|(h, r)| hash_bits + real_bits < h + r) {
+                best = Some((hash_bits, real_bits));
+            }
+        }
+
+        match best {
+            Some((hash_bits, real_bits)) => Ok(with_suffix(hash_bits, real_bits)),
+            None => Err(Error::TargetUnreachable {
+                target: target_fpr,
+                best: best_fpr,
+            }),
+        }
+    }
+}
+
+// with_suffix returns the default options with the given suffix.
+fn with_suffix(hash_bits: u32, real_bits: u32) -> Options {
+    Options {
+        hash_bits,
+        real_bits,
+        ..Options::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fpr::analyze;
+    use crate::testing::random;
+
+    fn fpr(keys: &[Vec<u8>], queries: &[Query], hash_bits: u32, real_bits: u32) -> f64 {
+        let surf = Surf::new(keys.to_vec(), with_suffix(hash_bits, real_bits)).unwrap();
+        analyze(keys, &surf, queries).unwrap().all.fpr()
+    }
+
+    // check_tuned asserts that the tuned options meet the target, and that
+    // dropping any of their suffix bits does not.
+    fn check_tuned(keys: &[Vec<u8>], queries: &[Query], target: f64) -> Options {
+        let options = Options::tune_for(keys, queries, target).unwrap();
+        let (h, r) = (options.hash_bits, options.real_bits);
+
+        assert!(fpr(keys, queries, h, r) <= target);
+        if h > 0 {
+            assert!(fpr(keys, queries, h - 1, r) > target);
+        }
+        if r > 0 {
+            assert!(fpr(keys, queries, h, r - 1) > target);
+        }
+        options
+    }
+
+    #[test]
+    fn test_tune_for_points() {
+        let numbers = random(4_000, 0x2545F4914F6CDD1D);
+        let keys: Vec<Vec<u8>> = numbers[..2_000]
+            .iter()
+            .map(|x| x.to_be_bytes().to_vec())
+            .collect();
+        let queries: Vec<Query> = numbers[2_000..]
+            .iter()
+            .map(|x| Query::Point(x.to_be_bytes().to_vec()))
+            .collect();
+
+        let options = check_tuned(&keys, &queries, 0.01);
+        assert_eq!(options.r, Options::new().r);
+
+        // Anything meets a target of 100%.
+        let options = Options::tune_for(&keys, &queries, 1.0).unwrap();
+        assert_eq!((options.hash_bits, options.real_bits), (0, 0));
+    }
+
+    #[test]
+    fn test_tune_for_ranges() {
+        let numbers = random(4_000, 0x9E3779B97F4A7C15);
+        let keys: Vec<Vec<u8>> = numbers[..2_000]
+            .iter()
+            .map(|x| x.to_be_bytes().to_vec())
+            .collect();
+        let queries: Vec<Query> = numbers[2_000..]
+            .iter()
+            .map(|x| {
+                let low = x & !0xFFFF;
+                Query::Range(
+                    low.to_be_bytes().to_vec(),
+                    (low | 0xFF).to_be_bytes().to_vec(),
+                )
+            })
+            .collect();
+
+        // Hash bits do not serve range queries.
+        let options = check_tuned(&keys, &queries, 0.005);
+        assert_eq!(options.hash_bits, 0);
+        assert!(options.real_bits > 0);
+    }
+
+    #[test]
+    fn test_tune_for_unreachable() {
+        // The keys share more bytes past their truncated prefix than suffixes
+        // can hold, so no suffix tells them apart from the range.
+        let keys = vec![b"aaaaaaaaaaaaX1".to_vec(), b"b".to_vec()];
+        let queries = vec![Query::Range(
+            b"aaaaaaaaaaaaY0".to_vec(),
+            b"aaaaaaaaaaaaY9".to_vec(),
+        )];
+
+        assert_eq!(
+            Options::tune_for(&keys, &queries, 0.5),
+            Err(Error::TargetUnreachable {
+                target: 0.5,
+                best: 1.0
+            })
+        );
+    }
+}
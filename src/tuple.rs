@@ -0,0 +1,419 @@
+// Composite keys, encoded in the style of the FoundationDB tuple layer.
+//
+// A tuple is encoded as the concatenation of its elements, each of which
+// starts with a type code:
+//
+//   code       element
+//   0x01       byte string, 0x00 escaped as 0x00 0xFF, ending in 0x00 0x01
+//   0x02       UTF-8 string, encoded like a byte string
+//   0x0C-0x1C  integer of n = |code - 0x14| big-endian bytes. Negative
+//              integers are stored as the one's complement of their magnitude
+//   0x21       64-bit float, encoded like rsurf::encode does
+//   0x26       false
+//   0x27       true
+//
+// Descending elements are encoded the same way, with all bits inverted.
+// Unlike in FoundationDB, byte strings end in 0x00 0x01 rather than 0x00, so
+// that no element is a prefix of another, which keeps inverted elements in
+// reverse order even if they end the tuple.
+//
+// Elements of either order never start with 0x00 or 0xFF. Every key starting
+// with a partial tuple thus lies between the encoding of that tuple and the
+// same followed by 0xFF, which is what range returns.
+use crate::encode::KeyEncode;
+use crate::iterator::Error;
+
+const BYTES: u8 = 0x01;
+const STRING: u8 = 0x02;
+const INT_ZERO: u8 = 0x14;
+const FLOAT: u8 = 0x21;
+const FALSE: u8 = 0x26;
+const TRUE: u8 = 0x27;
+
+// Element is a single component of a tuple.
+//
+// Integers compare by value, whether signed or not, and are unpacked as Int
+// if they fit it. Elements of different types compare by type, in the order
+// of their type codes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Bytes(Vec<u8>),
+    String(String),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+}
+
+// Order is the order of a tuple component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+// Tuple is a composite key, consisting of elements which are each ordered
+// ascending or descending.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Tuple {
+    pub elements: Vec<(Element, Order)>,
+}
+
+impl Tuple {
+    pub fn new() -> Self {
+        Tuple {
+            elements: Vec::new(),
+        }
+    }
+
+    // asc appends an element which is ordered ascending.
+    pub fn asc(mut self, element: impl Into<Element>) -> Self {
+        self.elements.push((element.into(), Order::Ascending));
+        self
+    }
+
+    // desc appends an element which is ordered descending.
+    pub fn desc(mut self, element: impl Into<Element>) -> Self {
+        self.elements.push((element.into(), Order::Descending));
+        self
+    }
+
+    // pack returns the encoding of the tuple, which is the key to store.
+    //
+    // The encoding of a partial tuple is the exact prefix of the keys of all
+    // tuples starting with it.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (element, order) in &self.elements {
+            let start = out.len();
+            encode(element, &mut out);
+            if *order == Order::Descending {
+                out[start..].iter_mut().for_each(|b| *b = !*b);
+            }
+        }
+        out
+    }
+
+    // range returns the bounds to pass to Surf::range to find the keys of all
+    // tuples starting with this partial tuple.
+    pub fn range(&self) -> (Vec<u8>, Vec<u8>) {
+        let low = self.pack();
+        let mut high = low.clone();
+        high.push(0xFF);
+        (low, high)
+    }
+
+    // between returns the bounds to pass to Surf::range to find the keys of
+    // all tuples starting with a partial tuple between low and high, both
+    // inclusive.
+    pub fn between(low: &Tuple, high: &Tuple) -> (Vec<u8>, Vec<u8>) {
+        (low.pack(), high.range().1)
+    }
+
+    // unpack decodes a tuple encoded using pack.
+    pub fn unpack(bytes: &[u8]) -> Result<Tuple, Error> {
+        let mut tuple = Tuple::new();
+        let mut at = 0;
+        while at < bytes.len() {
+            // The type codes of descending elements are the inverse of those
+            // of ascending ones, and never collide with them.
+            let order = match bytes[at] {
+                0x80.. => Order::Descending,
+                _ => Order::Ascending,
+            };
+            let element: Vec<u8> = match order {
+                Order::Ascending => bytes[at..].to_vec(),
+                Order::Descending => bytes[at..].iter().map(|b| !b).collect(),
+            };
+
+            let (value, len) = decode(&element).ok_or_else(|| {
+                Error::CustomError(format!("Invalid tuple element at byte {}", at))
+            })?;
+            tuple.elements.push((value, order));
+            at += len;
+        }
+        Ok(tuple)
+    }
+}
+
+// encode appends the ascending encoding of the given element.
+fn encode(element: &Element, out: &mut Vec<u8>) {
+    match element {
+        Element::Bytes(b) => encode_bytes(BYTES, b, out),
+        Element::String(s) => encode_bytes(STRING, s.as_bytes(), out),
+        Element::Int(v) => encode_int(*v as i128, out),
+        Element::UInt(v) => encode_int(*v as i128, out),
+        Element::Float(v) => {
+            out.push(FLOAT);
+            out.extend_from_slice(&v.encode());
+        }
+        Element::Bool(false) => out.push(FALSE),
+        Element::Bool(true) => out.push(TRUE),
+    }
+}
+
+fn encode_bytes(code: u8, bytes: &[u8], out: &mut Vec<u8>) {
+    out.push(code);
+    for b in bytes {
+        out.push(*b);
+        if *b == 0x00 {
+            out.push(0xFF);
+        }
+    }
+    out.extend_from_slice(&[0x00, 0x01]);
+}
+
+fn encode_int(v: i128, out: &mut Vec<u8>) {
+    let magnitude = v.unsigned_abs();
+    let n = (128 - magnitude.leading_zeros()).div_ceil(8) as usize;
+
+    let stored = match v < 0 {
+        true => {
+            out.push(INT_ZERO - n as u8);
+            !magnitude
+        }
+        false => {
+            out.push(INT_ZERO + n as u8);
+            magnitude
+        }
+    };
+    out.extend_from_slice(&stored.to_be_bytes()[16 - n..]);
+}
+
+// decode decodes the ascending element the given bytes start with, and
+// returns it along with the length of its encoding.
+fn decode(bytes: &[u8]) -> Option<(Element, usize)> {
+    match *bytes.first()? {
+        BYTES => {
+            let (b, len) = decode_bytes(&bytes[1..])?;
+            Some((Element::Bytes(b), len + 1))
+        }
+        STRING => {
+            let (b, len) = decode_bytes(&bytes[1..])?;
+            Some((Element::String(String::from_utf8(b).ok()?), len + 1))
+        }
+        code @ 0x0C..=0x1C => {
+            let n = code.abs_diff(INT_ZERO) as usize;
+            let stored = bytes.get(1..=n)?;
+
+            let mut v = [0u8; 16];
+            v[16 - n..].copy_from_slice(stored);
+            let v = u128::from_be_bytes(v);
+            let value = match code < INT_ZERO {
+                true => -((v ^ ((1u128 << (8 * n)) - 1)) as i128),
+                false => v as i128,
+            };
+
+            let element = match i64::try_from(value) {
+                Ok(v) => Element::Int(v),
+                Err(_) => Element::UInt(u64::try_from(value).ok()?),
+            };
+            Some((element, n + 1))
+        }
+        FLOAT => Some((Element::Float(f64::decode(bytes.get(1..9)?)?), 9)),
+        FALSE => Some((Element::Bool(false), 1)),
+        TRUE => Some((Element::Bool(true), 1)),
+        _ => None,
+    }
+}
+
+// decode_bytes decodes an escaped byte string, and returns it along with the
+// length of its encoding.
+fn decode_bytes(bytes: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    loop {
+        match *bytes.get(i)? {
+            0x00 => match *bytes.get(i + 1)? {
+                0xFF => out.push(0x00),
+                0x01 => return Some((out, i + 2)),
+                _ => return None,
+            },
+            b => {
+                out.push(b);
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+}
+
+impl From<Vec<u8>> for Element {
+    fn from(v: Vec<u8>) -> Element {
+        Element::Bytes(v)
+    }
+}
+
+impl From<&[u8]> for Element {
+    fn from(v: &[u8]) -> Element {
+        Element::Bytes(v.to_vec())
+    }
+}
+
+impl From<String> for Element {
+    fn from(v: String) -> Element {
+        Element::String(v)
+    }
+}
+
+impl From<&str> for Element {
+    fn from(v: &str) -> Element {
+        Element::String(v.to_string())
+    }
+}
+
+impl From<i64> for Element {
+    fn from(v: i64) -> Element {
+        Element::Int(v)
+    }
+}
+
+impl From<i32> for Element {
+    fn from(v: i32) -> Element {
+        Element::Int(v as i64)
+    }
+}
+
+impl From<u64> for Element {
+    fn from(v: u64) -> Element {
+        Element::UInt(v)
+    }
+}
+
+impl From<u32> for Element {
+    fn from(v: u32) -> Element {
+        Element::UInt(v as u64)
+    }
+}
+
+impl From<f64> for Element {
+    fn from(v: f64) -> Element {
+        Element::Float(v)
+    }
+}
+
+impl From<bool> for Element {
+    fn from(v: bool) -> Element {
+        Element::Bool(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+    use crate::surf::Surf;
+
+    // check_order asserts that the given tuples, which are in ascending order,
+    // are packed in ascending order, and unpack to themselves.
+    fn check_order(tuples: &[Tuple]) {
+        for pair in tuples.windows(2) {
+            assert!(
+                pair[0].pack() < pair[1].pack(),
+                "{:?} < {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+        for t in tuples {
+            assert_eq!(Tuple::unpack(&t.pack()).as_ref(), Ok(t));
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        let values = [
+            i64::MIN,
+            -65_536,
+            -65_535,
+            -256,
+            -255,
+            -1,
+            0,
+            1,
+            255,
+            256,
+            i64::MAX,
+        ];
+        let mut tuples: Vec<Tuple> = values.iter().map(|v| Tuple::new().asc(*v)).collect();
+        tuples.push(Tuple::new().asc(u64::MAX));
+        check_order(&tuples);
+
+        tuples.reverse();
+        let tuples: Vec<Tuple> = tuples
+            .into_iter()
+            .map(|t| Tuple::new().desc(t.elements[0].0.clone()))
+            .collect();
+        check_order(&tuples);
+
+        assert_eq!(Tuple::new().asc(5u32).pack(), Tuple::new().asc(5i32).pack());
+    }
+
+    #[test]
+    fn test_strings() {
+        let values = ["", "\0", "\0\0", "a", "a\0", "a\0b", "ab", "b"];
+        let tuples: Vec<Tuple> = values.iter().map(|v| Tuple::new().asc(*v)).collect();
+        check_order(&tuples);
+
+        let tuples: Vec<Tuple> = values.iter().rev().map(|v| Tuple::new().desc(*v)).collect();
+        check_order(&tuples);
+
+        // Strings keep their order when followed by further elements.
+        check_order(&[
+            Tuple::new().desc("ab").asc(1),
+            Tuple::new().desc("a").asc(0),
+            Tuple::new().desc("a").asc(1),
+        ]);
+    }
+
+    #[test]
+    fn test_mixed() {
+        check_order(&[
+            Tuple::new().asc(vec![0xFFu8]),
+            Tuple::new().asc("a"),
+            Tuple::new().asc(-1),
+            Tuple::new().asc(-1.5),
+            Tuple::new().asc(false),
+            Tuple::new().asc(true),
+        ]);
+
+        assert!(Tuple::unpack(&[0x03]).is_err());
+        assert!(Tuple::unpack(&[BYTES, b'a', 0x00]).is_err());
+        assert!(Tuple::unpack(&[INT_ZERO + 2, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_range() {
+        let key =
+            |tenant: u64, table: &str, ts: i64| Tuple::new().asc(tenant).asc(table).desc(ts).pack();
+        let keys = vec![
+            key(1, "orders", 100),
+            key(1, "orders", 200),
+            key(1, "users", 100),
+            key(2, "orders", 300),
+        ];
+        let surf = Surf::new(keys.clone(), Options::new()).unwrap();
+
+        // Newer timestamps come first.
+        assert!(key(1, "orders", 200) < key(1, "orders", 100));
+
+        let (low, high) = Tuple::new().asc(1u64).asc("orders").range();
+        assert_eq!(low, Tuple::new().asc(1u64).asc("orders").pack());
+        assert!(keys
+            .iter()
+            .all(|k| k.starts_with(&low) == (low <= *k && *k <= high)));
+        assert_eq!(surf.count(low, high), Ok(2));
+
+        let (low, high) = Tuple::new().asc(3u64).range();
+        assert_eq!(surf.range(low, high), Ok(false));
+
+        let (low, high) = Tuple::between(&Tuple::new().asc(1u64).asc("p"), &Tuple::new().asc(2u64));
+        assert_eq!(surf.count(low, high), Ok(2));
+
+        // Timestamps from 250 down to 150.
+        let prefix = Tuple::new().asc(1u64).asc("orders");
+        let (low, high) = Tuple::between(&prefix.clone().desc(250), &prefix.desc(150));
+        assert_eq!(surf.range(low, high), Ok(true));
+    }
+}
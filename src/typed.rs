@@ -0,0 +1,195 @@
+use std::io::Read;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::encode::{predecessor, successor, KeyEncode};
+use crate::iterator::Error;
+use crate::options::Options;
+use crate::transform::KeyTransform;
+
+// Surf is a filter over typed keys, which are stored using their
+// order-preserving encoding. Queries take keys and ranges of the same type,
+// e.g. range(10i64..=20).
+pub struct Surf<K: KeyEncode> {
+    surf: crate::surf::Surf,
+    keys: PhantomData<K>,
+}
+
+impl<K: KeyEncode> Surf<K> {
+    // new builds a filter from the given keys. Options::transform must be
+    // the identity, as transformed encodings would no longer be of a fixed
+    // width, nor ordered like the keys.
+    pub fn new(keys: impl IntoIterator<Item = K>, options: Options) -> Result<Self, Error> {
+        check_transform(&options)?;
+        let keys = keys.into_iter().map(|k| k.encode()).collect();
+
+        Ok(Surf {
+            surf: crate::surf::Surf::new(keys, options)?,
+            keys: PhantomData,
+        })
+    }
+
+    // from_inner wraps the given filter over encoded keys, e.g. one loaded
+    // from a file, failing unless it holds keys of K::WIDTH bytes only, and
+    // was built without a transform.
+    pub fn from_inner(surf: crate::surf::Surf) -> Result<Self, Error> {
+        check_transform(surf.options())?;
+        surf.trie().check_key_width(K::WIDTH)?;
+
+        Ok(Surf {
+            surf,
+            keys: PhantomData,
+        })
+    }
+
+    // read_from loads a filter serialized by Surf::write_to of its inner
+    // filter, like from_inner.
+    pub fn read_from(r: impl Read) -> Result<Self, Error> {
+        Surf::from_inner(crate::surf::Surf::read_from(r)?)
+    }
+
+    // inner returns the underlying filter over encoded keys, e.g. to
+    // serialize it.
+    pub fn inner(&self) -> &crate::surf::Surf {
+        &self.surf
+    }
+
+    pub fn get(&self, key: &K) -> Result<bool, Error> {
+        let (exists, _, _) = self.surf.get(key.encode())?;
+        Ok(exists)
+    }
+
+    pub fn range(&self, range: impl RangeBounds<K>) -> Result<bool, Error> {
+        match bounds(&range) {
+            Some((low, high)) => self.surf.range(low, high),
+            None => Ok(false),
+        }
+    }
+
+    pub fn count(&self, range: impl RangeBounds<K>) -> Result<usize, Error> {
+        match bounds(&range) {
+            Some((low, high)) => self.surf.count(low, high),
+            None => Ok(0),
+        }
+    }
+}
+
+// check_transform checks that the given options do not transform keys.
+fn check_transform(options: &Options) -> Result<(), Error> {
+    match options.transform {
+        KeyTransform::Identity => Ok(()),
+        _ => Err(Error::CustomError(
+            "Typed keys can not be transformed".to_string(),
+        )),
+    }
+}
+
+// bounds returns the inclusive bounds of the encodings of the given range,
+// or None if it is empty.
+fn bounds<K: KeyEncode>(range: &impl RangeBounds<K>) -> Option<(Vec<u8>, Vec<u8>)> {
+    // Encodings are of a fixed width, so the keys right after and before a
+    // bound are known.
+    let low = match range.start_bound() {
+        Bound::Included(k) => k.encode(),
+        Bound::Excluded(k) => successor(&k.encode())?,
+        Bound::Unbounded => vec![0x00; K::WIDTH],
+    };
+    let high = match range.end_bound() {
+        Bound::Included(k) => k.encode(),
+        Bound::Excluded(k) => predecessor(&k.encode())?,
+        Bound::Unbounded => vec![0xFF; K::WIDTH],
+    };
+
+    (low <= high).then_some((low, high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_surf() -> Surf<i64> {
+        let options = Options {
+            hash_bits: 0,
+            real_bits: 8,
+            ..Options::new()
+        };
+        Surf::new([-1_000i64, -20, 10, 20, 3_000_000], options).unwrap()
+    }
+
+    #[test]
+    fn test_get() {
+        let surf = get_surf();
+        for k in [-1_000i64, -20, 10, 20, 3_000_000] {
+            assert!(surf.get(&k).unwrap(), "{}", k);
+        }
+        assert!(!surf.get(&i64::MIN).unwrap());
+    }
+
+    #[test]
+    fn test_range() {
+        let surf = get_surf();
+
+        assert!(surf.range(10i64..=20).unwrap());
+        assert!(surf.range(-25i64..-15).unwrap());
+        assert!(surf.range(..-999i64).unwrap());
+        assert!(surf.range(2_999_999i64..).unwrap());
+        assert!(surf.range(..).unwrap());
+
+        assert!(!surf.range(11i64..20).unwrap());
+        assert!(!surf.range(21i64..=999).unwrap());
+        // 3_000_001 would share the truncated key and real suffix of
+        // 3_000_000, unlike 3_000_300.
+        assert!(!surf.range(3_000_300i64..).unwrap());
+        assert!(!surf.range(..-1_000i64).unwrap());
+        assert!(!surf
+            .range((Bound::Excluded(i64::MAX), Bound::Unbounded))
+            .unwrap());
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 20i64..10;
+        assert!(!surf.range(empty).unwrap());
+
+        assert_eq!(surf.count(-20i64..=20).unwrap(), 3);
+        assert_eq!(surf.count(..).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_read_from() {
+        let surf = get_surf();
+        let mut buf = Vec::new();
+        surf.inner().write_to(&mut buf).unwrap();
+
+        let loaded = Surf::<i64>::read_from(buf.as_slice()).unwrap();
+        for k in [-1_000i64, -20, 10, 20, 3_000_000] {
+            assert!(loaded.get(&k).unwrap(), "{}", k);
+        }
+        assert_eq!(loaded.count(..).unwrap(), 5);
+
+        // Keys of 8 bytes are truncated to more than 2 bytes, and keys of
+        // different widths may be prefixes of one another.
+        assert!(Surf::<i16>::read_from(buf.as_slice()).is_err());
+        let mixed = vec![vec![1], vec![1, 2]];
+        let mixed = crate::surf::Surf::new(mixed, Options::new()).unwrap();
+        assert!(Surf::<u64>::from_inner(mixed).is_err());
+    }
+
+    #[test]
+    fn test_transform() {
+        let options = Options {
+            transform: KeyTransform::Prefix(4),
+            ..Options::new()
+        };
+        assert!(Surf::new([1u64, 2], options.clone()).is_err());
+
+        let inner = crate::surf::Surf::new(vec![vec![0; 8]], options).unwrap();
+        assert!(Surf::<u64>::from_inner(inner).is_err());
+    }
+
+    #[test]
+    fn test_floats() {
+        let surf = Surf::new([-2.5f64, 0.0, 1.25], Options::new()).unwrap();
+
+        assert!(surf.get(&-0.0).unwrap());
+        assert!(surf.range(-3.0f64..-2.0).unwrap());
+        assert!(surf.range(1.0f64..=f64::INFINITY).unwrap());
+    }
+}
@@ -0,0 +1,840 @@
+use std::fmt;
+
+use crate::bitmap::BitmapRef;
+use crate::bitops::range_masks;
+use crate::iterator::Section;
+use crate::layout::{self, DenseLayout};
+use crate::rank;
+use crate::root_table;
+use crate::suffix;
+use crate::surf::{Surf, SurfRef};
+use crate::trie::Trie;
+
+// Invariant names a structural property every LOUDS-DENSE encoded tree must
+// have.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Invariant {
+    // D-Labels holds one bit per symbol of the alphabet for every node of the
+    // trie, padded to whole words with zeroes.
+    LabelsLength,
+    // D-HasChild holds one bit per symbol of the alphabet for every node of
+    // the trie, padded to whole words with zeroes.
+    HasChildLength,
+    // D-IsPrefixKey holds one bit for every node of the trie, padded to whole
+    // words with zeroes.
+    IsPrefixKeyLength,
+    // D-HasChain is empty for tries built without path compression, and
+    // holds one bit for every node of the trie otherwise, padded to whole
+    // words with zeroes.
+    HasChainLength,
+    // The rank directory of a bitmap has one entry per block of its words.
+    RankDirectoryLength(Section),
+    // The suffixes hold one suffix per stored key, padded to whole words
+    // with zeroes.
+    SuffixesLength,
+    // The first level consists of the root node only.
+    SingleRoot,
+    // Every node has at least one edge, except for a root which only the
+    // empty key ends on.
+    NodeHasEdges,
+    // A D-HasChild bit is only set for edges which have a label.
+    ChildHasLabel,
+    // The child an edge leads to, as given by rank on D-HasChild, is the next
+    // node in level order.
+    ChildNumbering,
+    // The children of the nodes on one level are the nodes of the next level.
+    ChildOnNextLevel,
+    // Every node but the root is the child of an edge.
+    NodeIsChild,
+    // The entries of the rank directory of a bitmap match the bitmap.
+    RankDirectory(Section),
+    // There is one chain end for every D-HasChain bit which is set.
+    ChainEndsLength,
+    // Every chain holds at least one label, i.e. the chain ends increase.
+    ChainHasLabels,
+    // The chain labels hold the labels of all chains, padded to whole words
+    // with zeroes.
+    ChainLabelsLength,
+    // Every chain label is a symbol of the alphabet.
+    ChainLabel,
+    // The root table holds one entry per symbol, plus one per pair of
+    // symbols if indexed by two, padded to whole words with zeroes.
+    RootTableLength,
+    // Every entry of the root table is the node its symbols lead to, or 0 if
+    // they do not lead to one below the root and level 1 nodes without
+    // chains.
+    RootTableEntry,
+    // The records of the interleaved layout hold one record per node, padded
+    // to whole cache lines with zeroes, and are empty for the Bitmaps layout.
+    RecordsLength,
+    // Every record holds the D-Labels and D-HasChild blocks, the
+    // D-IsPrefixKey bit and the child-rank base of its node.
+    Record,
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Invariant::LabelsLength => "D-Labels does not hold one bit per symbol and node",
+            Invariant::HasChildLength => "D-HasChild does not hold one bit per symbol and node",
+            Invariant::IsPrefixKeyLength => "D-IsPrefixKey does not hold one bit per node",
+            Invariant::HasChainLength => "D-HasChain does not hold one bit per node",
+            Invariant::RankDirectoryLength(section) => {
+                return write!(f, "rank directory does not cover {}", section);
+            }
+            Invariant::SuffixesLength => "suffixes do not hold one suffix per key",
+            Invariant::SingleRoot => "first level does not consist of a single root",
+            Invariant::NodeHasEdges => "node has no edges",
+            Invariant::ChildHasLabel => "D-HasChild bit is set without its label",
+            Invariant::ChildNumbering => "edge does not lead to the next node in level order",
+            Invariant::ChildOnNextLevel => "edge leads past the next level",
+            Invariant::NodeIsChild => "node is not the child of any edge",
+            Invariant::RankDirectory(section) => {
+                return write!(f, "rank directory entry does not match {}", section);
+            }
+            Invariant::ChainEndsLength => "chain ends do not hold one end per chain",
+            Invariant::ChainHasLabels => "chain has no labels",
+            Invariant::ChainLabelsLength => "chain labels do not hold the labels of all chains",
+            Invariant::ChainLabel => "chain label is not a symbol of the alphabet",
+            Invariant::RootTableLength => "root table does not hold one entry per symbol",
+            Invariant::RootTableEntry => "root table entry does not match the trie",
+            Invariant::RecordsLength => "records do not hold one record per node",
+            Invariant::Record => "record does not match the bitmaps",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Violation reports a violated invariant, along with where in the trie it
+// was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Violation {
+    pub invariant: Invariant,
+    // level is the level of the offending node.
+    pub level: usize,
+    // node is the level-order ID of the offending node.
+    //
+    // For violations concerning the length of a bitmap, level and node are
+    // the number of levels and nodes the bitmap should cover.
+    pub node: usize,
+    // bit is the offset of the offending bit within its bitmap. For
+    // violations concerning the length of a bitmap, it is the length the
+    // bitmap actually has. For violations concerning the labels of a chain,
+    // it is the offset of the offending label, for the root table the index
+    // of the offending entry, and for records the offset of the offending
+    // bit within the records.
+    pub bit: usize,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at level {}, node {}, bit {}",
+            self.invariant, self.level, self.node, self.bit
+        )
+    }
+}
+
+impl std::error::Error for Violation {}
+
+impl Surf {
+    // verify walks the whole trie, and checks that it satisfies all
+    // structural invariants of the LOUDS-DENSE encoding.
+    //
+    // The first violated invariant is returned. Filters built by Surf::new
+    // always pass, so this is intended for tests, and for filters which were
+    // loaded from untrusted storage.
+    pub fn verify(&self) -> Result<(), Violation> {
+        check(&self.trie(), &self.levels)
+    }
+}
+
+impl<'a> SurfRef<'a> {
+    // verify walks the whole trie, and checks that it satisfies all
+    // structural invariants of the LOUDS-DENSE encoding.
+    //
+    // SurfRef::new already verifies the trie, so this only fails if the
+    // underlying buffer was modified since.
+    pub fn verify(&self) -> Result<(), Violation> {
+        check(&self.trie, &self.levels)
+    }
+}
+
+// check verifies the structural invariants of the given trie, whose levels
+// hold the given number of nodes.
+pub(crate) fn check(trie: &Trie, levels: &[usize]) -> Result<(), Violation> {
+    let labels = trie.labels.words();
+    let has_child = trie.has_child.words();
+    let is_prefix_key = trie.is_prefix_key.words();
+    let nodes = levels.iter().try_fold(0usize, |sum, n| sum.checked_add(*n));
+
+    // The lengths are checked first, as all further checks rely on them.
+    let violation = |invariant, bit| Violation {
+        invariant,
+        level: levels.len(),
+        node: nodes.unwrap_or(usize::MAX),
+        bit,
+    };
+    let width = trie.symbols.width();
+    let bits = match nodes.and_then(|n| n.checked_mul(width)) {
+        Some(bits) if bits.div_ceil(64) == labels.len() => bits,
+        _ => return Err(violation(Invariant::LabelsLength, 64 * labels.len())),
+    };
+    if bits.div_ceil(64) != has_child.len() {
+        return Err(violation(Invariant::HasChildLength, 64 * has_child.len()));
+    }
+    for (words, invariant) in [
+        (labels, Invariant::LabelsLength),
+        (has_child, Invariant::HasChildLength),
+    ] {
+        if let Some(last) = words.last() {
+            let padding = last & trailing_padding(bits);
+            if padding != 0 {
+                let bit = (words.len() - 1) * 64 + padding.leading_zeros() as usize;
+                return Err(violation(invariant, bit));
+            }
+        }
+    }
+    let nodes: usize = levels.iter().sum();
+    if is_prefix_key.len() != nodes.div_ceil(64) {
+        return Err(violation(
+            Invariant::IsPrefixKeyLength,
+            64 * is_prefix_key.len(),
+        ));
+    }
+    if let Some(last) = is_prefix_key.last() {
+        let padding = last & trailing_padding(nodes);
+        if padding != 0 {
+            let bit = (is_prefix_key.len() - 1) * 64 + padding.leading_zeros() as usize;
+            return Err(violation(Invariant::IsPrefixKeyLength, bit));
+        }
+    }
+    let has_chain = trie.chains.has_chain.words();
+    if !has_chain.is_empty() {
+        if has_chain.len() != nodes.div_ceil(64) {
+            return Err(violation(Invariant::HasChainLength, 64 * has_chain.len()));
+        }
+        let padding = has_chain[has_chain.len() - 1] & trailing_padding(nodes);
+        if padding != 0 {
+            let bit = (has_chain.len() - 1) * 64 + padding.leading_zeros() as usize;
+            return Err(violation(Invariant::HasChainLength, bit));
+        }
+    }
+    for (bitmap, section) in directories(trie) {
+        let ranks = bitmap.ranks();
+        if ranks.len() != bitmap.words().len().div_ceil(rank::BLOCK_WORDS) {
+            return Err(violation(
+                Invariant::RankDirectoryLength(section),
+                64 * ranks.len(),
+            ));
+        }
+    }
+
+    let ranks = trie.has_child.ranks();
+    if let Some(root) = levels.first() {
+        if *root != 1 {
+            return Err(Violation {
+                invariant: Invariant::SingleRoot,
+                level: 0,
+                node: 0,
+                bit: 0,
+            });
+        }
+    }
+
+    // Walk all nodes in level order, keeping track of the child the next
+    // edge with a D-HasChild bit must lead to.
+    let mut node = 0;
+    let mut next_child = 1;
+    for (level, level_nodes) in levels.iter().enumerate() {
+        let next_level_end = next_child + levels.get(level + 1).copied().unwrap_or(0);
+
+        for _ in 0..*level_nodes {
+            let location = |invariant, bit| Violation {
+                invariant,
+                level,
+                node,
+                bit,
+            };
+
+            let block = || range_masks(width * node, width * (node + 1));
+            let only_empty_key = levels.len() == 1 && trie.is_prefix_key.get(0) == Ok(1);
+            if block().all(|(w, mask)| labels[w] & mask == 0) && !only_empty_key {
+                return Err(location(Invariant::NodeHasEdges, width * node));
+            }
+
+            for (w, mask) in block() {
+                let stray = has_child[w] & !labels[w] & mask;
+                if stray != 0 {
+                    let bit = 64 * w + stray.leading_zeros() as usize;
+                    return Err(location(Invariant::ChildHasLabel, bit));
+                }
+
+                let mut children = has_child[w] & mask;
+                while children != 0 {
+                    let bit = 64 * w + children.leading_zeros() as usize;
+                    children &= !(0x8000000000000000 >> (bit % 64));
+
+                    if next_child >= next_level_end {
+                        return Err(location(Invariant::ChildOnNextLevel, bit));
+                    }
+                    if rank::rank1(has_child, ranks, bit) != next_child {
+                        return Err(location(Invariant::ChildNumbering, bit));
+                    }
+                    next_child += 1;
+                }
+            }
+
+            node += 1;
+        }
+
+        if next_child != next_level_end {
+            return Err(Violation {
+                invariant: Invariant::NodeIsChild,
+                level: level + 1,
+                node: next_child,
+                bit: width * next_child,
+            });
+        }
+    }
+
+    // The number of keys is only known to be right once the walk passed.
+    let suffixes = trie.suffixes.words;
+    let keys = stored_keys(labels, has_child, is_prefix_key);
+    let suffix_bits = keys * trie.suffixes.width() as usize;
+    if suffixes.len() != suffix::words(keys, trie.suffixes.width()) {
+        return Err(violation(Invariant::SuffixesLength, 64 * suffixes.len()));
+    }
+    if let Some(last) = suffixes.last() {
+        let padding = last & trailing_padding(suffix_bits);
+        if padding != 0 {
+            let bit = (suffixes.len() - 1) * 64 + padding.leading_zeros() as usize;
+            return Err(violation(Invariant::SuffixesLength, bit));
+        }
+    }
+
+    check_chains(trie, levels)?;
+    check_root_table(trie, levels)?;
+    check_records(trie, levels)?;
+
+    // Entries of the rank directory of D-HasChild which did not lead to a
+    // wrongly numbered child may still be off, just like those of the other
+    // bitmaps, which locate the suffixes of keys.
+    for (bitmap, section) in directories(trie) {
+        let ranks = bitmap.ranks();
+        for (entry, expected) in rank::directory(bitmap.words()).into_iter().enumerate() {
+            if ranks[entry] != expected {
+                let bit = entry * rank::BLOCK_WORDS * 64;
+                let node = match section {
+                    Section::IsPrefixKey | Section::HasChain => bit,
+                    _ => bit / width,
+                };
+                return Err(Violation {
+                    invariant: Invariant::RankDirectory(section),
+                    level: level_of(levels, node),
+                    node,
+                    bit,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// directories returns the bitmaps of the given trie which have a rank
+// directory.
+fn directories<'a>(trie: &Trie<'a>) -> [(BitmapRef<'a>, Section); 4] {
+    [
+        (trie.labels, Section::Labels),
+        (trie.has_child, Section::HasChild),
+        (trie.is_prefix_key, Section::IsPrefixKey),
+        (trie.chains.has_chain, Section::HasChain),
+    ]
+}
+
+// check_chains verifies the chains of the given trie, whose D-HasChain bitmap
+// is known to have the right length.
+fn check_chains(trie: &Trie, levels: &[usize]) -> Result<(), Violation> {
+    let chains = trie.chains;
+    let location = |invariant, node, bit| Violation {
+        invariant,
+        level: level_of(levels, node),
+        node,
+        bit,
+    };
+
+    // The nodes which have a chain, in level order.
+    let owners: Vec<usize> = chains
+        .has_chain
+        .words()
+        .iter()
+        .enumerate()
+        .flat_map(|(w, word)| {
+            (0..64)
+                .filter(move |i| word & (0x8000000000000000 >> i) != 0)
+                .map(move |i| 64 * w + i)
+        })
+        .collect();
+    if chains.ends.len() != owners.len() {
+        return Err(Violation {
+            invariant: Invariant::ChainEndsLength,
+            level: levels.len(),
+            node: owners.len(),
+            bit: 64 * chains.ends.len(),
+        });
+    }
+
+    let mut start = 0;
+    for (node, end) in owners.iter().zip(chains.ends) {
+        if *end <= start {
+            return Err(location(Invariant::ChainHasLabels, *node, start as usize));
+        }
+        start = *end;
+    }
+
+    // All chain ends are known to be in order, the last one being the
+    // number of labels.
+    let labels = start;
+    if chains.labels.len() as u64 != labels.div_ceil(8) {
+        return Err(Violation {
+            invariant: Invariant::ChainLabelsLength,
+            level: levels.len(),
+            node: owners.len(),
+            bit: 64 * chains.labels.len(),
+        });
+    }
+    if let Some(last) = chains.labels.last() {
+        // Labels are packed starting with the least significant byte.
+        let padding = match labels % 8 {
+            0 => 0,
+            used => last & (u64::MAX << (8 * used)),
+        };
+        if padding != 0 {
+            let bit = (chains.labels.len() - 1) * 8 + padding.trailing_zeros() as usize / 8;
+            return Err(location(
+                Invariant::ChainLabelsLength,
+                owners[owners.len() - 1],
+                bit,
+            ));
+        }
+    }
+
+    let width = trie.symbols.width();
+    let mut start = 0;
+    for (node, end) in owners.iter().zip(chains.ends) {
+        for offset in start..*end as usize {
+            if chains.label(offset) as usize >= width {
+                return Err(location(Invariant::ChainLabel, *node, offset));
+            }
+        }
+        start = *end as usize;
+    }
+
+    Ok(())
+}
+
+// check_root_table verifies the root table of the given trie, whose structure
+// and chains are known to be valid.
+fn check_root_table(trie: &Trie, levels: &[usize]) -> Result<(), Violation> {
+    let table = trie.root_table;
+    let width = trie.symbols.width();
+    let entries = root_table::entries(width, table.bytes);
+    if table.words.len() != entries.div_ceil(2)
+        || (entries % 2 == 1 && table.words[entries / 2] >> 32 != 0)
+    {
+        return Err(Violation {
+            invariant: Invariant::RootTableLength,
+            level: levels.len(),
+            node: levels.iter().sum(),
+            bit: 64 * table.words.len(),
+        });
+    }
+
+    // Building the table from the valid trie only fails if it has more nodes
+    // than entries can address.
+    let expected = root_table::build(trie, table.bytes).unwrap_or_default();
+    for index in 0..entries {
+        let entry = root_table::entry(table.words, index);
+        if expected.is_empty() || entry != root_table::entry(&expected, index) {
+            let node = entry as usize;
+            return Err(Violation {
+                invariant: Invariant::RootTableEntry,
+                level: level_of(levels, node),
+                node,
+                bit: index,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// stored_keys returns the number of keys stored in a trie with the given
+// bitmaps, i.e. the number of its leaf edges and prefix keys.
+pub(crate) fn stored_keys(labels: &[u64], has_child: &[u64], is_prefix_key: &[u64]) -> usize {
+    let leaves: usize = labels
+        .iter()
+        .zip(has_child)
+        .map(|(l, c)| (l & !c).count_ones() as usize)
+        .sum();
+    let prefix_keys: usize = is_prefix_key.iter().map(|w| w.count_ones() as usize).sum();
+
+    leaves + prefix_keys
+}
+
+// level_of returns the level the node with the given ID is on.
+// check_records verifies the records of the given trie, whose structure is
+// known to be valid.
+fn check_records(trie: &Trie, levels: &[usize]) -> Result<(), Violation> {
+    let records = trie.records;
+    let layout = match records.stride {
+        0 => DenseLayout::Bitmaps,
+        _ => DenseLayout::Interleaved,
+    };
+    let nodes = levels.iter().sum();
+    if 8 * records.words.len() != layout::encoded_bytes(layout, nodes, trie.symbols.width()) {
+        return Err(Violation {
+            invariant: Invariant::RecordsLength,
+            level: levels.len(),
+            node: nodes,
+            bit: 64 * records.words.len(),
+        });
+    }
+
+    // Building the records from the valid trie only fails if it has more
+    // children than their bases can address.
+    let expected = layout::build(trie, layout, nodes).unwrap_or_default();
+    let expected = layout::words(&expected);
+    for (i, word) in records.words.iter().enumerate() {
+        let diff = word ^ expected.get(i).unwrap_or(&!word);
+        if diff != 0 {
+            let node = i / records.stride;
+            return Err(Violation {
+                invariant: Invariant::Record,
+                level: level_of(levels, node),
+                node,
+                bit: 64 * i + diff.leading_zeros() as usize,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn level_of(levels: &[usize], node: usize) -> usize {
+    let mut first = 0;
+    for (level, n) in levels.iter().enumerate() {
+        first += n;
+        if node < first {
+            return level;
+        }
+    }
+    levels.len()
+}
+
+// trailing_padding returns a mask of the bits of the last word of a bitmap of
+// n bits which lie past its end.
+fn trailing_padding(n: usize) -> u64 {
+    match n % 64 {
+        0 => 0,
+        used => u64::MAX >> used,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::options::Options;
+
+    fn get_surf_keys() -> Vec<Vec<u8>> {
+        vec![
+            b"f".to_vec(),
+            b"far".to_vec(),
+            b"fast".to_vec(),
+            b"s".to_vec(),
+            b"top".to_vec(),
+            b"toy".to_vec(),
+            b"trie".to_vec(),
+        ]
+    }
+
+    fn get_surf() -> Surf {
+        Surf::new(get_surf_keys(), Options::new()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_valid() {
+        assert_eq!(get_surf().verify(), Ok(()));
+        assert_eq!(Surf::new(vec![], Options::new()).unwrap().verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_violations() {
+        let mut surf = get_surf();
+        surf.dense_has_child.data[5] |= 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChildHasLabel,
+                level: 1,
+                node: 1,
+                bit: 5 * 64 + 63,
+            })
+        );
+
+        // Turning the leaf edge 's' of the root into an edge with a child
+        // leaves one child too many for the next level.
+        let mut surf = get_surf();
+        let bit = b's' as usize;
+        surf.dense_has_child.data[1] |= 0x8000000000000000 >> (bit - 64);
+        surf.dense_has_child_ranks = rank::directory(&surf.dense_has_child.data);
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChildOnNextLevel,
+                level: 0,
+                node: 0,
+                bit: b't' as usize,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.dense_labels.data[8..12].fill(0);
+        surf.dense_has_child.data[8..12].fill(0);
+        surf.dense_has_child_ranks = rank::directory(&surf.dense_has_child.data);
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::NodeHasEdges,
+                level: 1,
+                node: 2,
+                bit: 512,
+            })
+        );
+
+        // A stale rank directory numbers children wrongly.
+        let mut surf = get_surf();
+        surf.dense_has_child_ranks[0] = 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChildNumbering,
+                level: 0,
+                node: 0,
+                bit: b'f' as usize,
+            })
+        );
+
+        // Node 4 only has leaf edges, so its rank directory entry is not
+        // needed to number any child.
+        let mut surf = get_surf();
+        surf.dense_has_child_ranks[2] += 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::RankDirectory(Section::HasChild),
+                level: 2,
+                node: 4,
+                bit: 1024,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.dense_is_prefix_key.data[0] |= 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::IsPrefixKeyLength,
+                level: 3,
+                node: 5,
+                bit: 63,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.levels.push(1);
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::LabelsLength,
+                level: 4,
+                node: 6,
+                bit: 5 * 256,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_chains() {
+        let keys = vec![
+            b"tenant/a/x1".to_vec(),
+            b"tenant/a/x2".to_vec(),
+            b"tenant/b".to_vec(),
+        ];
+        let options = Options {
+            path_compression: true,
+            alphabet: Alphabet::Detect,
+            ..Options::new()
+        };
+        let get_surf = || Surf::new(keys.clone(), options.clone()).unwrap();
+        assert_eq!(get_surf().verify(), Ok(()));
+
+        // Nodes 0 and 1 have the chains "tenant/" and "/x".
+        let mut surf = get_surf();
+        surf.chain_ends[1] = 7;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChainHasLabels,
+                level: 1,
+                node: 1,
+                bit: 7,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.chain_ends.pop();
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChainEndsLength,
+                level: 2,
+                node: 2,
+                bit: 64,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.chain_labels[1] |= 0xFF << 56;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChainLabelsLength,
+                level: 1,
+                node: 1,
+                bit: 15,
+            })
+        );
+
+        // The alphabet holds 9 symbols, "t" being the 8th.
+        let mut surf = get_surf();
+        surf.chain_labels[0] |= 0x0B;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::ChainLabel,
+                level: 0,
+                node: 0,
+                bit: 0,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.dense_has_chain.data[0] |= 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::HasChainLength,
+                level: 2,
+                node: 2,
+                bit: 63,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_root_table() {
+        let options = Options {
+            root_table_bytes: 1,
+            ..Options::new()
+        };
+        let get_surf = || Surf::new(get_surf_keys(), options.clone()).unwrap();
+        assert_eq!(get_surf().verify(), Ok(()));
+
+        // The entry of s points at node 3, although s is a leaf edge.
+        let mut surf = get_surf();
+        surf.root_table[b's' as usize / 2] |= 3 << 32;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::RootTableEntry,
+                level: 2,
+                node: 3,
+                bit: b's' as usize,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.root_table.pop();
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::RootTableLength,
+                level: 3,
+                node: 5,
+                bit: 64 * 127,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_records() {
+        let options = Options {
+            layout: DenseLayout::Interleaved,
+            ..Options::new()
+        };
+        let get_surf = || Surf::new(get_surf_keys(), options.clone()).unwrap();
+        assert_eq!(get_surf().verify(), Ok(()));
+
+        // The record of node 1 starts its children at node 2 rather than 3.
+        let mut surf = get_surf();
+        surf.records[2].0[0] -= 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::Record,
+                level: 1,
+                node: 1,
+                bit: 64 * 16 + 62,
+            })
+        );
+
+        let mut surf = get_surf();
+        surf.records.pop();
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::RecordsLength,
+                level: 3,
+                node: 5,
+                bit: 64 * 72,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_alphabet() {
+        let options = Options {
+            alphabet: Alphabet::Detect,
+            ..Options::new()
+        };
+        let mut surf = Surf::new(get_surf_keys(), options).unwrap();
+        assert_eq!(surf.verify(), Ok(()));
+
+        // The 5 nodes of 10 symbols leave the last 14 bits of the single
+        // word unused.
+        surf.dense_labels.data[0] |= 1;
+        assert_eq!(
+            surf.verify(),
+            Err(Violation {
+                invariant: Invariant::LabelsLength,
+                level: 3,
+                node: 5,
+                bit: 63,
+            })
+        );
+    }
+}